@@ -380,34 +380,77 @@ pub fn testnet_genesis(
             crypto_price_sources: vec![(
                 b"BTC".to_vec(),
                 vec![
-                    (
-                        b"coincap".to_vec(),
-                        b"https://api.coincap.io/v2/assets/bitcoin".to_vec(),
-                        vec![b"data".to_vec(), b"priceUsd".to_vec()],
-                    ),
-                    (
-                        b"cryptocompare".to_vec(),
-                        b"https://min-api.cryptocompare.com/data/price?fsym=BTC&tsyms=USD".to_vec(),
-                        vec![b"USD".to_vec()],
-                    ),
+                    node_runtime::PriceSource {
+                        name: b"coincap".to_vec(),
+                        url: b"https://api.coincap.io/v2/assets/bitcoin".to_vec(),
+                        json_path: vec![
+                            node_runtime::JsonPathSegment::Key(b"data".to_vec()),
+                            node_runtime::JsonPathSegment::Key(b"priceUsd".to_vec()),
+                        ],
+                        inverted: false,
+                    },
+                    node_runtime::PriceSource {
+                        name: b"cryptocompare".to_vec(),
+                        url: b"https://min-api.cryptocompare.com/data/price?fsym=BTC&tsyms=USD"
+                            .to_vec(),
+                        json_path: vec![node_runtime::JsonPathSegment::Key(b"USD".to_vec())],
+                        inverted: false,
+                    },
                 ],
             )],
             current_price: vec![(b"DUSD".to_vec(), 1 * node_runtime::ORACLE_PRICE_SCALE)],
+            token_decimals: vec![],
+            operators: vec![(
+                get_account_id_from_seed::<sr25519::Public>("Alice"),
+                get_from_seed::<node_runtime::OracleId>("Alice"),
+            )],
+            max_deviation: 10,
+            min_quorum: 1,
+            history_depth: 1000,
+            circuit_breaker_threshold: 20,
+            stable_rounds: 3,
+            max_source_failure_rate: 50,
+            max_source_deviation_rate: 30,
+            min_source_samples: 20,
+            min_failure_reporters: 2,
+            synthetic_tokens: vec![],
+            trading_pairs: vec![(b"BTC".to_vec(), b"DUSD".to_vec())],
         }),
         p2p: Some(PToPConfig {
             money_pool: get_account_id_from_seed::<sr25519::Public>("definex-vault"),
             platform: get_account_id_from_seed::<sr25519::Public>("Alice"),
-            trading_pairs: vec![node_runtime::TradingPair {
-                collateral: 1 as u32,
-                borrow: 0 as u32,
-            }],
-            safe_ltv: 30000,
-            liquidate_ltv: 15000,
-            min_borrow_terms: 1,
-            min_borrow_interest_rate: 10000,
+            trading_pairs: vec![(
+                node_runtime::TradingPair {
+                    collateral: 1 as u32,
+                    borrow: 0 as u32,
+                },
+                node_runtime::TradingPairConfig {
+                    safe_ltv: 30000,
+                    liquidate_ltv: 15000,
+                    min_borrow_terms: 1,
+                    max_borrow_terms: 365,
+                    min_borrow_interest_rate: 10000,
+                    enabled: true,
+                    origination_fee_rate: 10, // 0.1%
+                    interest_fee_rate: 1000,  // 10%
+                    reputation_tier: 300,     // weighted_days of the credit record
+                    reputation_safe_ltv: 25000,
+                    margin_call_ltv: 20000,
+                    margin_call_window: node_runtime::constants::time::DAYS as u64,
+                    auto_liquidation: false,
+                },
+            )],
+            max_available_borrows_per_account: 5,
+            borrow_check_interval: 2,
+            loan_check_interval: 5,
+            loan_check_weight_budget: 100 * node_runtime::P2P_LOAN_CHECK_WEIGHT,
+            overdue_grace_period: 3,
+            overdue_penalty_rate: 10000,
             charge_penalty: true,
             liquidator_discount: 90, // 90%
             liquidation_penalty: 50, // 50%
+            max_price_age: 10 * node_runtime::constants::time::MINUTES,
+            price_twap_window: 0,
         }),
         deposit_loan: Some(DepositLoanConfig {
             collection_asset_id: 0,
@@ -420,6 +463,8 @@ pub fn testnet_genesis(
             liquidate_discount: 95,
             minimum_collateral: 0,
             liquidation_penalty: 50,
+            max_price_age: 10 * node_runtime::constants::time::MINUTES,
+            price_twap_window: 0,
             collection_account_id: get_account_id_from_seed::<sr25519::Public>(
                 "collection_account_id",
             ),
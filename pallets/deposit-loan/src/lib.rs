@@ -97,11 +97,12 @@ mod mock;
 mod tests;
 
 pub use deposit_loan_primitives::*;
+use new_oracle_primitives::{at_common_decimals, PriceProvider};
 
-pub trait Trait:
-    frame_system::Trait + timestamp::Trait + generic_asset::Trait + new_oracle::Trait
-{
+pub trait Trait: frame_system::Trait + timestamp::Trait + generic_asset::Trait {
     type Event: From<Event<Self>> + Into<<Self as frame_system::Trait>::Event>;
+    /// prices of assets in USDT, with the decimals the provider gives for each asset
+    type PriceProvider: PriceProvider<Self::AssetId, BlockNumber = Self::BlockNumber>;
 }
 
 decl_storage! {
@@ -184,6 +185,12 @@ decl_storage! {
         pub SavingInterestRate get(saving_interest_rate) config() : T::Balance;
 
         pub LiquidateDiscount get(liquidate_discount) config() : T::Balance;
+
+        /// prices older than this many blocks can't open, draw or liquidate loans, 0 turns the check off
+        pub MaxPriceAge get(max_price_age) config() : T::BlockNumber;
+
+        /// prices are averaged over this many blocks of oracle history, 0 uses the spot price
+        pub PriceTwapWindow get(price_twap_window) config() : T::BlockNumber;
     }
 
     add_extra_genesis {
@@ -278,6 +285,20 @@ decl_module! {
             Ok(())
         }
 
+        #[weight = SimpleDispatchInfo::FixedNormal(0)]
+        pub fn set_max_price_age(origin, max_age: T::BlockNumber) -> DispatchResult {
+            ensure_root(origin)?;
+            <MaxPriceAge<T>>::put(max_age);
+            Ok(())
+        }
+
+        #[weight = SimpleDispatchInfo::FixedNormal(0)]
+        pub fn set_price_twap_window(origin, window: T::BlockNumber) -> DispatchResult {
+            ensure_root(origin)?;
+            <PriceTwapWindow<T>>::put(window);
+            Ok(())
+        }
+
         #[weight = SimpleDispatchInfo::FixedNormal(0)]
         pub fn staking(origin, asset_id: T::AssetId, amount: T::Balance) -> DispatchResult {
             ensure!(!Self::paused(), Error::<T>::Paused);
@@ -476,8 +497,8 @@ impl<T: Trait> Module<T> {
             Error::<T>::NotEnoughBalance
         );
 
-        let price_pair = Self::fetch_trading_pair_prices(collection_asset_id, collateral_asset_id)
-            .ok_or(Error::<T>::TradingPairPriceMissing)?;
+        let price_pair =
+            Self::fetch_fresh_trading_pair_prices(collection_asset_id, collateral_asset_id)?;
 
         // collateral asset will be transfered to this shop
         let shop = <PawnShop<T>>::get();
@@ -747,12 +768,15 @@ impl<T: Trait> Module<T> {
         );
 
         let trading_pair_prices =
-            Self::fetch_trading_pair_prices(collection_asset_id, collateral_asset_id)
-                .ok_or(Error::<T>::TradingPairPriceMissing)?;
+            Self::fetch_fresh_trading_pair_prices(collection_asset_id, collateral_asset_id)?;
 
         let collateral_worth = loan.collateral_balance_original
-            * T::Balance::from(trading_pair_prices.collateral_asset_price as u32)
-            / T::Balance::from(trading_pair_prices.borrow_asset_price as u32);
+            * <T::Balance as TryFrom<u64>>::try_from(trading_pair_prices.collateral_asset_price)
+                .ok()
+                .unwrap()
+            / <T::Balance as TryFrom<u64>>::try_from(trading_pair_prices.borrow_asset_price)
+                .ok()
+                .unwrap();
 
         // collateral_net_worth is the price that we sell to liquidator
         let collateral_net_worth: T::Balance =
@@ -909,7 +933,13 @@ impl<T: Trait> Module<T> {
         let collateral_price = <T::Balance as TryFrom<u128>>::try_from(collateral_price as u128)
             .ok()
             .unwrap();
-        let ltv = (loan_amount * T::Balance::from(collection_price as u32) * T::Balance::from(PRICE_PREC) * T::Balance::from(LTV_PREC))
+        let collection_price = <T::Balance as TryFrom<u128>>::try_from(collection_price as u128)
+            .ok()
+            .unwrap();
+        let ltv = (loan_amount
+            * collection_price
+            * T::Balance::from(PRICE_PREC)
+            * T::Balance::from(LTV_PREC))
             / (collateral_amount * collateral_price);
         TryInto::<LTV>::try_into(ltv).ok().unwrap()
     }
@@ -945,8 +975,8 @@ impl<T: Trait> Module<T> {
             Error::<T>::NotEnoughBalance
         );
 
-        let price_pair = Self::fetch_trading_pair_prices(collection_asset_id, collateral_asset_id)
-            .ok_or(Error::<T>::TradingPairPriceMissing)?;
+        let price_pair =
+            Self::fetch_fresh_trading_pair_prices(collection_asset_id, collateral_asset_id)?;
 
         let price_pair_borrow_asset_price =
             <T::Balance as TryFrom<u128>>::try_from(price_pair.borrow_asset_price as u128)
@@ -1011,7 +1041,15 @@ impl<T: Trait> Module<T> {
 
         let price_pair = price_pair.unwrap();
 
-        let all_loans = <LoanIdWithAllLoans>::get();
+        // no liquidation against a stale or held price
+        let all_loans =
+            if Self::fetch_fresh_trading_pair_prices(collection_asset_id, collateral_asset_id)
+                .is_ok()
+            {
+                <LoanIdWithAllLoans>::get()
+            } else {
+                vec![]
+            };
 
         for loan_id in all_loans {
             let loan = <Loans<T>>::get(&loan_id);
@@ -1160,33 +1198,56 @@ impl<T: Trait> Module<T> {
         borrow_asset_id: T::AssetId,
         collateral_asset_id: T::AssetId,
     ) -> Option<TradingPairPrices> {
-        let collateral_price = Self::fetch_price(collateral_asset_id);
-        let borrow_price = Self::fetch_price(borrow_asset_id);
-        if collateral_price.is_some() && borrow_price.is_some() {
-            Some(TradingPairPrices {
-                borrow_asset_price: borrow_price.unwrap(),
-                collateral_asset_price: collateral_price.unwrap(),
-            })
-        } else {
-            None
-        }
+        // only the ratio of the two prices matters, so they are brought to the same decimals
+        let (borrow_asset_price, collateral_asset_price) = at_common_decimals(
+            Self::fetch_price(borrow_asset_id)?,
+            Self::fetch_price(collateral_asset_id)?,
+        )?;
+        Some(TradingPairPrices {
+            borrow_asset_price,
+            collateral_asset_price,
+        })
     }
 
-    fn fetch_price(asset_id: T::AssetId) -> Option<u64> {
-        if !<generic_asset::Module<T>>::asset_id_exists(asset_id) {
-            return None;
-        }
-        let token = <generic_asset::Module<T>>::symbols(asset_id);
-        if !<new_oracle::Module<T>>::is_token_known(&token) {
-            return None;
+    /// prices to open, draw or liquidate a loan with, refuses prices older than `MaxPriceAge` or held by the oracle's circuit breaker
+    pub fn fetch_fresh_trading_pair_prices(
+        borrow_asset_id: T::AssetId,
+        collateral_asset_id: T::AssetId,
+    ) -> Result<TradingPairPrices, Error<T>> {
+        let prices = Self::fetch_trading_pair_prices(borrow_asset_id, collateral_asset_id)
+            .ok_or(Error::<T>::TradingPairPriceMissing)?;
+        ensure!(
+            Self::is_price_fresh(borrow_asset_id) && Self::is_price_fresh(collateral_asset_id),
+            Error::<T>::PriceStale
+        );
+        ensure!(
+            !Self::is_price_held(borrow_asset_id) && !Self::is_price_held(collateral_asset_id),
+            Error::<T>::PriceOnHold
+        );
+        Ok(prices)
+    }
+
+    fn is_price_held(asset_id: T::AssetId) -> bool {
+        T::PriceProvider::is_held(asset_id)
+    }
+
+    fn is_price_fresh(asset_id: T::AssetId) -> bool {
+        let max_age = Self::max_price_age();
+        if max_age.is_zero() {
+            return true;
         }
-        let current_price = <new_oracle::Module<T>>::current_price(&token);
-        let price: u64 = TryInto::<u64>::try_into(current_price).unwrap_or(0);
-        if price == 0 {
-            return None;
+        T::PriceProvider::is_fresh(asset_id, max_age)
+    }
+
+    /// the price of an asset with its decimals
+    fn fetch_price(asset_id: T::AssetId) -> Option<(u64, u32)> {
+        let window = Self::price_twap_window();
+        let price = if window.is_zero() {
+            T::PriceProvider::price(asset_id)
         } else {
-            return Some(price);
-        }
+            T::PriceProvider::twap(asset_id, window)
+        }?;
+        Some((price, T::PriceProvider::decimals(asset_id)))
     }
 }
 
@@ -1197,6 +1258,8 @@ decl_error! {
         SavingTypeNotAllowed,
         UnknowAssetId,
         TradingPairPriceMissing,
+        PriceStale,
+        PriceOnHold,
         MinCollateralAmount,
         UnknownLoanId,
         NotLoanOwner,
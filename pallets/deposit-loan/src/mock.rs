@@ -21,6 +21,7 @@
 use super::*;
 use crate::{GenesisConfig, Module, Trait};
 use balances;
+use new_oracle_primitives::{FixedPrices, PRICE_SCALE};
 use sp_core::H256;
 pub use sp_core::{sr25519, Pair, Public};
 use std::cell::RefCell;
@@ -82,27 +83,6 @@ impl system::Trait for Test {
     type OnKilledAccount = ();
 }
 
-type Extrinsic = TestXt<new_oracle::Call<Test>, ()>;
-type SubmitTransaction =
-    system::offchain::TransactionSubmitter<new_oracle::crypto::Public, Test, Extrinsic>;
-
-impl system::offchain::CreateTransaction<Test, Extrinsic> for Test {
-    type Public = sp_core::sr25519::Public;
-    type Signature = sp_core::sr25519::Signature;
-
-    fn create_transaction<F: system::offchain::Signer<Self::Public, Self::Signature>>(
-        call: <Extrinsic as ExtrinsicsT>::Call,
-        _public: Self::Public,
-        _account: <Test as system::Trait>::AccountId,
-        nonce: <Test as system::Trait>::Index,
-    ) -> Option<(
-        <Extrinsic as ExtrinsicsT>::Call,
-        <Extrinsic as ExtrinsicsT>::SignaturePayload,
-    )> {
-        Some((call, (nonce, ())))
-    }
-}
-
 parameter_types! {
     pub const MinimumPeriod: u64 = 5;
 }
@@ -123,31 +103,21 @@ impl generic_asset::Trait for Test {
 
 type BlockNumber = u64;
 
-parameter_types! {
-    pub const AggregateInterval: BlockNumber = 5;
-}
-impl new_oracle::Trait for Test {
-    type Event = MetaEvent;
-    type Call = new_oracle::Call<Test>;
-    type SubmitUnsignedTransaction = SubmitTransaction;
-    type SubmitSignedTransaction = SubmitTransaction;
-    type AggregateInterval = AggregateInterval;
-    type PriceInUSDT = u64;
-}
-
 mod deposit_loan {
     pub use super::super::*;
 }
 
+pub type TestPrices = FixedPrices<BlockNumber>;
+
 impl Trait for Test {
     type Event = MetaEvent;
+    type PriceProvider = TestPrices;
 }
 
 impl_outer_event! {
     pub enum MetaEvent for Test {
         system<T>,
         sudo<T>,
-        new_oracle<T>,
         generic_asset<T>,
         // p2p<T>,
         deposit_loan<T>,
@@ -205,15 +175,9 @@ pub fn new_test_ext() -> sp_io::TestExternalities {
         .assimilate_storage(&mut t)
         .unwrap();
 
-    new_oracle::GenesisConfig::<Test> {
-        crypto_price_sources: vec![],
-        current_price: vec![
-            (b"DUSD".to_vec(), 1 * new_oracle::PRICE_SCALE),
-            (b"BTC".to_vec(), 10000 * new_oracle::PRICE_SCALE),
-        ],
-    }
-    .assimilate_storage(&mut t)
-    .unwrap();
+    TestPrices::clear();
+    TestPrices::set_price(constants::USDT, 1 * PRICE_SCALE);
+    TestPrices::set_price(constants::BTC, 10000 * PRICE_SCALE);
 
     generic_asset::GenesisConfig::<Test> {
         next_asset_id: 2,
@@ -236,6 +200,8 @@ pub fn new_test_ext() -> sp_io::TestExternalities {
         liquidate_discount: 95,
         minimum_collateral: 0,
         liquidation_penalty: 50,
+        max_price_age: 0,
+        price_twap_window: 0,
         collection_account_id: get_from_seed::<sr25519::Public>("collection_account_id"),
         pawn_shop: get_from_seed::<sr25519::Public>("pawn_shop"),
         profit_pool: get_from_seed::<sr25519::Public>("profit_pool"),
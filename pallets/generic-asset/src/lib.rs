@@ -619,7 +619,7 @@ impl<T: Trait> Module<T> {
 
         let idf = Self::generic_asset_lock_identifier(asset_id);
         <Locks<T>>::mutate(&idf, who, |v| {
-            for l in v.iter_mut() {
+            for l in v.iter_mut().filter(|l| l.id == lock_id) {
                 l.amount += amount;
             }
         });
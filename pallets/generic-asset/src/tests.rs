@@ -293,6 +293,26 @@ fn reserve_should_not_moves_amount_from_balance_to_reserved_balance() {
         });
 }
 
+#[test]
+fn increase_reserved_balance_should_only_change_the_given_lock() {
+    ExtBuilder::default()
+        .next_asset_id(10)
+        .symbols(vec![(0, b"temp".to_vec())])
+        .build()
+        .execute_with(|| {
+            let who = 123;
+            assert_ok!(GenericAsset::mint(Origin::signed(root), 0, who, 100));
+            let first = GenericAsset::reserve(&0, &who, 20).unwrap();
+            let second = GenericAsset::reserve(&0, &who, 30).unwrap();
+            assert_ok!(GenericAsset::increase_reserved_balance(&0, first, &who, 10));
+            assert_eq!(GenericAsset::locked_balance(&0, &who, first), Some(30));
+            assert_eq!(GenericAsset::locked_balance(&0, &who, second), Some(30));
+            assert_ok!(GenericAsset::unreserve(&0, &who, 30, Some(second)));
+            assert_eq!(GenericAsset::free_balance(&0, &who), 70);
+            assert_eq!(GenericAsset::reserved_balance(&0, &who), 30);
+        });
+}
+
 #[test]
 fn create_reserved_should_create_a_default_account_with_the_balance_given() {
     ExtBuilder::default()
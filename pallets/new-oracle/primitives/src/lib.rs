@@ -0,0 +1,187 @@
+// Copyright (C) 2020 by definex.io
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use codec::{Decode, Encode};
+#[cfg(feature = "std")]
+use serde::{Deserialize, Serialize};
+use sp_std::prelude::*;
+
+/// prices are in USDT, multiplied by this scale
+pub const PRICE_SCALE: u64 = 10000;
+/// decimals of `PRICE_SCALE`
+pub const PRICE_DECIMALS: u32 = 4;
+
+/// `(price, (block, timestamp) of its last update, held price)` of a token,
+/// the update is none for a price pinned at genesis
+pub type PriceInfo<BlockNumber, Moment, Price> =
+    (Price, Option<(BlockNumber, Moment)>, Option<Price>);
+
+/// one step into a JSON document
+#[derive(Encode, Decode, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug, Serialize, Deserialize))]
+pub enum JsonPathSegment {
+    Key(Vec<u8>),
+    Index(u32),
+}
+
+/// an endpoint quoting a token's price
+#[derive(Encode, Decode, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug, Serialize, Deserialize))]
+pub struct PriceSource {
+    pub name: Vec<u8>,
+    pub url: Vec<u8>,
+    /// where the quote is in the response, parsed from a JSONPath like `$.data[0].priceUsd`
+    pub json_path: Vec<JsonPathSegment>,
+    /// the endpoint quotes USDT in the token, e.g. BTC per USDT, and the price is its reciprocal
+    pub inverted: bool,
+}
+
+/// how an oracle source has been doing since it was added or last re-enabled
+#[derive(Encode, Decode, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct SourceHealth {
+    /// prices fetched from the source
+    pub successes: u32,
+    /// fetches that failed
+    pub failures: u32,
+    /// fetched prices beyond `MaxDeviation` of the aggregated price
+    pub deviations: u32,
+    /// operators skip a disabled source until governance enables it again
+    pub disabled: bool,
+}
+
+/// Source of asset prices for the lending pallets.
+///
+/// Prices are in USDT with the decimals of their asset, `None` means the asset has no usable price.
+pub trait PriceProvider<AssetId> {
+    type BlockNumber;
+
+    /// the decimals prices of an asset are given with, small caps have more than `PRICE_DECIMALS`
+    fn decimals(asset_id: AssetId) -> u32;
+
+    /// the latest price of an asset
+    fn price(asset_id: AssetId) -> Option<u64>;
+
+    /// the time weighted average price over the last `window` blocks, falls back to the latest price
+    fn twap(asset_id: AssetId, window: Self::BlockNumber) -> Option<u64>;
+
+    /// whether the price was updated within the last `max_age` blocks
+    fn is_fresh(asset_id: AssetId, max_age: Self::BlockNumber) -> bool;
+
+    /// whether a new price is held back waiting for confirmation
+    fn is_held(asset_id: AssetId) -> bool;
+}
+
+/// two prices given with their decimals, brought to the larger of the decimals so they can be divided,
+/// `None` if either doesn't fit in a `u64` then
+pub fn at_common_decimals(a: (u64, u32), b: (u64, u32)) -> Option<(u64, u64)> {
+    let decimals = a.1.max(b.1);
+    let scale = |(price, d): (u64, u32)| {
+        10u64
+            .checked_pow(decimals - d)
+            .and_then(|s| price.checked_mul(s))
+    };
+    Some((scale(a)?, scale(b)?))
+}
+
+#[cfg(feature = "std")]
+pub use fixed::FixedPrices;
+
+#[cfg(feature = "std")]
+mod fixed {
+    use super::{PriceProvider, PRICE_DECIMALS};
+    use codec::Encode;
+    use std::{cell::RefCell, collections::BTreeMap, marker::PhantomData};
+
+    #[derive(Clone, Default)]
+    struct FixedPrice {
+        price: u64,
+        decimals: Option<u32>,
+        stale: bool,
+        held: bool,
+    }
+
+    thread_local! {
+        static PRICES: RefCell<BTreeMap<Vec<u8>, FixedPrice>> = RefCell::new(BTreeMap::new());
+    }
+
+    /// Prices set by hand, for testing pallets without an oracle.
+    ///
+    /// Prices are kept per thread and never age unless marked stale.
+    pub struct FixedPrices<BlockNumber>(PhantomData<BlockNumber>);
+
+    impl<BlockNumber> FixedPrices<BlockNumber> {
+        fn with_price<AssetId: Encode, R>(
+            asset_id: &AssetId,
+            f: impl FnOnce(&mut FixedPrice) -> R,
+        ) -> R {
+            PRICES.with(|prices| f(prices.borrow_mut().entry(asset_id.encode()).or_default()))
+        }
+
+        fn get<AssetId: Encode>(asset_id: &AssetId) -> Option<FixedPrice> {
+            PRICES.with(|prices| prices.borrow().get(&asset_id.encode()).cloned())
+        }
+
+        pub fn set_price<AssetId: Encode>(asset_id: AssetId, price: u64) {
+            Self::with_price(&asset_id, |p| p.price = price);
+        }
+
+        pub fn set_decimals<AssetId: Encode>(asset_id: AssetId, decimals: u32) {
+            Self::with_price(&asset_id, |p| p.decimals = Some(decimals));
+        }
+
+        pub fn set_stale<AssetId: Encode>(asset_id: AssetId, stale: bool) {
+            Self::with_price(&asset_id, |p| p.stale = stale);
+        }
+
+        pub fn set_held<AssetId: Encode>(asset_id: AssetId, held: bool) {
+            Self::with_price(&asset_id, |p| p.held = held);
+        }
+
+        pub fn clear() {
+            PRICES.with(|prices| prices.borrow_mut().clear());
+        }
+    }
+
+    impl<AssetId: Encode, BlockNumber> PriceProvider<AssetId> for FixedPrices<BlockNumber> {
+        type BlockNumber = BlockNumber;
+
+        fn decimals(asset_id: AssetId) -> u32 {
+            Self::get(&asset_id)
+                .and_then(|p| p.decimals)
+                .unwrap_or(PRICE_DECIMALS)
+        }
+
+        fn price(asset_id: AssetId) -> Option<u64> {
+            Self::get(&asset_id)
+                .map(|p| p.price)
+                .filter(|price| *price > 0)
+        }
+
+        fn twap(asset_id: AssetId, _window: BlockNumber) -> Option<u64> {
+            Self::price(asset_id)
+        }
+
+        fn is_fresh(asset_id: AssetId, _max_age: BlockNumber) -> bool {
+            Self::get(&asset_id).map_or(false, |p| !p.stale)
+        }
+
+        fn is_held(asset_id: AssetId) -> bool {
+            Self::get(&asset_id).map_or(false, |p| p.held)
+        }
+    }
+}
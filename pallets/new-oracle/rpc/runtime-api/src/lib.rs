@@ -0,0 +1,25 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use codec::Codec;
+use sp_std::vec::Vec;
+
+pub use new_oracle_primitives::{JsonPathSegment, PriceInfo, PriceSource, SourceHealth};
+
+sp_api::decl_runtime_apis! {
+    pub trait NewOracleApi<AssetId, BlockNumber, Moment, PriceInUSDT> where
+        AssetId: Codec,
+        BlockNumber: Codec,
+        Moment: Codec,
+        PriceInUSDT: Codec,
+    {
+        fn get_prices() -> Vec<(Vec<u8>, PriceInfo<BlockNumber, Moment, PriceInUSDT>)>;
+        fn get_price(token: Vec<u8>) -> Option<PriceInfo<BlockNumber, Moment, PriceInUSDT>>;
+        fn get_asset_price(asset_id: AssetId) -> Option<(Vec<u8>, PriceInfo<BlockNumber, Moment, PriceInUSDT>)>;
+        fn get_candidates(token: Vec<u8>) -> Vec<(Vec<u8>, PriceInUSDT)>;
+        fn get_sources(token: Vec<u8>) -> Vec<PriceSource>;
+        fn get_next_aggregate_at() -> BlockNumber;
+        fn get_price_history(token: Vec<u8>) -> Vec<(BlockNumber, Moment, PriceInUSDT)>;
+        fn get_source_health(token: Vec<u8>) -> Vec<(Vec<u8>, SourceHealth)>;
+        fn get_pair_price(base: Vec<u8>, quote: Vec<u8>) -> Option<(PriceInUSDT, BlockNumber)>;
+    }
+}
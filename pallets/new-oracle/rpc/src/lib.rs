@@ -0,0 +1,364 @@
+use std::sync::Arc;
+
+use codec::Codec;
+use jsonrpc_core::{Error as RPCError, ErrorCode, Result};
+use jsonrpc_derive::rpc;
+use serde::{Deserialize, Serialize};
+use sp_api::ProvideRuntimeApi;
+use sp_blockchain::HeaderBackend;
+use sp_runtime::{generic::BlockId, traits::Block as BlockT};
+
+pub use self::gen_client::Client as NewOracleClient;
+pub use new_oracle_rpc_runtime_api::{self as runtime_api, NewOracleApi as NewOracleRuntimeApi};
+use runtime_api::{JsonPathSegment, PriceInfo};
+
+pub enum Error {
+    RuntimeError,
+}
+impl From<Error> for i64 {
+    fn from(e: Error) -> i64 {
+        match e {
+            Error::RuntimeError => 1,
+        }
+    }
+}
+impl From<Error> for String {
+    fn from(e: Error) -> String {
+        match e {
+            Error::RuntimeError => "runtime trapped".to_string(),
+        }
+    }
+}
+
+/// the current price of a token
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenPrice<BlockNumber, Moment, PriceInUSDT> {
+    pub token: String,
+    pub price: PriceInUSDT,
+    /// block and timestamp of the last update, none for a price pinned at genesis
+    pub block_number: Option<BlockNumber>,
+    pub timestamp: Option<Moment>,
+    /// a new price held back by the circuit breaker
+    pub held_price: Option<PriceInUSDT>,
+}
+
+impl<BlockNumber, Moment, PriceInUSDT> TokenPrice<BlockNumber, Moment, PriceInUSDT> {
+    fn new(token: Vec<u8>, info: PriceInfo<BlockNumber, Moment, PriceInUSDT>) -> Self {
+        let (price, updated_at, held_price) = info;
+        let (block_number, timestamp) = match updated_at {
+            Some((block_number, timestamp)) => (Some(block_number), Some(timestamp)),
+            None => (None, None),
+        };
+        TokenPrice {
+            token: String::from_utf8_lossy(&token).into_owned(),
+            price,
+            block_number,
+            timestamp,
+            held_price,
+        }
+    }
+}
+
+/// a price submitted for the current round
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Candidate<PriceInUSDT> {
+    pub source: String,
+    pub price: PriceInUSDT,
+}
+
+/// a source of a token
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceInfo {
+    pub name: String,
+    pub url: String,
+    /// e.g. `$.data[0].priceUsd`
+    pub json_path: String,
+    pub inverted: bool,
+}
+
+/// a JSONPath in the dot notation, keys which aren't identifiers in the bracket notation
+fn json_path_string(path: &[JsonPathSegment]) -> String {
+    let mut s = String::from("$");
+    for segment in path {
+        match segment {
+            JsonPathSegment::Key(key) => {
+                let key = String::from_utf8_lossy(key);
+                if !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                    s.push('.');
+                    s.push_str(&key);
+                } else {
+                    s.push_str(&format!("['{}']", key));
+                }
+            }
+            JsonPathSegment::Index(i) => s.push_str(&format!("[{}]", i)),
+        }
+    }
+    s
+}
+
+/// an aggregated price of a token
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PricePoint<BlockNumber, Moment, PriceInUSDT> {
+    pub block_number: BlockNumber,
+    pub timestamp: Moment,
+    pub price: PriceInUSDT,
+}
+
+/// the price of a base token in a quote token with `PAIR_PRICE_DECIMALS`, and the block it was derived at
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PairPrice<BlockNumber, PriceInUSDT> {
+    pub price: PriceInUSDT,
+    pub block_number: BlockNumber,
+}
+
+/// counters of a source of a token
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceReport {
+    pub name: String,
+    pub successes: u32,
+    pub failures: u32,
+    pub deviations: u32,
+    pub disabled: bool,
+}
+
+/// NewOracle RPC methods
+#[rpc]
+pub trait NewOracleApi<
+    BlockHash,
+    AssetId,
+    BlockNumber,
+    PriceResult,
+    CandidateResult,
+    HistoryResult,
+    PairPriceResult,
+>
+{
+    /// current price of every token
+    #[rpc(name = "newOracle_prices")]
+    fn prices(&self, at: Option<BlockHash>) -> Result<Vec<PriceResult>>;
+
+    #[rpc(name = "newOracle_price")]
+    fn price(&self, token: String, at: Option<BlockHash>) -> Result<Option<PriceResult>>;
+
+    /// current price of the token of an asset
+    #[rpc(name = "newOracle_assetPrice")]
+    fn asset_price(&self, asset_id: AssetId, at: Option<BlockHash>) -> Result<Option<PriceResult>>;
+
+    /// prices submitted for a token in the current round
+    #[rpc(name = "newOracle_candidates")]
+    fn candidates(&self, token: String, at: Option<BlockHash>) -> Result<Vec<CandidateResult>>;
+
+    #[rpc(name = "newOracle_sources")]
+    fn sources(&self, token: String, at: Option<BlockHash>) -> Result<Vec<SourceInfo>>;
+
+    /// block of the next aggregation
+    #[rpc(name = "newOracle_nextAggregateAt")]
+    fn next_aggregate_at(&self, at: Option<BlockHash>) -> Result<BlockNumber>;
+
+    /// aggregated prices of a token, oldest first
+    #[rpc(name = "newOracle_history")]
+    fn history(&self, token: String, at: Option<BlockHash>) -> Result<HistoryResult>;
+
+    /// fetch and deviation counters of every source of a token
+    #[rpc(name = "newOracle_sourceHealth")]
+    fn source_health(&self, token: String, at: Option<BlockHash>) -> Result<Vec<SourceReport>>;
+
+    /// derived price of a registered trading pair, none before its first aggregation
+    #[rpc(name = "newOracle_pairPrice")]
+    fn pair_price(
+        &self,
+        base: String,
+        quote: String,
+        at: Option<BlockHash>,
+    ) -> Result<Option<PairPriceResult>>;
+}
+
+fn runtime_error<E: std::fmt::Debug>(e: E) -> RPCError {
+    RPCError {
+        code: ErrorCode::ServerError(Error::RuntimeError.into()),
+        message: Error::RuntimeError.into(),
+        data: Some(format!("{:?}", e).into()),
+    }
+}
+
+pub struct NewOracle<C, B> {
+    client: Arc<C>,
+    _marker: std::marker::PhantomData<B>,
+}
+impl<C, B> NewOracle<C, B> {
+    pub fn new(client: Arc<C>) -> Self {
+        NewOracle {
+            client,
+            _marker: Default::default(),
+        }
+    }
+}
+
+impl<C, Block, AssetId, BlockNumber, Moment, PriceInUSDT>
+    NewOracleApi<
+        <Block as BlockT>::Hash,
+        AssetId,
+        BlockNumber,
+        TokenPrice<BlockNumber, Moment, PriceInUSDT>,
+        Candidate<PriceInUSDT>,
+        Vec<PricePoint<BlockNumber, Moment, PriceInUSDT>>,
+        PairPrice<BlockNumber, PriceInUSDT>,
+    > for NewOracle<C, Block>
+where
+    Block: BlockT,
+    C: Send + Sync + 'static + ProvideRuntimeApi<Block> + HeaderBackend<Block>,
+    C::Api: NewOracleRuntimeApi<Block, AssetId, BlockNumber, Moment, PriceInUSDT>,
+    AssetId: Codec,
+    BlockNumber: Codec,
+    Moment: Codec,
+    PriceInUSDT: Codec,
+{
+    fn prices(
+        &self,
+        at: Option<<Block as BlockT>::Hash>,
+    ) -> Result<Vec<TokenPrice<BlockNumber, Moment, PriceInUSDT>>> {
+        let api = self.client.runtime_api();
+        let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+        let prices = api.get_prices(&at).map_err(runtime_error)?;
+
+        Ok(prices
+            .into_iter()
+            .map(|(token, info)| TokenPrice::new(token, info))
+            .collect())
+    }
+
+    fn price(
+        &self,
+        token: String,
+        at: Option<<Block as BlockT>::Hash>,
+    ) -> Result<Option<TokenPrice<BlockNumber, Moment, PriceInUSDT>>> {
+        let api = self.client.runtime_api();
+        let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+        let token = token.into_bytes();
+        let info = api.get_price(&at, token.clone()).map_err(runtime_error)?;
+
+        Ok(info.map(|info| TokenPrice::new(token, info)))
+    }
+
+    fn asset_price(
+        &self,
+        asset_id: AssetId,
+        at: Option<<Block as BlockT>::Hash>,
+    ) -> Result<Option<TokenPrice<BlockNumber, Moment, PriceInUSDT>>> {
+        let api = self.client.runtime_api();
+        let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+        let price = api.get_asset_price(&at, asset_id).map_err(runtime_error)?;
+
+        Ok(price.map(|(token, info)| TokenPrice::new(token, info)))
+    }
+
+    fn candidates(
+        &self,
+        token: String,
+        at: Option<<Block as BlockT>::Hash>,
+    ) -> Result<Vec<Candidate<PriceInUSDT>>> {
+        let api = self.client.runtime_api();
+        let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+        let candidates = api
+            .get_candidates(&at, token.into_bytes())
+            .map_err(runtime_error)?;
+
+        Ok(candidates
+            .into_iter()
+            .map(|(source, price)| Candidate {
+                source: String::from_utf8_lossy(&source).into_owned(),
+                price,
+            })
+            .collect())
+    }
+
+    fn sources(
+        &self,
+        token: String,
+        at: Option<<Block as BlockT>::Hash>,
+    ) -> Result<Vec<SourceInfo>> {
+        let api = self.client.runtime_api();
+        let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+        let sources = api
+            .get_sources(&at, token.into_bytes())
+            .map_err(runtime_error)?;
+
+        Ok(sources
+            .into_iter()
+            .map(|source| SourceInfo {
+                name: String::from_utf8_lossy(&source.name).into_owned(),
+                url: String::from_utf8_lossy(&source.url).into_owned(),
+                json_path: json_path_string(&source.json_path),
+                inverted: source.inverted,
+            })
+            .collect())
+    }
+
+    fn next_aggregate_at(&self, at: Option<<Block as BlockT>::Hash>) -> Result<BlockNumber> {
+        let api = self.client.runtime_api();
+        let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+        api.get_next_aggregate_at(&at).map_err(runtime_error)
+    }
+
+    fn history(
+        &self,
+        token: String,
+        at: Option<<Block as BlockT>::Hash>,
+    ) -> Result<Vec<PricePoint<BlockNumber, Moment, PriceInUSDT>>> {
+        let api = self.client.runtime_api();
+        let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+        let history = api
+            .get_price_history(&at, token.into_bytes())
+            .map_err(runtime_error)?;
+
+        Ok(history
+            .into_iter()
+            .map(|(block_number, timestamp, price)| PricePoint {
+                block_number,
+                timestamp,
+                price,
+            })
+            .collect())
+    }
+
+    fn source_health(
+        &self,
+        token: String,
+        at: Option<<Block as BlockT>::Hash>,
+    ) -> Result<Vec<SourceReport>> {
+        let api = self.client.runtime_api();
+        let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+        let sources = api
+            .get_source_health(&at, token.into_bytes())
+            .map_err(runtime_error)?;
+
+        Ok(sources
+            .into_iter()
+            .map(|(name, health)| SourceReport {
+                name: String::from_utf8_lossy(&name).into_owned(),
+                successes: health.successes,
+                failures: health.failures,
+                deviations: health.deviations,
+                disabled: health.disabled,
+            })
+            .collect())
+    }
+
+    fn pair_price(
+        &self,
+        base: String,
+        quote: String,
+        at: Option<<Block as BlockT>::Hash>,
+    ) -> Result<Option<PairPrice<BlockNumber, PriceInUSDT>>> {
+        let api = self.client.runtime_api();
+        let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+        let pair = api
+            .get_pair_price(&at, base.into_bytes(), quote.into_bytes())
+            .map_err(runtime_error)?;
+
+        Ok(pair.map(|(price, block_number)| PairPrice {
+            price,
+            block_number,
+        }))
+    }
+}
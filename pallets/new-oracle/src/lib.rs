@@ -22,27 +22,37 @@ use sp_core::crypto::KeyTypeId;
 use sp_core::H256;
 #[allow(unused_imports)]
 use sp_runtime::{
+    helpers_128bit::multiply_by_rational,
     offchain::{http, storage::StorageValueRef, Duration},
     traits::{
         AtLeast32Bit, Bounded, CheckedAdd, CheckedSub, MaybeSerializeDeserialize, Member,
-        Saturating, Zero,
+        Saturating, UniqueSaturatedFrom, UniqueSaturatedInto, Zero,
     },
     transaction_validity::{
         InvalidTransaction, TransactionLongevity, TransactionValidity, ValidTransaction,
     },
+    RuntimeAppPublic, RuntimeDebug,
 };
 use sp_std::{
+    collections::btree_map::BTreeMap,
     convert::{TryFrom, TryInto},
     fmt::Debug,
     prelude::*,
 };
 use support::{
-    debug, decl_event, decl_module, decl_storage, dispatch::DispatchResult, ensure,
-    storage::IterableStorageMap, traits::Get, weights::SimpleDispatchInfo, Parameter, StorageValue,
+    debug, decl_error, decl_event, decl_module, decl_storage,
+    dispatch::DispatchResult,
+    ensure,
+    storage::IterableStorageMap,
+    traits::Get,
+    weights::{SimpleDispatchInfo, WeighData, Weight},
+    Parameter, StorageValue,
 };
 
 #[cfg(not(feature = "std"))]
 use num_traits::float::FloatCore;
+#[cfg(feature = "std")]
+use serde::{Deserialize, Serialize};
 
 #[allow(unused_imports)]
 use system::{ensure_none, ensure_root, ensure_signed, offchain};
@@ -54,7 +64,27 @@ mod tests;
 
 pub type StrBytes = Vec<u8>;
 pub const KEY_TYPE: KeyTypeId = KeyTypeId(*b"orcl");
-pub const PRICE_SCALE: u64 = 10000;
+pub use new_oracle_primitives::{
+    JsonPathSegment, PriceInfo, PriceProvider, PriceSource, SourceHealth, PRICE_SCALE,
+};
+/// at most this many sources are queried for one token in a single fetch round
+pub const MAX_SOURCES_PER_FETCH: usize = 3;
+/// a submission reports at most this many `(token, source)` prices
+pub const MAX_SUBMITTED_REPORTS: usize = 128;
+/// a failing token waits at most `FetchInterval * 2^MAX_BACKOFF_EXPONENT` blocks before retrying
+pub const MAX_BACKOFF_EXPONENT: u32 = 6;
+/// prefix of the offchain local storage key holding a token's `(next_fetch_at, failures)`
+pub const FETCH_SCHEDULE_PREFIX: &[u8] = b"definex::new-oracle::schedule::";
+/// decimals of a token's price unless set in `TokenDecimals`, the precision of `PRICE_SCALE`
+pub const DEFAULT_PRICE_DECIMALS: u32 = 4;
+/// prices keep at most this many decimals
+pub const MAX_PRICE_DECIMALS: u32 = 18;
+/// layout of `CryptoPriceSources`, bumped when it has to be migrated
+pub const SOURCES_VERSION: u32 = 1;
+/// decimals of the derived price of a trading pair
+pub const PAIR_PRICE_DECIMALS: u32 = 8;
+/// a synthetic token's formula has at most this many steps
+pub const MAX_FORMULA_LEN: usize = 16;
 
 pub mod crypto {
     use super::KEY_TYPE;
@@ -62,6 +92,42 @@ pub mod crypto {
     app_crypto!(sr25519, KEY_TYPE);
 }
 
+/// `PriceInfo` of a runtime
+pub type TokenPriceInfo<T> = PriceInfo<
+    <T as system::Trait>::BlockNumber,
+    <T as timestamp::Trait>::Moment,
+    <T as Trait>::PriceInUSDT,
+>;
+
+/// what an operator got from each source as `(token, source, price)`, the price is none when the fetch failed
+pub type SourceReports<PriceInUSDT> = Vec<(StrBytes, StrBytes, Option<PriceInUSDT>)>;
+
+/// prices observed by an operator, signed with its `orcl` key when submitted unsigned
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct PricePayload<BlockNumber, PriceInUSDT> {
+    pub public: crypto::Public,
+    pub block_number: BlockNumber,
+    pub prices: SourceReports<PriceInUSDT>,
+}
+
+/// one step of a synthetic token's formula, the steps are evaluated in postfix order,
+/// e.g. `[Price(ETH), Price(BTC), Div]` is the price of ETH in BTC.
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+pub enum FormulaOp {
+    /// the current price of a token with sources
+    Price(StrBytes),
+    /// a constant with the decimals of `PRICE_SCALE`
+    Const(u64),
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+/// `(name, url, object keys)`, how sources were stored before `SOURCES_VERSION` 1
+type LegacyPriceSource = (StrBytes, StrBytes, Vec<StrBytes>);
+
 pub trait Trait: timestamp::Trait + system::Trait {
     /// The overarching event type.
     type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
@@ -80,52 +146,370 @@ pub trait Trait: timestamp::Trait + system::Trait {
         + Debug;
 
     type AggregateInterval: Get<Self::BlockNumber>;
+    /// blocks between two successful fetches of the same token
+    type FetchInterval: Get<Self::BlockNumber>;
 }
 
 decl_storage! {
     trait Store for Module<T: Trait> as NewOracle {
-        pub CryptoPriceSources get(crypto_price_sources) config() : map hasher(opaque_blake2_256) StrBytes => Vec<(StrBytes, StrBytes, Vec<StrBytes>)>;
-        pub PriceCandidates get(price_candidates) : map hasher(twox_64_concat) StrBytes => Vec<T::PriceInUSDT>;
+        pub CryptoPriceSources get(crypto_price_sources) config() : map hasher(twox_64_concat) StrBytes => Vec<PriceSource>;
+        /// layout version of CryptoPriceSources, older layouts are migrated on the next block
+        SourcesVersion get(sources_version) build(|_: &GenesisConfig<T>| SOURCES_VERSION) : u32;
+        /// legacy sources the migration couldn't name a token for, see `migrate_legacy_sources`
+        pub LegacySourcesLeft get(legacy_sources_left) : u32;
+        /// decimals a token's price is kept with, `DEFAULT_PRICE_DECIMALS` if unset
+        pub TokenDecimals get(token_decimals) config() : map hasher(twox_64_concat) StrBytes => Option<u32>;
+        /// prices submitted in the current round with the operator and source they came from
+        pub PriceCandidates get(price_candidates) : map hasher(twox_64_concat) StrBytes => Vec<(T::AccountId, StrBytes, T::PriceInUSDT)>;
+        /// sources that operators failed to fetch in the current round
+        pub FailedReports get(failed_reports) : map hasher(twox_64_concat) StrBytes => Vec<(T::AccountId, StrBytes)>;
+        /// counters of each source of a token, by token and source name, counted once per round
+        pub SourceStats get(source_stats) : double_map hasher(twox_64_concat) StrBytes, hasher(twox_64_concat) StrBytes => SourceHealth;
+        /// a source failing more than this percentage of its fetches is disabled, 0 turns the check off
+        pub MaxSourceFailureRate get(max_source_failure_rate) config() : u32;
+        /// a source whose prices deviate from the aggregate more than this percentage of the time is disabled, 0 turns the check off
+        pub MaxSourceDeviationRate get(max_source_deviation_rate) config() : u32;
+        /// a source is only judged after this many fetches
+        pub MinSourceSamples get(min_source_samples) config() : u32;
+        /// a source only fails a round when this many operators, capped by the number of operators, and more than got its price report so
+        pub MinFailureReporters get(min_failure_reporters) config() : u32;
         pub CurrentPrice get(current_price) config() : map hasher(twox_64_concat) StrBytes => T::PriceInUSDT;
+        /// block and timestamp of the last aggregation that updated a token's price
+        pub PriceUpdatedAt get(price_updated_at) : map hasher(twox_64_concat) StrBytes => Option<(T::BlockNumber, T::Moment)>;
+        /// aggregated prices of a token with their block and timestamp, oldest first
+        pub PriceHistory get(price_history) : map hasher(twox_64_concat) StrBytes => Vec<(T::BlockNumber, T::Moment, T::PriceInUSDT)>;
+        /// at most this many entries are kept in a token's PriceHistory, 0 keeps none
+        pub HistoryDepth get(history_depth) config() : u32;
+
+        /// an aggregated price moving more than this percentage from the current price is held, 0 turns the breaker off
+        pub CircuitBreakerThreshold get(circuit_breaker_threshold) config() : u32;
+        /// a held price is accepted after staying within the threshold for this many more rounds, 0 waits for governance
+        pub StableRounds get(stable_rounds) config() : u32;
+        /// held prices and the number of rounds they have been stable for
+        pub PendingPrice get(pending_price) : map hasher(twox_64_concat) StrBytes => Option<(T::PriceInUSDT, u32)>;
         pub NextAggregateAt get(next_aggregate_at) : T::BlockNumber;
+
+        /// tokens priced by a formula over other tokens instead of sources
+        pub SyntheticTokens get(synthetic_formula) config() : map hasher(twox_64_concat) StrBytes => Option<Vec<FormulaOp>>;
+        /// `(base, quote)` pairs whose price is derived at each aggregation
+        pub TradingPairs get(trading_pairs) config() : Vec<(StrBytes, StrBytes)>;
+        /// price of a base token in a quote token with `PAIR_PRICE_DECIMALS`, and the block it was derived at
+        pub PairPrice get(pair_price) : double_map hasher(twox_64_concat) StrBytes, hasher(twox_64_concat) StrBytes => Option<(T::PriceInUSDT, T::BlockNumber)>;
+        /// candidates deviating more than this percentage from the median are dropped, 0 to keep all
+        pub MaxDeviation get(max_deviation) config() : u32;
+        /// minimum number of accepted candidates for a new price
+        pub MinQuorum get(min_quorum) config() : u32;
+
+        /// oracle operators and the `orcl` key each of them signs unsigned submissions with
+        pub Operators get(operators) config() : map hasher(blake2_128_concat) T::AccountId => Option<crypto::Public>;
+        pub OperatorKeys get(operator_of_key) : map hasher(blake2_128_concat) crypto::Public => Option<T::AccountId>;
+        /// the last aggregation round an operator submitted prices in
+        pub LastSubmittedRound get(last_submitted_round) : map hasher(blake2_128_concat) T::AccountId => Option<T::BlockNumber>;
+    }
+    add_extra_genesis {
+        build(|config: &GenesisConfig<T>| {
+            for (who, key) in config.operators.iter() {
+                <OperatorKeys<T>>::insert(key, who);
+            }
+        });
+    }
+}
+
+decl_error! {
+    pub enum Error for Module<T: Trait> {
+        NotOperator,
+        OperatorKeyInUse,
+        AlreadySubmitted,
+        StaleSubmission,
+        EmptySubmission,
+        /// a submission reports the same source of a token twice
+        DuplicateReport,
+        TooManyReports,
+        NoPendingPrice,
+        InvalidJsonPath,
+        TooManyDecimals,
+        /// a stored price would overflow with the new decimals
+        PriceOverflow,
+        SourceExists,
+        SourceNotFound,
+        InvalidFormula,
+        /// the token is priced by a formula
+        SyntheticToken,
+        NotSyntheticToken,
+        /// the token is priced by its sources
+        TokenHasSources,
+        PairExists,
+        PairNotFound,
     }
 }
 
 decl_module! {
     pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+        type Error = Error<T>;
+
         fn deposit_event() = default;
 
+        fn on_initialize(_bn: T::BlockNumber) -> Weight {
+            if Self::sources_version() < SOURCES_VERSION {
+                Self::migrate_sources();
+            }
+            SimpleDispatchInfo::default().weigh_data(())
+        }
+
         fn on_finalize(bn: T::BlockNumber) {
             Self::_on_finalize_(bn)
         }
 
-        pub fn add_source(origin, token: StrBytes, source_name: StrBytes, source_url: StrBytes, json_path: Vec<StrBytes>) -> DispatchResult {
+        /// add a source of a token, `json_path` is a JSONPath to the quote in the response, e.g. `$.data[0].priceUsd`
+        pub fn add_source(origin, token: StrBytes, source_name: StrBytes, source_url: StrBytes, json_path: StrBytes, inverted: bool) -> DispatchResult {
             ensure_root(origin)?;
-            if CryptoPriceSources::contains_key(&token) {
-                CryptoPriceSources::mutate(&token, |v| {
-                    v.push((source_name, source_url, json_path));
-                })
-            } else{
-                CryptoPriceSources::insert(&token, vec![(source_name, source_url, json_path)]);
+            let json_path = Self::parse_json_path(&json_path).ok_or(Error::<T>::InvalidJsonPath)?;
+            ensure!(!SyntheticTokens::contains_key(&token), Error::<T>::SyntheticToken);
+            ensure!(Self::source_index(&token, &source_name).is_none(), Error::<T>::SourceExists);
+            CryptoPriceSources::mutate(&token, |v| {
+                v.push(PriceSource {
+                    name: source_name.clone(),
+                    url: source_url,
+                    json_path,
+                    inverted,
+                });
+            });
+
+            Self::deposit_event(RawEvent::SourceAdded(token, source_name));
+            Ok(())
+        }
+
+        /// replace the endpoint of a source, its counters start over
+        pub fn update_source(origin, token: StrBytes, source_name: StrBytes, source_url: StrBytes, json_path: StrBytes, inverted: bool) -> DispatchResult {
+            ensure_root(origin)?;
+            let json_path = Self::parse_json_path(&json_path).ok_or(Error::<T>::InvalidJsonPath)?;
+            let index = Self::source_index(&token, &source_name).ok_or(Error::<T>::SourceNotFound)?;
+            CryptoPriceSources::mutate(&token, |v| {
+                v[index] = PriceSource {
+                    name: source_name.clone(),
+                    url: source_url,
+                    json_path,
+                    inverted,
+                };
+            });
+            SourceStats::remove(&token, &source_name);
+
+            Self::deposit_event(RawEvent::SourceUpdated(token, source_name));
+            Ok(())
+        }
+
+        pub fn remove_source(origin, token: StrBytes, source_name: StrBytes) -> DispatchResult {
+            ensure_root(origin)?;
+            let index = Self::source_index(&token, &source_name).ok_or(Error::<T>::SourceNotFound)?;
+            let mut sources = Self::crypto_price_sources(&token);
+            sources.remove(index);
+            if sources.is_empty() {
+                CryptoPriceSources::remove(&token);
+            } else {
+                CryptoPriceSources::insert(&token, sources);
             }
+            SourceStats::remove(&token, &source_name);
 
+            Self::deposit_event(RawEvent::SourceRemoved(token, source_name));
             Ok(())
         }
 
-        pub fn stack_price_unsigned(origin, block_number: T::BlockNumber, token: StrBytes, price: T::PriceInUSDT) -> DispatchResult {
-            ensure_none(origin)?;
-            Self::stack_price(block_number, token, price)?;
+        pub fn disable_source(origin, token: StrBytes, source_name: StrBytes) -> DispatchResult {
+            ensure_root(origin)?;
+            ensure!(Self::source_index(&token, &source_name).is_some(), Error::<T>::SourceNotFound);
+            SourceStats::mutate(&token, &source_name, |health| health.disabled = true);
+
+            Self::deposit_event(RawEvent::SourceDisabled(token, source_name));
             Ok(())
         }
 
-        pub fn stack_price_signed(origin, block_number: T::BlockNumber, token: StrBytes, price: T::PriceInUSDT) -> DispatchResult {
-            let who = ensure_signed(origin)?;
-            // Self::stack_price(block_number, token, price);
+        /// enable a source again, its counters start over
+        pub fn enable_source(origin, token: StrBytes, source_name: StrBytes) -> DispatchResult {
+            ensure_root(origin)?;
+            ensure!(Self::source_index(&token, &source_name).is_some(), Error::<T>::SourceNotFound);
+            SourceStats::remove(&token, &source_name);
+
+            Self::deposit_event(RawEvent::SourceEnabled(token, source_name));
+            Ok(())
+        }
+
+        pub fn set_source_limits(origin, max_failure_rate: u32, max_deviation_rate: u32, min_samples: u32, min_failure_reporters: u32) -> DispatchResult {
+            ensure_root(origin)?;
+            MaxSourceFailureRate::put(max_failure_rate);
+            MaxSourceDeviationRate::put(max_deviation_rate);
+            MinSourceSamples::put(min_samples);
+            MinFailureReporters::put(min_failure_reporters);
+            Ok(())
+        }
+
+        /// migrate the legacy sources of tokens the runtime upgrade couldn't find a name for
+        pub fn migrate_legacy_sources(origin, tokens: Vec<StrBytes>) -> DispatchResult {
+            ensure_root(origin)?;
+            let mut migrated = 0;
+            for token in tokens {
+                if Self::migrate_legacy_token(&token) {
+                    migrated += 1;
+                }
+            }
+            ensure!(migrated > 0, Error::<T>::SourceNotFound);
+            LegacySourcesLeft::mutate(|left| *left = left.saturating_sub(migrated));
+            Ok(())
+        }
+
+        /// price a token by a formula over tokens with sources, replacing its previous formula
+        pub fn set_synthetic_token(origin, token: StrBytes, formula: Vec<FormulaOp>) -> DispatchResult {
+            ensure_root(origin)?;
+            ensure!(!CryptoPriceSources::contains_key(&token), Error::<T>::TokenHasSources);
+            ensure!(Self::is_valid_formula(&token, &formula), Error::<T>::InvalidFormula);
+            SyntheticTokens::insert(&token, formula);
+
+            Self::deposit_event(RawEvent::SyntheticTokenSet(token));
+            Ok(())
+        }
+
+        /// stop updating a synthetic token, its last price ages out
+        pub fn remove_synthetic_token(origin, token: StrBytes) -> DispatchResult {
+            ensure_root(origin)?;
+            ensure!(SyntheticTokens::contains_key(&token), Error::<T>::NotSyntheticToken);
+            SyntheticTokens::remove(&token);
+
+            Self::deposit_event(RawEvent::SyntheticTokenRemoved(token));
+            Ok(())
+        }
+
+        pub fn add_trading_pair(origin, base: StrBytes, quote: StrBytes) -> DispatchResult {
+            ensure_root(origin)?;
+            let pair = (base, quote);
+            ensure!(!Self::trading_pairs().contains(&pair), Error::<T>::PairExists);
+            TradingPairs::mutate(|pairs| pairs.push(pair.clone()));
+
+            Self::deposit_event(RawEvent::TradingPairAdded(pair.0, pair.1));
+            Ok(())
+        }
+
+        pub fn remove_trading_pair(origin, base: StrBytes, quote: StrBytes) -> DispatchResult {
+            ensure_root(origin)?;
+            let pair = (base, quote);
+            ensure!(Self::trading_pairs().contains(&pair), Error::<T>::PairNotFound);
+            TradingPairs::mutate(|pairs| pairs.retain(|p| *p != pair));
+            <PairPrice<T>>::remove(&pair.0, &pair.1);
+
+            Self::deposit_event(RawEvent::TradingPairRemoved(pair.0, pair.1));
+            Ok(())
+        }
+
+        /// change the decimals a token's price is kept with, the current price and history are rescaled
+        pub fn set_token_decimals(origin, token: StrBytes, decimals: u32) -> DispatchResult {
+            ensure_root(origin)?;
+            ensure!(decimals <= MAX_PRICE_DECIMALS, Error::<T>::TooManyDecimals);
+            let from = Self::decimals_of(&token);
+            if from == decimals {
+                return Ok(());
+            }
+
+            // everything is rescaled before anything is written, a price that would overflow rejects the call
+            let rescale = |price| Self::rescale(price, from, decimals).ok_or(Error::<T>::PriceOverflow);
+            let current_price = if <CurrentPrice<T>>::contains_key(&token) {
+                Some(rescale(Self::current_price(&token))?)
+            } else {
+                None
+            };
+            let history = Self::price_history(&token)
+                .into_iter()
+                .map(|(block_number, moment, price)| Ok((block_number, moment, rescale(price)?)))
+                .collect::<Result<Vec<_>, Error<T>>>()?;
+            let pending = match Self::pending_price(&token) {
+                Some((price, rounds)) => Some((rescale(price)?, rounds)),
+                None => None,
+            };
+
+            if let Some(price) = current_price {
+                <CurrentPrice<T>>::insert(&token, price);
+            }
+            if !history.is_empty() {
+                <PriceHistory<T>>::insert(&token, history);
+            }
+            if let Some(pending) = pending {
+                <PendingPrice<T>>::insert(&token, pending);
+            }
+            // candidates of this round were quoted with the old decimals
+            <PriceCandidates<T>>::remove(&token);
+            TokenDecimals::insert(&token, decimals);
+            Ok(())
+        }
+
+        pub fn set_max_deviation(origin, percent: u32) -> DispatchResult {
+            ensure_root(origin)?;
+            MaxDeviation::put(percent);
+            Ok(())
+        }
+
+        pub fn set_min_quorum(origin, quorum: u32) -> DispatchResult {
+            ensure_root(origin)?;
+            MinQuorum::put(quorum);
+            Ok(())
+        }
+
+        pub fn set_history_depth(origin, depth: u32) -> DispatchResult {
+            ensure_root(origin)?;
+            HistoryDepth::put(depth);
+            Ok(())
+        }
+
+        pub fn set_circuit_breaker(origin, threshold: u32, stable_rounds: u32) -> DispatchResult {
+            ensure_root(origin)?;
+            CircuitBreakerThreshold::put(threshold);
+            StableRounds::put(stable_rounds);
+            Ok(())
+        }
+
+        /// accept a held price as the current price
+        pub fn confirm_price(origin, token: StrBytes) -> DispatchResult {
+            ensure_root(origin)?;
+            let (price, _) = <PendingPrice<T>>::take(&token).ok_or(Error::<T>::NoPendingPrice)?;
+            Self::set_price(&token, price, <system::Module<T>>::block_number());
+            Self::deposit_event(RawEvent::PriceConfirmed(token, price));
             Ok(())
         }
 
+        pub fn add_operator(origin, who: T::AccountId, key: crypto::Public) -> DispatchResult {
+            ensure_root(origin)?;
+            ensure!(!<OperatorKeys<T>>::contains_key(&key), Error::<T>::OperatorKeyInUse);
+
+            if let Some(old_key) = <Operators<T>>::get(&who) {
+                <OperatorKeys<T>>::remove(&old_key);
+            }
+            <Operators<T>>::insert(&who, key.clone());
+            <OperatorKeys<T>>::insert(&key, who.clone());
+
+            Self::deposit_event(RawEvent::OperatorAdded(who));
+            Ok(())
+        }
+
+        pub fn remove_operator(origin, who: T::AccountId) -> DispatchResult {
+            ensure_root(origin)?;
+            let key = <Operators<T>>::take(&who).ok_or(Error::<T>::NotOperator)?;
+            <OperatorKeys<T>>::remove(&key);
+            <LastSubmittedRound<T>>::remove(&who);
+
+            Self::deposit_event(RawEvent::OperatorRemoved(who));
+            Ok(())
+        }
+
+        pub fn stack_prices_signed(origin, block_number: T::BlockNumber, prices: SourceReports<T::PriceInUSDT>) -> DispatchResult {
+            let who = ensure_signed(origin)?;
+            Self::submit_prices(who, block_number, prices)
+        }
+
+        /// the signature of the payload is checked in `validate_unsigned`
+        pub fn stack_prices_unsigned(origin, payload: PricePayload<T::BlockNumber, T::PriceInUSDT>, _signature: crypto::Signature) -> DispatchResult {
+            ensure_none(origin)?;
+            let who = Self::operator_of_key(&payload.public).ok_or(Error::<T>::NotOperator)?;
+            Self::submit_prices(who, payload.block_number, payload.prices)
+        }
+
         fn offchain_worker(block_number: T::BlockNumber) {
-            Self::fetch_price_and_submit_unsigned(block_number);
+            Self::fetch_prices_and_submit_unsigned(block_number);
         }
     }
 }
@@ -135,58 +519,357 @@ impl<T: Trait> Module<T> {
         <CurrentPrice<T>>::contains_key(token)
     }
 
-    fn fetch_price_and_submit_unsigned(block_number: T::BlockNumber) {
+    /// the current price of a token with its last update and held price
+    pub fn price_info(token: &StrBytes) -> Option<TokenPriceInfo<T>> {
+        if !Self::is_token_known(token) {
+            return None;
+        }
+        let updated_at = Self::price_updated_at(token);
+        let held = Self::pending_price(token).map(|(price, _)| price);
+        Some((Self::current_price(token), updated_at, held))
+    }
+
+    /// every token with a price
+    pub fn prices() -> Vec<(StrBytes, TokenPriceInfo<T>)> {
+        <CurrentPrice<T>>::iter()
+            .filter_map(|(token, _)| Self::price_info(&token).map(|info| (token, info)))
+            .collect()
+    }
+
+    /// the current price of a token if it was updated within `max_age` blocks.
+    /// a price never updated by the oracle is only fresh when the token has no sources, e.g. a peg set at genesis.
+    pub fn price_if_fresh(token: &StrBytes, max_age: T::BlockNumber) -> Option<T::PriceInUSDT> {
+        if !Self::is_token_known(token) {
+            return None;
+        }
+        match Self::price_updated_at(token) {
+            Some((updated_at, _)) => {
+                let now = <system::Module<T>>::block_number();
+                if now.saturating_sub(updated_at) > max_age {
+                    return None;
+                }
+            }
+            None => {
+                if CryptoPriceSources::contains_key(token) {
+                    return None;
+                }
+            }
+        }
+        Some(Self::current_price(token))
+    }
+
+    fn round_of(block_number: T::BlockNumber) -> T::BlockNumber {
+        block_number / T::AggregateInterval::get()
+    }
+
+    /// check `who` is an operator which hasn't submitted in the current round yet, returns the round.
+    fn ensure_can_submit(
+        who: &T::AccountId,
+        block_number: T::BlockNumber,
+    ) -> Result<T::BlockNumber, Error<T>> {
+        ensure!(<Operators<T>>::contains_key(who), Error::<T>::NotOperator);
+
+        let now = <system::Module<T>>::block_number();
+        ensure!(
+            block_number <= now && block_number.saturating_add(T::AggregateInterval::get()) >= now,
+            Error::<T>::StaleSubmission
+        );
+
+        let round = Self::round_of(now);
+        ensure!(
+            Self::last_submitted_round(who) != Some(round),
+            Error::<T>::AlreadySubmitted
+        );
+
+        Ok(round)
+    }
+
+    fn submit_prices(
+        who: T::AccountId,
+        block_number: T::BlockNumber,
+        prices: SourceReports<T::PriceInUSDT>,
+    ) -> DispatchResult {
+        Self::check_reports(&prices)?;
+        let round = Self::ensure_can_submit(&who, block_number)?;
+
+        for (token, source, price) in prices {
+            // sources removed or disabled since the operator fetched them are ignored
+            if Self::source_index(&token, &source).is_none()
+                || Self::source_stats(&token, &source).disabled
+            {
+                continue;
+            }
+            match price {
+                Some(price) => {
+                    <PriceCandidates<T>>::mutate(&token, |v| v.push((who.clone(), source, price)))
+                }
+                None => <FailedReports<T>>::mutate(&token, |v| v.push((who.clone(), source))),
+            }
+        }
+        <LastSubmittedRound<T>>::insert(&who, round);
+
+        Self::deposit_event(RawEvent::PricesSubmitted(who, block_number));
+        Ok(())
+    }
+
+    /// the first `orcl` key in the local keystore that belongs to an operator
+    fn local_operator() -> Option<(crypto::Public, T::AccountId)> {
+        crypto::Public::all()
+            .into_iter()
+            .find_map(|key| Self::operator_of_key(&key).map(|who| (key, who)))
+    }
+
+    fn fetch_prices_and_submit_unsigned(block_number: T::BlockNumber) {
         use system::offchain::SubmitUnsignedTransaction;
 
+        let (key, who) = match Self::local_operator() {
+            Some(operator) => operator,
+            None => return,
+        };
+        if Self::last_submitted_round(&who) == Some(Self::round_of(block_number)) {
+            return;
+        }
+
+        let mut prices: SourceReports<T::PriceInUSDT> = vec![];
+        for (token, sources) in CryptoPriceSources::iter() {
+            let (next_fetch_at, failures) = Self::fetch_schedule(&token);
+            if block_number < next_fetch_at {
+                continue;
+            }
+            // the tokens left are fetched in the next round
+            if prices.len() + MAX_SOURCES_PER_FETCH > MAX_SUBMITTED_REPORTS {
+                break;
+            }
+
+            let sources: Vec<PriceSource> = sources
+                .into_iter()
+                .filter(|s| !Self::source_stats(&token, &s.name).disabled)
+                .collect();
+            let fetched = Self::fetch_token_prices(&sources, Self::decimals_of(&token));
+            let succeeded = fetched.iter().any(|(_, price)| price.is_some());
+            prices.extend(
+                fetched
+                    .into_iter()
+                    .map(|(source, price)| (token.clone(), source, price)),
+            );
+            if !succeeded {
+                let failures = failures.saturating_add(1);
+                debug::warn!(
+                    "Fail to fetch price of {:?} from any source, {} failures in a row",
+                    core::str::from_utf8(&token).unwrap_or_default(),
+                    failures
+                );
+                Self::set_fetch_schedule(&token, block_number, failures);
+            } else {
+                Self::set_fetch_schedule(&token, block_number, 0);
+            }
+        }
+
+        if prices.is_empty() {
+            return;
+        }
+
+        let payload = PricePayload {
+            public: key.clone(),
+            block_number,
+            prices,
+        };
+        let signature = match key.sign(&payload.encode()) {
+            Some(signature) => signature,
+            None => {
+                debug::error!("Fail to sign prices with the oracle key");
+                return;
+            }
+        };
+
+        let call = Call::stack_prices_unsigned(payload, signature);
+        if let Err(e) = T::SubmitUnsignedTransaction::submit_unsigned(call) {
+            debug::error!("Fail to submit unsigned transaction for prices: {:?}", e);
+        }
+    }
+
+    /// query up to `MAX_SOURCES_PER_FETCH` sources, starting from a random one, and return what each of them gave.
+    fn fetch_token_prices(
+        sources: &[PriceSource],
+        decimals: u32,
+    ) -> Vec<(StrBytes, Option<T::PriceInUSDT>)> {
+        if sources.is_empty() {
+            return vec![];
+        }
+
         let rand_s = sp_io::offchain::random_seed();
-        let r = u64::from_ne_bytes(sp_io::hashing::twox_64(&rand_s));
-        let token = b"BTC".to_vec();
-        let sources = CryptoPriceSources::get(&token);
-        let source = &sources[r as usize % sources.len()];
-
-        match Self::fetch_json(&source.1) {
-            Err(e) => {
-                debug::error!("Fail to fetch price: {}", e);
-            }
-            Ok(json_data) => {
-                let price_r = Self::parse_price(json_data, &source.2);
-                match price_r {
-                    Ok(price) => {
-                        let call = Call::stack_price_unsigned(block_number, token, price);
-                        match T::SubmitUnsignedTransaction::submit_unsigned(call) {
-                            Err(e) => {
-                                debug::error!(
-                                    "Fail to submit unsigned transaction for price: {:?}",
-                                    e
-                                );
-                            }
-                            Ok(_) => {
-                                return ();
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        debug::error!("Fail to parse price: {}", e);
-                    }
+        let r = u64::from_ne_bytes(sp_io::hashing::twox_64(&rand_s)) as usize;
+        let n = sources.len().min(MAX_SOURCES_PER_FETCH);
+
+        let mut prices = vec![];
+        for i in 0..n {
+            let source = &sources[(r.wrapping_add(i)) % sources.len()];
+            match Self::fetch_json(&source.url).and_then(|json| {
+                Self::parse_price(json, &source.json_path, source.inverted, decimals)
+            }) {
+                Ok(price) => prices.push((source.name.clone(), Some(price))),
+                Err(e) => {
+                    debug::warn!(
+                        "Fail to fetch price from {:?}: {}",
+                        core::str::from_utf8(&source.name).unwrap_or_default(),
+                        e
+                    );
+                    prices.push((source.name.clone(), None));
                 }
             }
         }
+        prices
     }
 
-    fn stack_price(
-        _block_number: T::BlockNumber,
-        token: StrBytes,
-        price: T::PriceInUSDT,
-    ) -> Result<(), &'static str> {
-        // check price
+    fn fetch_schedule_key(token: &StrBytes) -> Vec<u8> {
+        [FETCH_SCHEDULE_PREFIX, &token[..]].concat()
+    }
 
-        <PriceCandidates<T>>::mutate(&token, |v| {
-            v.push(price);
-        });
+    /// `(next_fetch_at, failures)` of a token, kept in offchain local storage
+    fn fetch_schedule(token: &StrBytes) -> (T::BlockNumber, u32) {
+        let key = Self::fetch_schedule_key(token);
+        StorageValueRef::persistent(&key)
+            .get::<(T::BlockNumber, u32)>()
+            .flatten()
+            .unwrap_or_default()
+    }
+
+    /// schedule the next fetch of a token, backing off exponentially after consecutive failures
+    fn set_fetch_schedule(token: &StrBytes, block_number: T::BlockNumber, failures: u32) {
+        let exp = failures.min(MAX_BACKOFF_EXPONENT);
+        let wait = T::FetchInterval::get().saturating_mul((1u32 << exp).into());
+        let key = Self::fetch_schedule_key(token);
+        StorageValueRef::persistent(&key).set(&(block_number.saturating_add(wait), failures));
+    }
 
+    /// a submission reports each source of a token at most once and no more than `MAX_SUBMITTED_REPORTS` prices
+    fn check_reports(prices: &SourceReports<T::PriceInUSDT>) -> Result<(), Error<T>> {
+        ensure!(!prices.is_empty(), Error::<T>::EmptySubmission);
+        ensure!(
+            prices.len() <= MAX_SUBMITTED_REPORTS,
+            Error::<T>::TooManyReports
+        );
+        let mut reported: Vec<(&StrBytes, &StrBytes)> = prices
+            .iter()
+            .map(|(token, source, _)| (token, source))
+            .collect();
+        reported.sort();
+        reported.dedup();
+        ensure!(reported.len() == prices.len(), Error::<T>::DuplicateReport);
         Ok(())
     }
 
+    /// one price per operator, the median of what it got from the sources of the token
+    fn operator_prices(
+        candidates: &[(T::AccountId, StrBytes, T::PriceInUSDT)],
+    ) -> Vec<T::PriceInUSDT> {
+        let mut by_operator: BTreeMap<&T::AccountId, Vec<T::PriceInUSDT>> = BTreeMap::new();
+        for (who, _, price) in candidates {
+            by_operator.entry(who).or_insert_with(Vec::new).push(*price);
+        }
+        by_operator
+            .into_iter()
+            .filter_map(|(_, mut prices)| {
+                prices.sort();
+                Self::median(&prices)
+            })
+            .collect()
+    }
+
+    fn source_index(token: &StrBytes, source: &StrBytes) -> Option<usize> {
+        Self::crypto_price_sources(token)
+            .iter()
+            .position(|s| s.name == *source)
+    }
+
+    /// count a round of a token's sources, each operator reporting a source once.
+    /// a source succeeds if an operator got its price and deviates if the median of its prices is beyond
+    /// `MaxDeviation` of the aggregated price, it fails only as `failure_reporters_needed` allows.
+    fn record_round(
+        token: &StrBytes,
+        candidates: &[(T::AccountId, StrBytes, T::PriceInUSDT)],
+        failed: &[(T::AccountId, StrBytes)],
+        price: Option<T::PriceInUSDT>,
+    ) {
+        let max_deviation = Self::max_deviation();
+        let failure_reporters = Self::failure_reporters_needed();
+        for source in Self::crypto_price_sources(token) {
+            let mut health = Self::source_stats(token, &source.name);
+            if health.disabled {
+                continue;
+            }
+
+            let mut fetched: BTreeMap<&T::AccountId, T::PriceInUSDT> = BTreeMap::new();
+            for (who, _, p) in candidates.iter().filter(|(_, s, _)| *s == source.name) {
+                fetched.insert(who, *p);
+            }
+            let mut failing: Vec<&T::AccountId> = failed
+                .iter()
+                .filter(|(who, s)| *s == source.name && !fetched.contains_key(who))
+                .map(|(who, _)| who)
+                .collect();
+            failing.sort();
+            failing.dedup();
+
+            if failing.len() >= failure_reporters && failing.len() > fetched.len() {
+                health.failures = health.failures.saturating_add(1);
+            } else if !fetched.is_empty() {
+                health.successes = health.successes.saturating_add(1);
+                let mut prices: Vec<T::PriceInUSDT> = fetched.values().cloned().collect();
+                prices.sort();
+                let deviated = match (price, Self::median(&prices)) {
+                    (Some(price), Some(median)) => {
+                        max_deviation > 0 && Self::deviates(median, price, max_deviation)
+                    }
+                    _ => false,
+                };
+                if deviated {
+                    health.deviations = health.deviations.saturating_add(1);
+                }
+            } else {
+                continue;
+            }
+            Self::judge_source(token, &source.name, health);
+        }
+    }
+
+    /// `MinFailureReporters`, but no more than there are operators, so a small operator set can still fail a source
+    fn failure_reporters_needed() -> usize {
+        let operators = <Operators<T>>::iter().count();
+        (Self::min_failure_reporters() as usize)
+            .min(operators)
+            .max(1)
+    }
+
+    /// store the counters of a source, disabling it when its failure or deviation rate is too high
+    fn judge_source(token: &StrBytes, source: &StrBytes, mut health: SourceHealth) {
+        let samples = health.successes.saturating_add(health.failures);
+        if !health.disabled && samples > 0 && samples >= Self::min_source_samples() {
+            let rate = |n: u32, total: u32| (n as u64) * 100 / (total as u64).max(1);
+            let max_failure_rate = Self::max_source_failure_rate();
+            let max_deviation_rate = Self::max_source_deviation_rate();
+            if (max_failure_rate > 0 && rate(health.failures, samples) > max_failure_rate as u64)
+                || (max_deviation_rate > 0
+                    && rate(health.deviations, health.successes) > max_deviation_rate as u64)
+            {
+                health.disabled = true;
+                Self::deposit_event(RawEvent::SourceDisabled(token.clone(), source.clone()));
+            }
+        }
+        SourceStats::insert(token, source, health);
+    }
+
+    /// every source of a token with its counters
+    pub fn source_health(token: &StrBytes) -> Vec<(StrBytes, SourceHealth)> {
+        Self::crypto_price_sources(token)
+            .into_iter()
+            .map(|s| {
+                let health = Self::source_stats(token, &s.name);
+                (s.name, health)
+            })
+            .collect()
+    }
+
     fn fetch_json(url: &StrBytes) -> Result<JsonValue, &'static str> {
         let deadline = sp_io::offchain::timestamp().add(Duration::from_millis(2_000));
         let remote_url = core::str::from_utf8(&url).map_err(|_| "Invalid Remote URL")?;
@@ -209,57 +892,288 @@ impl<T: Trait> Module<T> {
         .map_err(|_| "invalid JSON response")?)
     }
 
+    /// read the quote at `json_path` and scale it to `decimals`, exactly and rounding half up
     fn parse_price(
         json_data: JsonValue,
-        json_path: &[StrBytes],
+        json_path: &[JsonPathSegment],
+        inverted: bool,
+        decimals: u32,
     ) -> Result<T::PriceInUSDT, &'static str> {
-        if json_path.len() == 0 {
-            // if let Some(p_f64) = json_data.get_number_f64() {
-            //     return Ok(((p_f64 * 10000.).round() as u64).try_into().ok.unwrap());
-            // } else if let Some(price_u8) = json_data.get_bytes() {
-            //     let val_f64: f64 = core::str::from_utf8(&price_u8)
-            //         .map_err(|_| "fetch_price: val_f64 convert to string error")?
-            //         .parse::<f64>()
-            //         .map_err(|_| "fetch_price: val_u8 parsing to f64 error")?;
-            //     return Ok((val_f64 * 10000.).round() as u64);
-            // }
-            return Ok(Self::parse_field(&json_data)?.try_into().ok().unwrap());
+        let mut data_cur = &json_data;
+        for segment in json_path {
+            data_cur = match (segment, data_cur) {
+                (JsonPathSegment::Key(key), JsonValue::Object(fields)) => fields
+                    .iter()
+                    .find(|(k, _)| *key == Self::vecchars_to_vecbytes(k))
+                    .map(|(_, v)| v),
+                (JsonPathSegment::Index(i), JsonValue::Array(items)) => items.get(*i as usize),
+                _ => None,
+            }
+            .ok_or("JSON does not conform to expectation")?;
+        }
+
+        let (mantissa, exponent) = Self::parse_field(data_cur)?;
+        let price = Self::scale_decimal(mantissa, exponent, decimals, inverted)?;
+        T::PriceInUSDT::try_from(price).map_err(|_| "price overflows")
+    }
+
+    /// a JSON number or numeric string as `(mantissa, exponent)` of base 10
+    fn parse_field(json_data: &JsonValue) -> Result<(u128, i32), &'static str> {
+        match json_data {
+            JsonValue::Number(n) => {
+                if n.integer < 0 {
+                    return Err("negative price");
+                }
+                let mantissa = 10u128
+                    .checked_pow(n.fraction_length)
+                    .and_then(|shift| (n.integer as u128).checked_mul(shift))
+                    .and_then(|integer| integer.checked_add(n.fraction as u128))
+                    .ok_or("too many digits")?;
+                Ok((mantissa, n.exponent - n.fraction_length as i32))
+            }
+            JsonValue::String(chars) => Self::parse_decimal(&Self::vecchars_to_vecbytes(chars)),
+            _ => Err("unknown data"),
+        }
+    }
+
+    /// parse a decimal such as `6536.92`, `0.5` or `1.2e-5` into `(mantissa, exponent)` of base 10
+    pub fn parse_decimal(s: &[u8]) -> Result<(u128, i32), &'static str> {
+        let (digits, mut exponent) = match s.iter().position(|c| *c == b'e' || *c == b'E') {
+            Some(i) => {
+                let exponent = core::str::from_utf8(&s[i + 1..])
+                    .ok()
+                    .and_then(|e| e.parse::<i32>().ok())
+                    .ok_or("invalid exponent")?;
+                (&s[..i], exponent)
+            }
+            None => (s, 0),
+        };
+        let digits = match digits.first() {
+            Some(b'+') => &digits[1..],
+            _ => digits,
+        };
+
+        let mut mantissa: u128 = 0;
+        let mut seen_digit = false;
+        let mut seen_point = false;
+        for c in digits {
+            match c {
+                b'0'..=b'9' => {
+                    mantissa = mantissa
+                        .checked_mul(10)
+                        .and_then(|m| m.checked_add((c - b'0') as u128))
+                        .ok_or("too many digits")?;
+                    seen_digit = true;
+                    if seen_point {
+                        exponent = exponent.checked_sub(1).ok_or("invalid exponent")?;
+                    }
+                }
+                b'.' if !seen_point => seen_point = true,
+                _ => return Err("not a decimal"),
+            }
+        }
+        if !seen_digit {
+            return Err("not a decimal");
+        }
+        Ok((mantissa, exponent))
+    }
+
+    /// `mantissa * 10^exponent`, or its reciprocal if inverted, with `decimals` decimals, rounding half up
+    pub fn scale_decimal(
+        mantissa: u128,
+        exponent: i32,
+        decimals: u32,
+        inverted: bool,
+    ) -> Result<u128, &'static str> {
+        if mantissa == 0 {
+            return Err("zero price");
+        }
+        let price = if inverted {
+            let shift = decimals as i32 - exponent;
+            if shift < 0 {
+                0
+            } else {
+                let numerator = 10u128.checked_pow(shift as u32).ok_or("price overflows")?;
+                Self::div_round(numerator, mantissa)
+            }
         } else {
-            let mut data_cur = &json_data;
+            let shift = decimals as i32 + exponent;
+            if shift >= 0 {
+                10u128
+                    .checked_pow(shift as u32)
+                    .and_then(|scale| mantissa.checked_mul(scale))
+                    .ok_or("price overflows")?
+            } else {
+                match 10u128.checked_pow((-shift) as u32) {
+                    Some(scale) => Self::div_round(mantissa, scale),
+                    None => 0,
+                }
+            }
+        };
+        if price == 0 {
+            return Err("price rounds to zero");
+        }
+        Ok(price)
+    }
+
+    fn div_round(n: u128, d: u128) -> u128 {
+        let q = n / d;
+        if (n % d).saturating_mul(2) >= d {
+            q.saturating_add(1)
+        } else {
+            q
+        }
+    }
 
-            for f in json_path {
-                if let Some(obj_vec) = data_cur.get_object() {
-                    let (_, v) = obj_vec
+    /// parse a JSONPath with dot or bracket notation, e.g. `$.data[0].priceUsd` or `$['RAW']['BTC']`.
+    /// the leading `$` is optional, an empty path selects the whole document.
+    pub fn parse_json_path(path: &[u8]) -> Option<Vec<JsonPathSegment>> {
+        let mut segments = vec![];
+        let mut rest = match path.first() {
+            Some(b'$') => &path[1..],
+            _ => path,
+        };
+        // `USD` is read as `$.USD`
+        let mut dotless = path
+            .first()
+            .map_or(false, |c| ![b'$', b'.', b'['].contains(c));
+
+        while !rest.is_empty() {
+            match rest[0] {
+                b'[' => {
+                    let end = rest.iter().position(|c| *c == b']')?;
+                    let inner = &rest[1..end];
+                    let segment = match inner.first()? {
+                        q @ b'\'' | q @ b'"' => {
+                            if inner.len() < 2 || inner[inner.len() - 1] != *q {
+                                return None;
+                            }
+                            JsonPathSegment::Key(inner[1..inner.len() - 1].to_vec())
+                        }
+                        _ => {
+                            JsonPathSegment::Index(core::str::from_utf8(inner).ok()?.parse().ok()?)
+                        }
+                    };
+                    segments.push(segment);
+                    rest = &rest[end + 1..];
+                }
+                c if c == b'.' || dotless => {
+                    let start = if dotless { 0 } else { 1 };
+                    let end = rest[start..]
                         .iter()
-                        .filter(|(k, _)| f.to_vec() == Self::vecchars_to_vecbytes(k))
-                        .nth(0)
-                        .ok_or("fetch_price: JSON does not conform to expectation")?;
-                    data_cur = v;
-                } else {
-                    return Err("JSON does not confirm to expectation");
+                        .position(|c| *c == b'.' || *c == b'[')
+                        .map(|i| i + start)
+                        .unwrap_or(rest.len());
+                    let key = &rest[start..end];
+                    if key.is_empty() || key == b"*" {
+                        return None;
+                    }
+                    segments.push(JsonPathSegment::Key(key.to_vec()));
+                    rest = &rest[end..];
                 }
+                _ => return None,
             }
-
-            return Ok(Self::parse_field(data_cur)?.try_into().ok().unwrap());
+            dotless = false;
         }
+        Some(segments)
+    }
+
+    /// decimals a token's price is kept with
+    pub fn decimals_of(token: &StrBytes) -> u32 {
+        Self::token_decimals(token).unwrap_or(DEFAULT_PRICE_DECIMALS)
     }
 
-    /// parse_field can only parse number & &[u8]
-    /// float point will be round up
-    fn parse_field(json_data: &JsonValue) -> Result<u64, &'static str> {
-        if let Some(p_f64) = json_data.get_number_f64() {
-            return Ok(((p_f64 * PRICE_SCALE as f64).round() as u64)
-                .try_into()
-                .ok()
-                .unwrap());
-        } else if let Some(price_u8) = json_data.get_bytes() {
-            let val_f64: f64 = core::str::from_utf8(&price_u8)
-                .map_err(|_| "parse_field: val_f64 convert to string error")?
-                .parse::<f64>()
-                .map_err(|_| "parse_field: val_u8 parsing to f64 error")?;
-            return Ok((val_f64 * PRICE_SCALE as f64).round() as u64);
+    /// a price kept with `from` decimals expressed with `to` decimals, none if it overflows `PriceInUSDT`
+    pub fn rescale(price: T::PriceInUSDT, from: u32, to: u32) -> Option<T::PriceInUSDT> {
+        let price: u128 = price.unique_saturated_into();
+        let price = if to >= from {
+            price.checked_mul(10u128.checked_pow(to - from)?)?
+        } else {
+            match 10u128.checked_pow(from - to) {
+                Some(scale) => Self::div_round(price, scale),
+                None => 0,
+            }
+        };
+        T::PriceInUSDT::try_from(price).ok()
+    }
+
+    /// storage prefix of `CryptoPriceSources`, the same under either hasher
+    fn sources_prefix() -> Vec<u8> {
+        let mut prefix = sp_io::hashing::twox_128(b"NewOracle").to_vec();
+        prefix.extend_from_slice(&sp_io::hashing::twox_128(b"CryptoPriceSources"));
+        prefix
+    }
+
+    /// storage key of a token's sources from before `CryptoPriceSources` was keyed with `twox_64_concat`
+    fn legacy_sources_key(token: &StrBytes) -> Vec<u8> {
+        let mut key = Self::sources_prefix();
+        key.extend_from_slice(&sp_io::hashing::blake2_256(&token.encode()));
+        key
+    }
+
+    /// move sources stored as `LegacyPriceSource` under their `opaque_blake2_256` keys to
+    /// `PriceSource` under the `twox_64_concat` ones. The old keys are found by a prefix scan,
+    /// but a hash doesn't give its token back, so they are matched against every token the
+    /// other maps know of. The ones left unmatched stay in place, counted in `LegacySourcesLeft`,
+    /// for governance to name in `migrate_legacy_sources`.
+    fn migrate_sources() {
+        // nothing is under the new keys yet, so everything under the prefix is legacy
+        let prefix = Self::sources_prefix();
+        let mut legacy_keys = Vec::new();
+        let mut key = prefix.clone();
+        while let Some(next) = sp_io::storage::next_key(&key) {
+            if !next.starts_with(&prefix) {
+                break;
+            }
+            legacy_keys.push(next.clone());
+            key = next;
+        }
+
+        let mut tokens: Vec<StrBytes> = <CurrentPrice<T>>::iter().map(|(k, _)| k).collect();
+        tokens.extend(<PriceCandidates<T>>::iter().map(|(k, _)| k));
+        tokens.extend(<PriceHistory<T>>::iter().map(|(k, _)| k));
+        tokens.extend(TokenDecimals::iter().map(|(k, _)| k));
+        tokens.sort();
+        tokens.dedup();
+
+        let mut migrated = 0;
+        for token in tokens {
+            if legacy_keys.contains(&Self::legacy_sources_key(&token))
+                && Self::migrate_legacy_token(&token)
+            {
+                migrated += 1;
+            }
         }
-        Err("unknown data")
+        LegacySourcesLeft::put(legacy_keys.len() as u32 - migrated);
+        SourcesVersion::put(SOURCES_VERSION);
+    }
+
+    /// move a token's legacy sources to `CryptoPriceSources`, the old key is only cleared once decoded
+    fn migrate_legacy_token(token: &StrBytes) -> bool {
+        let old_key = Self::legacy_sources_key(token);
+        let legacy = match sp_io::storage::get(&old_key)
+            .map(|raw| Vec::<LegacyPriceSource>::decode(&mut &raw[..]))
+        {
+            Some(Ok(legacy)) => legacy,
+            Some(Err(_)) => {
+                debug::warn!("legacy sources of {:?} don't decode, kept", token);
+                return false;
+            }
+            None => return false,
+        };
+        let sources = legacy
+            .into_iter()
+            .map(|(name, url, keys)| PriceSource {
+                name,
+                url,
+                json_path: keys.into_iter().map(JsonPathSegment::Key).collect(),
+                inverted: false,
+            })
+            .collect::<Vec<_>>();
+        CryptoPriceSources::insert(token, sources);
+        sp_io::storage::clear(&old_key);
+        Self::deposit_event(RawEvent::SourcesMigrated(token.clone()));
+        true
     }
 
     fn vecchars_to_vecbytes<I: IntoIterator<Item = char> + Clone>(it: &I) -> Vec<u8> {
@@ -268,25 +1182,290 @@ impl<T: Trait> Module<T> {
 
     fn _on_finalize_(bn: T::BlockNumber) {
         if (bn % T::AggregateInterval::get()).is_zero() {
-            for (k, _) in <PriceCandidates<T>>::iter() {
+            let mut tokens: Vec<StrBytes> = <PriceCandidates<T>>::iter().map(|(k, _)| k).collect();
+            tokens.extend(<FailedReports<T>>::iter().map(|(k, _)| k));
+            tokens.sort();
+            tokens.dedup();
+            for k in tokens {
                 let pc = <PriceCandidates<T>>::take(&k);
-                let l = pc.len();
-                let t: T::PriceInUSDT = pc.into_iter().fold(0.into(), |acc, x| acc + x);
-                let mean = t / <T::PriceInUSDT as TryFrom<usize>>::try_from(l)
-                    .ok()
-                    .unwrap();
-                if <CurrentPrice<T>>::contains_key(&k) {
-                    <CurrentPrice<T>>::mutate(&k, |v| {
-                        *v = mean;
-                    });
-                } else {
-                    <CurrentPrice<T>>::insert(&k, mean);
+                let failed = <FailedReports<T>>::take(&k);
+                if pc.is_empty() {
+                    Self::record_round(&k, &pc, &failed, None);
+                    continue;
+                }
+                match Self::aggregate_price(Self::operator_prices(&pc)) {
+                    Ok(price) => {
+                        Self::record_round(&k, &pc, &failed, Some(price));
+                        Self::update_price(&k, price, bn);
+                    }
+                    Err(accepted) => {
+                        Self::record_round(&k, &pc, &failed, None);
+                        Self::deposit_event(RawEvent::QuorumNotMet(k, accepted));
+                    }
                 }
             }
+            Self::derive_prices(bn);
             <NextAggregateAt<T>>::put(bn + T::AggregateInterval::get());
         }
     }
 
+    /// update the synthetic tokens, then the trading pairs, whose tokens were all updated at `bn`.
+    /// a derived price is never fresher than the prices it comes from.
+    fn derive_prices(bn: T::BlockNumber) {
+        for (token, formula) in SyntheticTokens::iter() {
+            let updated = formula.iter().all(|op| match op {
+                FormulaOp::Price(t) => Self::is_updated_at(t, bn),
+                _ => true,
+            });
+            if !updated {
+                continue;
+            }
+            match Self::evaluate(&formula, Self::decimals_of(&token)) {
+                Some(price) => Self::update_price(&token, price, bn),
+                None => Self::deposit_event(RawEvent::DerivationFailed(token)),
+            }
+        }
+
+        for (base, quote) in Self::trading_pairs() {
+            if !Self::is_updated_at(&base, bn) || !Self::is_updated_at(&quote, bn) {
+                continue;
+            }
+            let formula = [
+                FormulaOp::Price(base.clone()),
+                FormulaOp::Price(quote.clone()),
+                FormulaOp::Div,
+            ];
+            match Self::evaluate(&formula, PAIR_PRICE_DECIMALS) {
+                Some(price) => <PairPrice<T>>::insert(&base, &quote, (price, bn)),
+                None => <PairPrice<T>>::remove(&base, &quote),
+            }
+        }
+    }
+
+    /// whether the token's price was aggregated at `bn`, prices pinned at genesis always are
+    fn is_updated_at(token: &StrBytes, bn: T::BlockNumber) -> bool {
+        match Self::price_updated_at(token) {
+            Some((updated_at, _)) => updated_at == bn,
+            None => Self::is_token_known(token) && !CryptoPriceSources::contains_key(token),
+        }
+    }
+
+    /// a formula is well formed, refers to tokens other than synthetic ones and doesn't refer to `token`
+    fn is_valid_formula(token: &StrBytes, formula: &[FormulaOp]) -> bool {
+        if formula.is_empty() || formula.len() > MAX_FORMULA_LEN {
+            return false;
+        }
+        let mut depth = 0usize;
+        for op in formula {
+            match op {
+                FormulaOp::Price(t) => {
+                    if t == token || SyntheticTokens::contains_key(t) {
+                        return false;
+                    }
+                    depth += 1;
+                }
+                FormulaOp::Const(_) => depth += 1,
+                _ => {
+                    if depth < 2 {
+                        return false;
+                    }
+                    depth -= 1;
+                }
+            }
+        }
+        // a token priced by another synthetic token's formula can't become synthetic itself
+        depth == 1
+            && !SyntheticTokens::iter().any(|(_, f)| f.contains(&FormulaOp::Price(token.clone())))
+    }
+
+    /// the value of a formula over the current prices with `decimals`, none if a token is unknown,
+    /// a division is by zero or a value overflows
+    pub fn evaluate(formula: &[FormulaOp], decimals: u32) -> Option<T::PriceInUSDT> {
+        let one = 10u128.pow(MAX_PRICE_DECIMALS);
+        let mut stack: Vec<u128> = vec![];
+        for op in formula {
+            let value = match op {
+                FormulaOp::Price(token) => {
+                    if !Self::is_token_known(token) {
+                        return None;
+                    }
+                    let price: u128 = Self::current_price(token).unique_saturated_into();
+                    let exp = MAX_PRICE_DECIMALS.checked_sub(Self::decimals_of(token))?;
+                    price.checked_mul(10u128.pow(exp))?
+                }
+                FormulaOp::Const(c) => (*c as u128).checked_mul(one / PRICE_SCALE as u128)?,
+                op => {
+                    let b = stack.pop()?;
+                    let a = stack.pop()?;
+                    match op {
+                        FormulaOp::Add => a.checked_add(b)?,
+                        FormulaOp::Sub => a.checked_sub(b)?,
+                        FormulaOp::Mul => multiply_by_rational(a, b, one).ok()?,
+                        _ if b == 0 => return None,
+                        _ => multiply_by_rational(a, one, b).ok()?,
+                    }
+                }
+            };
+            stack.push(value);
+        }
+        if stack.len() != 1 || decimals > MAX_PRICE_DECIMALS {
+            return None;
+        }
+
+        let value = Self::div_round(stack[0], 10u128.pow(MAX_PRICE_DECIMALS - decimals));
+        let max: u128 = T::PriceInUSDT::max_value().unique_saturated_into();
+        if value > max {
+            return None;
+        }
+        Some(T::PriceInUSDT::unique_saturated_from(value))
+    }
+
+    /// whether lending against the token should wait for a held price to be settled
+    pub fn is_price_pending(token: &StrBytes) -> bool {
+        <PendingPrice<T>>::contains_key(token)
+    }
+
+    fn deviates(price: T::PriceInUSDT, reference: T::PriceInUSDT, percent: u32) -> bool {
+        let diff = if price > reference {
+            price - reference
+        } else {
+            reference - price
+        };
+        diff.saturating_mul(100u32.into()) > reference.saturating_mul(percent.into())
+    }
+
+    fn update_price(token: &StrBytes, price: T::PriceInUSDT, bn: T::BlockNumber) {
+        let threshold = Self::circuit_breaker_threshold();
+        if threshold > 0 && Self::is_token_known(token) {
+            let current = Self::current_price(token);
+            if Self::deviates(price, current, threshold) {
+                Self::hold_price(token, current, price, bn);
+                return;
+            }
+        }
+
+        if <PendingPrice<T>>::take(token).is_some() {
+            Self::deposit_event(RawEvent::PriceAlertCleared(token.clone()));
+        }
+        Self::set_price(token, price, bn);
+        Self::deposit_event(RawEvent::PriceAggregated(token.clone(), price));
+    }
+
+    fn hold_price(
+        token: &StrBytes,
+        current: T::PriceInUSDT,
+        price: T::PriceInUSDT,
+        bn: T::BlockNumber,
+    ) {
+        let threshold = Self::circuit_breaker_threshold();
+        match Self::pending_price(token) {
+            Some((pending, rounds)) if !Self::deviates(price, pending, threshold) => {
+                let rounds = rounds + 1;
+                let stable_rounds = Self::stable_rounds();
+                if stable_rounds > 0 && rounds >= stable_rounds {
+                    <PendingPrice<T>>::remove(token);
+                    Self::set_price(token, price, bn);
+                    Self::deposit_event(RawEvent::PriceConfirmed(token.clone(), price));
+                } else {
+                    <PendingPrice<T>>::insert(token, (price, rounds));
+                }
+            }
+            _ => {
+                <PendingPrice<T>>::insert(token, (price, 0));
+                Self::deposit_event(RawEvent::PriceDeviationAlert(token.clone(), current, price));
+            }
+        }
+    }
+
+    fn set_price(token: &StrBytes, price: T::PriceInUSDT, bn: T::BlockNumber) {
+        let now = <timestamp::Module<T>>::get();
+        <CurrentPrice<T>>::insert(token, price);
+        <PriceUpdatedAt<T>>::insert(token, (bn, now));
+        Self::record_history(token, bn, now, price);
+    }
+
+    fn record_history(
+        token: &StrBytes,
+        block_number: T::BlockNumber,
+        moment: T::Moment,
+        price: T::PriceInUSDT,
+    ) {
+        let depth = Self::history_depth() as usize;
+        <PriceHistory<T>>::mutate(token, |history| {
+            history.push((block_number, moment, price));
+            if history.len() > depth {
+                let excess = history.len() - depth;
+                history.drain(..excess);
+            }
+        });
+    }
+
+    /// time weighted average price over the last `window` blocks, each aggregated price weighs as many blocks as it stayed current.
+    /// none if the token has no history.
+    pub fn twap(token: &StrBytes, window: T::BlockNumber) -> Option<T::PriceInUSDT> {
+        let history = Self::price_history(token);
+        let (_, _, latest) = *history.last()?;
+
+        let now = <system::Module<T>>::block_number();
+        let start = now.saturating_sub(window);
+
+        let mut weighted: u128 = 0;
+        let mut total: u128 = 0;
+        for (i, (bn, _, price)) in history.iter().enumerate() {
+            let until = history.get(i + 1).map(|(next, _, _)| *next).unwrap_or(now);
+            if until <= start {
+                continue;
+            }
+            let from = if *bn > start { *bn } else { start };
+            let blocks: u128 = until.saturating_sub(from).unique_saturated_into();
+            let price: u128 = (*price).unique_saturated_into();
+            weighted = weighted.saturating_add(price.saturating_mul(blocks));
+            total = total.saturating_add(blocks);
+        }
+
+        if total == 0 {
+            return Some(latest);
+        }
+        Some(T::PriceInUSDT::unique_saturated_from(weighted / total))
+    }
+
+    fn median(sorted: &[T::PriceInUSDT]) -> Option<T::PriceInUSDT> {
+        let l = sorted.len();
+        if l == 0 {
+            return None;
+        }
+        if l % 2 == 1 {
+            Some(sorted[l / 2])
+        } else {
+            Some(sorted[l / 2 - 1].saturating_add(sorted[l / 2]) / 2u32.into())
+        }
+    }
+
+    /// mean of the operators' prices within `MaxDeviation` of their median.
+    /// returns the number of accepted operators as error when it's below `MinQuorum`.
+    fn aggregate_price(mut candidates: Vec<T::PriceInUSDT>) -> Result<T::PriceInUSDT, u32> {
+        candidates.sort();
+        let median = Self::median(&candidates).ok_or(0u32)?;
+
+        let max_deviation = Self::max_deviation();
+        let accepted: Vec<T::PriceInUSDT> = candidates
+            .into_iter()
+            .filter(|c| max_deviation == 0 || !Self::deviates(*c, median, max_deviation))
+            .collect();
+
+        let l = accepted.len();
+        if l == 0 || (l as u32) < Self::min_quorum() {
+            return Err(l as u32);
+        }
+
+        // summed in u128 as a few large prices overflow PriceInUSDT, their mean doesn't
+        let total = accepted
+            .into_iter()
+            .map(UniqueSaturatedInto::<u128>::unique_saturated_into)
+            .fold(0u128, |acc, x| acc.saturating_add(x));
+        Ok(T::PriceInUSDT::unique_saturated_from(total / l as u128))
+    }
+
     // fn parse_price(
     //     json_data: json::Value,
     //     json_path: &[StrBytes],
@@ -319,11 +1498,104 @@ impl<T: Trait> Module<T> {
     // }
 }
 
+impl<T: Trait + generic_asset::Trait> Module<T> {
+    /// the oracle token of an asset, none if the asset or its price is unknown
+    pub fn asset_token(asset_id: <T as generic_asset::Trait>::AssetId) -> Option<StrBytes> {
+        if !<generic_asset::Module<T>>::asset_id_exists(asset_id) {
+            return None;
+        }
+        let token = <generic_asset::Module<T>>::symbols(asset_id);
+        if !Self::is_token_known(&token) {
+            return None;
+        }
+        Some(token)
+    }
+
+    /// the oracle token of an asset with its price
+    pub fn asset_price(
+        asset_id: <T as generic_asset::Trait>::AssetId,
+    ) -> Option<(StrBytes, TokenPriceInfo<T>)> {
+        let token = Self::asset_token(asset_id)?;
+        let info = Self::price_info(&token)?;
+        Some((token, info))
+    }
+
+    /// a token's price as given to the lending pallets, with the token's own decimals
+    fn to_provided_price(price: T::PriceInUSDT) -> Option<u64> {
+        TryInto::<u64>::try_into(price)
+            .ok()
+            .filter(|price| *price > 0)
+    }
+}
+
+impl<T: Trait + generic_asset::Trait> PriceProvider<<T as generic_asset::Trait>::AssetId>
+    for Module<T>
+{
+    type BlockNumber = T::BlockNumber;
+
+    fn decimals(asset_id: <T as generic_asset::Trait>::AssetId) -> u32 {
+        let token = <generic_asset::Module<T>>::symbols(asset_id);
+        Self::decimals_of(&token)
+    }
+
+    fn price(asset_id: <T as generic_asset::Trait>::AssetId) -> Option<u64> {
+        let token = Self::asset_token(asset_id)?;
+        Self::to_provided_price(Self::current_price(&token))
+    }
+
+    fn twap(asset_id: <T as generic_asset::Trait>::AssetId, window: T::BlockNumber) -> Option<u64> {
+        let token = Self::asset_token(asset_id)?;
+        // tokens pinned at genesis have no history
+        let price = Self::twap(&token, window).unwrap_or_else(|| Self::current_price(&token));
+        Self::to_provided_price(price)
+    }
+
+    fn is_fresh(asset_id: <T as generic_asset::Trait>::AssetId, max_age: T::BlockNumber) -> bool {
+        let token = <generic_asset::Module<T>>::symbols(asset_id);
+        Self::price_if_fresh(&token, max_age).is_some()
+    }
+
+    fn is_held(asset_id: <T as generic_asset::Trait>::AssetId) -> bool {
+        let token = <generic_asset::Module<T>>::symbols(asset_id);
+        Self::is_price_pending(&token)
+    }
+}
+
 decl_event! {
     pub enum Event<T> where
-        BlockNumber = <T as system::Trait>::BlockNumber
+        AccountId = <T as system::Trait>::AccountId,
+        BlockNumber = <T as system::Trait>::BlockNumber,
+        PriceInUSDT = <T as Trait>::PriceInUSDT
     {
         FetchedPrice(BlockNumber, StrBytes, u64),
+        OperatorAdded(AccountId),
+        OperatorRemoved(AccountId),
+        PricesSubmitted(AccountId, BlockNumber),
+        PriceAggregated(StrBytes, PriceInUSDT),
+        /// not enough candidates within the allowed deviation, the previous price is kept
+        QuorumNotMet(StrBytes, u32),
+        /// the aggregated price moved too far from the current one and is held, (token, current, held)
+        PriceDeviationAlert(StrBytes, PriceInUSDT, PriceInUSDT),
+        /// a held price became the current price
+        PriceConfirmed(StrBytes, PriceInUSDT),
+        /// a held price was dropped as the feed came back in line with the current price
+        PriceAlertCleared(StrBytes),
+        /// (token, source)
+        SourceAdded(StrBytes, StrBytes),
+        SourceUpdated(StrBytes, StrBytes),
+        SourceRemoved(StrBytes, StrBytes),
+        /// a source was disabled by governance or for failing or deviating too often
+        SourceDisabled(StrBytes, StrBytes),
+        SourceEnabled(StrBytes, StrBytes),
+        /// the legacy sources of a token were moved to `CryptoPriceSources`
+        SourcesMigrated(StrBytes),
+        SyntheticTokenSet(StrBytes),
+        SyntheticTokenRemoved(StrBytes),
+        /// the formula of a synthetic token couldn't be evaluated, its previous price is kept
+        DerivationFailed(StrBytes),
+        /// (base, quote)
+        TradingPairAdded(StrBytes, StrBytes),
+        TradingPairRemoved(StrBytes, StrBytes),
     }
 }
 
@@ -333,13 +1605,31 @@ impl<T: Trait> support::unsigned::ValidateUnsigned for Module<T> {
 
     fn validate_unsigned(call: &Self::Call) -> TransactionValidity {
         match call {
-            Call::stack_price_unsigned(block, token, price) => Ok(ValidTransaction {
-                priority: 0,
-                requires: vec![],
-                provides: vec![(block, token, price).encode()],
-                longevity: 3,
-                propagate: true,
-            }),
+            Call::stack_prices_unsigned(payload, signature) => {
+                if !payload.public.verify(&payload.encode(), signature) {
+                    return InvalidTransaction::BadProof.into();
+                }
+                if let Err(e) = Self::check_reports(&payload.prices) {
+                    return InvalidTransaction::from(e).into();
+                }
+                let who = match Self::operator_of_key(&payload.public) {
+                    Some(who) => who,
+                    None => return InvalidTransaction::from(Error::<T>::NotOperator).into(),
+                };
+                let round = match Self::ensure_can_submit(&who, payload.block_number) {
+                    Ok(round) => round,
+                    Err(e) => return InvalidTransaction::from(e).into(),
+                };
+
+                Ok(ValidTransaction {
+                    priority: 0,
+                    requires: vec![],
+                    // one submission per operator per round
+                    provides: vec![(who, round).encode()],
+                    longevity: T::AggregateInterval::get().unique_saturated_into(),
+                    propagate: true,
+                })
+            }
             // Call::record_agg_pp(block, sym, price) => Ok(ValidTransaction {
             //     priority: 0,
             //     requires: vec![],
@@ -351,3 +1641,9 @@ impl<T: Trait> support::unsigned::ValidateUnsigned for Module<T> {
         }
     }
 }
+
+impl<T: Trait> From<Error<T>> for InvalidTransaction {
+    fn from(e: Error<T>) -> Self {
+        InvalidTransaction::Custom(e.as_u8())
+    }
+}
@@ -12,3 +12,195 @@
 
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Fake exchanges answering the offchain worker's HTTP requests.
+//!
+//! Replies are scripted by url, a url answers its replies in turn and repeats the last one.
+//! Everything but HTTP is left to `testing::TestOffchainExt`.
+
+#![cfg(test)]
+
+use parking_lot::RwLock;
+use sp_core::offchain::{
+    testing, Externalities, HttpError, HttpRequestId, HttpRequestStatus, OpaqueNetworkState,
+    StorageKind, Timestamp,
+};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::Arc,
+};
+
+/// what an exchange answers to a request
+#[derive(Clone, Debug)]
+pub enum Reply {
+    /// status 200 with a body
+    Ok(&'static str),
+    Status(u16),
+    /// the request never finishes
+    Timeout,
+}
+
+#[derive(Default)]
+pub struct ExchangesState {
+    replies: BTreeMap<String, VecDeque<Reply>>,
+    /// requests started, by id, with their reply and how much of its body was read
+    requests: BTreeMap<u16, (Reply, usize)>,
+    /// urls requested so far, in order
+    pub requested: Vec<String>,
+}
+
+impl ExchangesState {
+    /// script the replies of an url
+    pub fn reply(&mut self, url: &str, replies: Vec<Reply>) {
+        self.replies.insert(url.to_string(), replies.into());
+    }
+
+    fn next_reply(&mut self, url: &str) -> Reply {
+        let replies = self
+            .replies
+            .get_mut(url)
+            .unwrap_or_else(|| panic!("unexpected request to {}", url));
+        if replies.len() > 1 {
+            replies.pop_front().unwrap()
+        } else {
+            replies.front().cloned().unwrap_or(Reply::Timeout)
+        }
+    }
+}
+
+pub struct FakeExchanges {
+    inner: testing::TestOffchainExt,
+    state: Arc<RwLock<ExchangesState>>,
+}
+
+impl FakeExchanges {
+    pub fn new() -> (Self, Arc<RwLock<ExchangesState>>) {
+        let (inner, _) = testing::TestOffchainExt::new();
+        let state = Arc::new(RwLock::new(ExchangesState::default()));
+        let exchanges = FakeExchanges {
+            inner,
+            state: state.clone(),
+        };
+        (exchanges, state)
+    }
+}
+
+impl Externalities for FakeExchanges {
+    fn is_validator(&self) -> bool {
+        self.inner.is_validator()
+    }
+
+    fn network_state(&self) -> Result<OpaqueNetworkState, ()> {
+        self.inner.network_state()
+    }
+
+    fn timestamp(&mut self) -> Timestamp {
+        self.inner.timestamp()
+    }
+
+    fn sleep_until(&mut self, deadline: Timestamp) {
+        self.inner.sleep_until(deadline)
+    }
+
+    fn random_seed(&mut self) -> [u8; 32] {
+        self.inner.random_seed()
+    }
+
+    fn local_storage_set(&mut self, kind: StorageKind, key: &[u8], value: &[u8]) {
+        self.inner.local_storage_set(kind, key, value)
+    }
+
+    fn local_storage_compare_and_set(
+        &mut self,
+        kind: StorageKind,
+        key: &[u8],
+        old_value: Option<&[u8]>,
+        new_value: &[u8],
+    ) -> bool {
+        self.inner
+            .local_storage_compare_and_set(kind, key, old_value, new_value)
+    }
+
+    fn local_storage_get(&mut self, kind: StorageKind, key: &[u8]) -> Option<Vec<u8>> {
+        self.inner.local_storage_get(kind, key)
+    }
+
+    fn http_request_start(
+        &mut self,
+        method: &str,
+        uri: &str,
+        _meta: &[u8],
+    ) -> Result<HttpRequestId, ()> {
+        assert_eq!(method, "GET");
+        let mut state = self.state.write();
+        let reply = state.next_reply(uri);
+        let id = state.requested.len() as u16;
+        state.requested.push(uri.to_string());
+        state.requests.insert(id, (reply, 0));
+        Ok(HttpRequestId(id))
+    }
+
+    fn http_request_add_header(
+        &mut self,
+        _request_id: HttpRequestId,
+        _name: &str,
+        _value: &str,
+    ) -> Result<(), ()> {
+        Ok(())
+    }
+
+    fn http_request_write_body(
+        &mut self,
+        request_id: HttpRequestId,
+        _chunk: &[u8],
+        _deadline: Option<Timestamp>,
+    ) -> Result<(), HttpError> {
+        if self.state.read().requests.contains_key(&request_id.0) {
+            Ok(())
+        } else {
+            Err(HttpError::Invalid)
+        }
+    }
+
+    fn http_response_wait(
+        &mut self,
+        ids: &[HttpRequestId],
+        _deadline: Option<Timestamp>,
+    ) -> Vec<HttpRequestStatus> {
+        let state = self.state.read();
+        ids.iter()
+            .map(|id| match state.requests.get(&id.0) {
+                Some((Reply::Ok(_), _)) => HttpRequestStatus::Finished(200),
+                Some((Reply::Status(code), _)) => HttpRequestStatus::Finished(*code),
+                Some((Reply::Timeout, _)) => HttpRequestStatus::DeadlineReached,
+                None => HttpRequestStatus::Invalid,
+            })
+            .collect()
+    }
+
+    fn http_response_headers(&mut self, _request_id: HttpRequestId) -> Vec<(Vec<u8>, Vec<u8>)> {
+        vec![]
+    }
+
+    fn http_response_read_body(
+        &mut self,
+        request_id: HttpRequestId,
+        buffer: &mut [u8],
+        _deadline: Option<Timestamp>,
+    ) -> Result<usize, HttpError> {
+        let mut state = self.state.write();
+        let (reply, read) = state
+            .requests
+            .get_mut(&request_id.0)
+            .ok_or(HttpError::Invalid)?;
+        let body: &[u8] = match reply {
+            Reply::Ok(body) => body.as_bytes(),
+            Reply::Status(_) => b"",
+            Reply::Timeout => return Err(HttpError::DeadlineReached),
+        };
+        let n = buffer.len().min(body.len() - *read);
+        buffer[..n].copy_from_slice(&body[*read..*read + n]);
+        *read += n;
+        Ok(n)
+    }
+}
@@ -13,9 +13,13 @@
 // You should have received a copy of the GNU General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 #![cfg(test)]
-use crate::*;
+use crate::{
+    mock::{FakeExchanges, Reply},
+    *,
+};
 
 use codec::Decode;
+use parking_lot::RwLock;
 use sp_core::{
     offchain::{testing, OffchainExt, TransactionPoolExt},
     testing::KeyStore,
@@ -27,8 +31,11 @@ use sp_runtime::{
     traits::{BlakeTwo256, Extrinsic as ExtrinsicsT, IdentityLookup},
     Perbill, RuntimeAppPublic,
 };
+use std::sync::Arc;
 use support::{
-    assert_ok, impl_outer_origin, parameter_types,
+    assert_noop, assert_ok, impl_outer_origin, parameter_types,
+    traits::{OnFinalize, OnInitialize},
+    unsigned::ValidateUnsigned,
     weights::{GetDispatchInfo, Weight},
 };
 
@@ -100,6 +107,7 @@ impl system::offchain::CreateTransaction<Test, Extrinsic> for Test {
 
 parameter_types! {
     pub const AggregateInterval: u64 = 5;
+    pub const FetchInterval: u64 = 1;
 }
 
 impl Trait for Test {
@@ -109,6 +117,7 @@ impl Trait for Test {
     type SubmitUnsignedTransaction = SubmitTransaction;
     type PriceInUSDT = u64;
     type AggregateInterval = AggregateInterval;
+    type FetchInterval = FetchInterval;
 }
 
 type NewOracle = Module<Test>;
@@ -125,12 +134,449 @@ fn should_make_http_call_and_parse_result() {
         let url = b"https://min-api.cryptocompare.com/data/price?fsym=BTC&tsyms=USD".to_vec();
         let price = NewOracle::fetch_json(&url).unwrap();
         assert_eq!(
-            NewOracle::parse_price(price, &vec![b"USD".to_vec()]).ok(),
+            NewOracle::parse_price(price, &[JsonPathSegment::Key(b"USD".to_vec())], false, 4).ok(),
             Some(1552300)
         );
     });
 }
 
+fn expect_price_request(
+    state: &mut testing::OffchainState,
+    id: u16,
+    sources: &[(StrBytes, &str, &[u8])],
+    token: &StrBytes,
+) {
+    let (_, url, body) = sources.iter().find(|(t, _, _)| t == token).unwrap();
+    state.expect_request(
+        id,
+        testing::PendingRequest {
+            method: "GET".into(),
+            uri: (*url).into(),
+            response: Some(body.to_vec()),
+            sent: true,
+            ..Default::default()
+        },
+    );
+}
+
+fn submitted_prices(pool_state: &testing::PoolState, block_number: u64) -> SourceReports<u64> {
+    let tx = pool_state.transactions.last().unwrap();
+    let tx = Extrinsic::decode(&mut &**tx).unwrap();
+    assert_eq!(tx.signature, None);
+    match tx.call {
+        Call::stack_prices_unsigned(payload, signature) => {
+            assert!(payload.public.verify(&payload.encode(), &signature));
+            assert_eq!(payload.block_number, block_number);
+            let mut prices = payload.prices;
+            prices.sort();
+            prices
+        }
+        _ => panic!("unexpected call"),
+    }
+}
+
+/// each price comes from another operator
+fn candidates(source: &str, prices: &[u64]) -> Vec<(sp_core::sr25519::Public, StrBytes, u64)> {
+    prices
+        .iter()
+        .enumerate()
+        .map(|(i, p)| {
+            let who = sp_core::sr25519::Public::from_raw([i as u8 + 100; 32]);
+            (who, source.as_bytes().to_vec(), *p)
+        })
+        .collect()
+}
+
+#[test]
+fn should_batch_every_token_and_back_off_on_failure() {
+    let (offchain, offchain_state) = testing::TestOffchainExt::new();
+    let (pool, pool_state) = testing::TestTransactionPoolExt::new();
+    let mut t = sp_io::TestExternalities::default();
+    t.register_extension(OffchainExt::new(offchain));
+    t.register_extension(TransactionPoolExt::new(pool));
+    t.register_extension(KeystoreExt(KeyStore::new()));
+
+    let sources: Vec<(StrBytes, &str, &[u8])> = vec![
+        (
+            b"BTC".to_vec(),
+            "https://min-api.cryptocompare.com/data/price?fsym=BTC&tsyms=USD",
+            br#"{"USD": 155.23}"#,
+        ),
+        (
+            b"ETH".to_vec(),
+            "https://min-api.cryptocompare.com/data/price?fsym=ETH&tsyms=USD",
+            br#"{"USD": 2.5}"#,
+        ),
+        (
+            b"DOT".to_vec(),
+            "https://min-api.cryptocompare.com/data/price?fsym=DOT&tsyms=USD",
+            b"502 Bad Gateway",
+        ),
+    ];
+
+    t.execute_with(|| {
+        let operator = sp_core::sr25519::Public::from_raw([1u8; 32]);
+        let key = crypto::Public::generate_pair(None);
+        assert_ok!(NewOracle::add_operator(
+            system::RawOrigin::Root.into(),
+            operator,
+            key
+        ));
+
+        for (token, url, _) in &sources {
+            CryptoPriceSources::insert(
+                token,
+                vec![PriceSource {
+                    name: b"cryptocompare".to_vec(),
+                    url: url.as_bytes().to_vec(),
+                    json_path: vec![JsonPathSegment::Key(b"USD".to_vec())],
+                    inverted: false,
+                }],
+            );
+        }
+        // tokens are fetched in storage iteration order
+        let tokens: Vec<StrBytes> = CryptoPriceSources::iter().map(|(k, _)| k).collect();
+
+        // block 1: every token is due, DOT fails
+        for (id, token) in tokens.iter().enumerate() {
+            expect_price_request(&mut offchain_state.write(), id as u16, &sources, token);
+        }
+        NewOracle::fetch_prices_and_submit_unsigned(1);
+        assert_eq!(pool_state.read().transactions.len(), 1);
+        assert_eq!(
+            submitted_prices(&pool_state.read(), 1),
+            vec![
+                (b"BTC".to_vec(), b"cryptocompare".to_vec(), Some(1552300)),
+                (b"DOT".to_vec(), b"cryptocompare".to_vec(), None),
+                (b"ETH".to_vec(), b"cryptocompare".to_vec(), Some(25000)),
+            ]
+        );
+        assert_eq!(NewOracle::fetch_schedule(&b"BTC".to_vec()), (2, 0));
+        assert_eq!(NewOracle::fetch_schedule(&b"DOT".to_vec()), (3, 1));
+
+        // block 2: DOT is backing off and is not requested
+        let mut id = tokens.len() as u16;
+        for token in tokens.iter().filter(|t| t.as_slice() != b"DOT") {
+            expect_price_request(&mut offchain_state.write(), id, &sources, token);
+            id += 1;
+        }
+        NewOracle::fetch_prices_and_submit_unsigned(2);
+        assert_eq!(pool_state.read().transactions.len(), 2);
+        assert_eq!(
+            submitted_prices(&pool_state.read(), 2),
+            vec![
+                (b"BTC".to_vec(), b"cryptocompare".to_vec(), Some(1552300)),
+                (b"ETH".to_vec(), b"cryptocompare".to_vec(), Some(25000)),
+            ]
+        );
+        assert_eq!(NewOracle::fetch_schedule(&b"DOT".to_vec()), (3, 1));
+
+        // block 3: DOT fails again and waits twice as long
+        for token in tokens.iter() {
+            expect_price_request(&mut offchain_state.write(), id, &sources, token);
+            id += 1;
+        }
+        NewOracle::fetch_prices_and_submit_unsigned(3);
+        assert_eq!(pool_state.read().transactions.len(), 3);
+        assert_eq!(NewOracle::fetch_schedule(&b"DOT".to_vec()), (7, 2));
+    });
+}
+
+#[test]
+fn operator_submissions_work() {
+    let mut t = sp_io::TestExternalities::default();
+    t.register_extension(KeystoreExt(KeyStore::new()));
+
+    t.execute_with(|| {
+        let alice = sp_core::sr25519::Public::from_raw([1u8; 32]);
+        let bob = sp_core::sr25519::Public::from_raw([2u8; 32]);
+        let key = crypto::Public::generate_pair(None);
+        let prices = vec![(b"BTC".to_vec(), b"cryptocompare".to_vec(), Some(1552300))];
+        system::Module::<Test>::set_block_number(1);
+        assert_ok!(NewOracle::add_source(
+            system::RawOrigin::Root.into(),
+            b"BTC".to_vec(),
+            b"cryptocompare".to_vec(),
+            b"https://min-api.cryptocompare.com/data/price?fsym=BTC&tsyms=USD".to_vec(),
+            b"$.USD".to_vec(),
+            false
+        ));
+
+        assert_noop!(
+            NewOracle::stack_prices_signed(Origin::signed(alice), 1, prices.clone()),
+            Error::<Test>::NotOperator
+        );
+        assert_ok!(NewOracle::add_operator(
+            system::RawOrigin::Root.into(),
+            alice,
+            key.clone()
+        ));
+        assert_noop!(
+            NewOracle::add_operator(system::RawOrigin::Root.into(), bob, key.clone()),
+            Error::<Test>::OperatorKeyInUse
+        );
+
+        // signed submission, once per round
+        assert_noop!(
+            NewOracle::stack_prices_signed(Origin::signed(alice), 1, vec![]),
+            Error::<Test>::EmptySubmission
+        );
+        assert_noop!(
+            NewOracle::stack_prices_signed(
+                Origin::signed(alice),
+                1,
+                vec![prices[0].clone(), prices[0].clone()]
+            ),
+            Error::<Test>::DuplicateReport
+        );
+        assert_noop!(
+            NewOracle::stack_prices_signed(
+                Origin::signed(alice),
+                1,
+                vec![prices[0].clone(); MAX_SUBMITTED_REPORTS + 1]
+            ),
+            Error::<Test>::TooManyReports
+        );
+        assert_ok!(NewOracle::stack_prices_signed(
+            Origin::signed(alice),
+            1,
+            prices.clone()
+        ));
+        assert_eq!(
+            NewOracle::price_candidates(b"BTC".to_vec()),
+            vec![(alice, b"cryptocompare".to_vec(), 1552300)]
+        );
+        assert_noop!(
+            NewOracle::stack_prices_signed(Origin::signed(alice), 1, prices.clone()),
+            Error::<Test>::AlreadySubmitted
+        );
+
+        // unsigned submission with a signed payload in the next round
+        system::Module::<Test>::set_block_number(12);
+        assert_noop!(
+            NewOracle::stack_prices_signed(Origin::signed(alice), 1, prices.clone()),
+            Error::<Test>::StaleSubmission
+        );
+        let payload = PricePayload {
+            public: key.clone(),
+            block_number: 12,
+            prices: prices.clone(),
+        };
+        let signature = key.sign(&payload.encode()).unwrap();
+        let forged = PricePayload {
+            prices: vec![(b"BTC".to_vec(), b"cryptocompare".to_vec(), Some(1))],
+            ..payload.clone()
+        };
+        let duplicated = PricePayload {
+            prices: vec![prices[0].clone(), prices[0].clone()],
+            ..payload.clone()
+        };
+        let duplicated_signature = key.sign(&duplicated.encode()).unwrap();
+        let duplicate_report: TransactionValidity =
+            InvalidTransaction::from(Error::<Test>::DuplicateReport).into();
+        assert_eq!(
+            NewOracle::validate_unsigned(&Call::stack_prices_unsigned(
+                duplicated,
+                duplicated_signature
+            )),
+            duplicate_report
+        );
+        let bad_proof: TransactionValidity = InvalidTransaction::BadProof.into();
+        assert_eq!(
+            NewOracle::validate_unsigned(&Call::stack_prices_unsigned(forged, signature.clone())),
+            bad_proof
+        );
+        assert!(NewOracle::validate_unsigned(&Call::stack_prices_unsigned(
+            payload.clone(),
+            signature.clone()
+        ))
+        .is_ok());
+        assert_ok!(NewOracle::stack_prices_unsigned(
+            system::RawOrigin::None.into(),
+            payload.clone(),
+            signature.clone()
+        ));
+        assert_eq!(
+            NewOracle::price_candidates(b"BTC".to_vec()),
+            vec![
+                (alice, b"cryptocompare".to_vec(), 1552300),
+                (alice, b"cryptocompare".to_vec(), 1552300)
+            ]
+        );
+        assert_eq!(NewOracle::last_submitted_round(alice), Some(2));
+
+        // removed operators are rejected
+        system::Module::<Test>::set_block_number(15);
+        assert_ok!(NewOracle::remove_operator(
+            system::RawOrigin::Root.into(),
+            alice
+        ));
+        let payload = PricePayload {
+            block_number: 15,
+            ..payload
+        };
+        let signature = key.sign(&payload.encode()).unwrap();
+        let not_operator: TransactionValidity =
+            InvalidTransaction::from(Error::<Test>::NotOperator).into();
+        assert_eq!(
+            NewOracle::validate_unsigned(&Call::stack_prices_unsigned(payload, signature)),
+            not_operator
+        );
+    });
+}
+
+#[test]
+fn aggregation_rejects_outliers_and_needs_quorum() {
+    let mut t = sp_io::TestExternalities::default();
+
+    t.execute_with(|| {
+        MaxDeviation::put(10);
+        MinQuorum::put(3);
+        <PriceCandidates<Test>>::insert(b"BTC".to_vec(), candidates("test", &[100, 102, 98, 1000]));
+        <PriceCandidates<Test>>::insert(b"ETH".to_vec(), candidates("test", &[10, 11, 500]));
+        <CurrentPrice<Test>>::insert(b"ETH".to_vec(), 9);
+        // a single operator reporting many sources is one price
+        let alice = sp_core::sr25519::Public::from_raw([1u8; 32]);
+        <PriceCandidates<Test>>::insert(
+            b"DOT".to_vec(),
+            vec![
+                (alice, b"a".to_vec(), 50),
+                (alice, b"b".to_vec(), 51),
+                (alice, b"c".to_vec(), 52),
+            ],
+        );
+
+        NewOracle::_on_finalize_(5);
+
+        // median is 101, 1000 is dropped
+        assert_eq!(NewOracle::current_price(b"BTC".to_vec()), 100);
+        // only 10 and 11 are left, the previous price is kept
+        assert_eq!(NewOracle::current_price(b"ETH".to_vec()), 9);
+        assert!(!<PriceCandidates<Test>>::contains_key(b"ETH".to_vec()));
+        assert!(!NewOracle::is_token_known(&b"DOT".to_vec()));
+        assert_eq!(NewOracle::next_aggregate_at(), 10);
+
+        assert_eq!(NewOracle::aggregate_price(vec![]), Err(0));
+        // the sum of these overflows a u64
+        let high = u64::max_value() - 2;
+        assert_eq!(
+            NewOracle::aggregate_price(vec![high, high - 2, high - 4]),
+            Ok(high - 2)
+        );
+    });
+}
+
+#[test]
+fn price_if_fresh_works() {
+    let mut t = sp_io::TestExternalities::default();
+
+    t.execute_with(|| {
+        system::Module::<Test>::set_block_number(5);
+        <PriceCandidates<Test>>::insert(b"BTC".to_vec(), candidates("test", &[100]));
+        CryptoPriceSources::insert(
+            b"BTC".to_vec(),
+            vec![PriceSource {
+                name: b"cryptocompare".to_vec(),
+                url: b"https://min-api.cryptocompare.com/data/price?fsym=BTC&tsyms=USD".to_vec(),
+                json_path: vec![JsonPathSegment::Key(b"USD".to_vec())],
+                inverted: false,
+            }],
+        );
+        <CurrentPrice<Test>>::insert(b"DUSD".to_vec(), PRICE_SCALE);
+        assert_eq!(NewOracle::price_if_fresh(&b"BTC".to_vec(), 10), None);
+
+        NewOracle::_on_finalize_(5);
+        assert_eq!(NewOracle::price_updated_at(b"BTC".to_vec()), Some((5, 0)));
+        assert_eq!(NewOracle::price_if_fresh(&b"BTC".to_vec(), 10), Some(100));
+
+        system::Module::<Test>::set_block_number(16);
+        assert_eq!(NewOracle::price_if_fresh(&b"BTC".to_vec(), 10), None);
+        // a price without sources never goes stale
+        assert_eq!(
+            NewOracle::price_if_fresh(&b"DUSD".to_vec(), 10),
+            Some(PRICE_SCALE)
+        );
+        assert_eq!(NewOracle::price_if_fresh(&b"ETH".to_vec(), 10), None);
+    });
+}
+
+#[test]
+fn twap_works() {
+    let mut t = sp_io::TestExternalities::default();
+
+    t.execute_with(|| {
+        let btc = b"BTC".to_vec();
+        HistoryDepth::put(3);
+        for (bn, price) in vec![(10, 100), (20, 200), (30, 300), (40, 400)] {
+            NewOracle::record_history(&btc, bn, 0, price);
+        }
+        assert_eq!(
+            NewOracle::price_history(&btc),
+            vec![(20, 0, 200), (30, 0, 300), (40, 0, 400)]
+        );
+
+        system::Module::<Test>::set_block_number(50);
+        assert_eq!(NewOracle::twap(&btc, 20), Some(350));
+        assert_eq!(NewOracle::twap(&btc, 30), Some(300));
+        // the window can't reach further back than the history
+        assert_eq!(NewOracle::twap(&btc, 100), Some(300));
+
+        system::Module::<Test>::set_block_number(40);
+        assert_eq!(NewOracle::twap(&btc, 0), Some(400));
+        assert_eq!(NewOracle::twap(&b"ETH".to_vec(), 20), None);
+    });
+}
+
+#[test]
+fn circuit_breaker_works() {
+    let mut t = sp_io::TestExternalities::default();
+
+    t.execute_with(|| {
+        let btc = b"BTC".to_vec();
+        CircuitBreakerThreshold::put(20);
+        StableRounds::put(2);
+        <CurrentPrice<Test>>::insert(&btc, 100);
+
+        let aggregate = |bn: u64, price: u64| {
+            <PriceCandidates<Test>>::insert(&btc, candidates("test", &[price]));
+            NewOracle::_on_finalize_(bn);
+        };
+
+        // a jump is held, the current price stays
+        aggregate(5, 150);
+        assert_eq!(NewOracle::pending_price(&btc), Some((150, 0)));
+        assert_eq!(NewOracle::current_price(&btc), 100);
+        assert!(NewOracle::is_price_pending(&btc));
+
+        // the feed comes back in line
+        aggregate(10, 101);
+        assert!(!NewOracle::is_price_pending(&btc));
+        assert_eq!(NewOracle::current_price(&btc), 101);
+
+        // the jump stays stable for 2 more rounds
+        aggregate(15, 150);
+        aggregate(20, 155);
+        assert_eq!(NewOracle::pending_price(&btc), Some((155, 1)));
+        assert_eq!(NewOracle::current_price(&btc), 101);
+        aggregate(25, 160);
+        assert!(!NewOracle::is_price_pending(&btc));
+        assert_eq!(NewOracle::current_price(&btc), 160);
+
+        // governance confirms a held price
+        aggregate(30, 400);
+        assert_eq!(NewOracle::current_price(&btc), 160);
+        assert_ok!(NewOracle::confirm_price(
+            system::RawOrigin::Root.into(),
+            btc.clone()
+        ));
+        assert_eq!(NewOracle::current_price(&btc), 400);
+        assert_noop!(
+            NewOracle::confirm_price(system::RawOrigin::Root.into(), btc.clone()),
+            Error::<Test>::NoPendingPrice
+        );
+    });
+}
+
 // #[test]
 // fn should_submit_signed_transaction_on_chain() {
 //     const PHRASE: &str =
@@ -177,7 +623,7 @@ fn should_make_http_call_and_parse_result() {
 //     price_oracle_response(&mut offchain_state.write());
 
 //     t.execute_with(|| {
-//         NewOracle::fetch_price_and_submit_unsigned(1);
+//         NewOracle::fetch_prices_and_submit_unsigned(1);
 
 //         let tx = pool_state.write().transactions.pop().unwrap();
 //         assert!(pool_state.read().transactions.is_empty());
@@ -214,6 +660,7 @@ fn price_oracle_response(state: &mut testing::OffchainState) {
 
 #[test]
 fn parse_price_works() {
+    let usd = vec![JsonPathSegment::Key(b"USD".to_vec())];
     let test_data = vec![
         ("{\"USD\":6536.92}", Some(65369200)),
         ("{\"USD\":65.92}", Some(659200)),
@@ -221,13 +668,768 @@ fn parse_price_works() {
         ("{\"USD\":6536}", Some(65360000)),
         ("{\"USD2\":6536}", None),
         ("{\"USD\":\"6432\"}", Some(64320000)),
+        ("{\"USD\":1.5e3}", Some(15000000)),
+        ("{\"USD\":\"0.00004\"}", None), // rounds to zero
+        ("{\"USD\":-1}", None),
     ];
-    let json_parse_path = vec![b"USD".to_vec()];
     for (json, expected) in test_data {
         let json_value = simple_json::parse_json(json).unwrap();
         assert_eq!(
             expected,
-            NewOracle::parse_price(json_value, &json_parse_path).ok()
+            NewOracle::parse_price(json_value, &usd, false, 4).ok()
+        );
+    }
+
+    // small caps keep their precision with more decimals
+    let json_value = simple_json::parse_json("{\"USD\":\"0.000012345678\"}").unwrap();
+    assert_eq!(
+        NewOracle::parse_price(json_value, &usd, false, 12).ok(),
+        Some(12345678)
+    );
+
+    // array indices
+    let json_value =
+        simple_json::parse_json("{\"data\":[{\"price\":\"9321.5\"},{\"price\":\"1\"}]}").unwrap();
+    let path = NewOracle::parse_json_path(b"$.data[0].price").unwrap();
+    assert_eq!(
+        NewOracle::parse_price(json_value, &path, false, 4).ok(),
+        Some(93215000)
+    );
+
+    // BTC per USDT
+    let json_value = simple_json::parse_json("{\"BTC\":0.0001}").unwrap();
+    let path = vec![JsonPathSegment::Key(b"BTC".to_vec())];
+    assert_eq!(
+        NewOracle::parse_price(json_value, &path, true, 4).ok(),
+        Some(100000000)
+    );
+}
+
+#[test]
+fn json_path_works() {
+    use JsonPathSegment::*;
+
+    assert_eq!(NewOracle::parse_json_path(b""), Some(vec![]));
+    assert_eq!(NewOracle::parse_json_path(b"$"), Some(vec![]));
+    assert_eq!(
+        NewOracle::parse_json_path(b"USD"),
+        Some(vec![Key(b"USD".to_vec())])
+    );
+    assert_eq!(
+        NewOracle::parse_json_path(b"data.priceUsd"),
+        Some(vec![Key(b"data".to_vec()), Key(b"priceUsd".to_vec())])
+    );
+    assert_eq!(
+        NewOracle::parse_json_path(b"$.data[0].price"),
+        Some(vec![
+            Key(b"data".to_vec()),
+            Index(0),
+            Key(b"price".to_vec())
+        ])
+    );
+    assert_eq!(
+        NewOracle::parse_json_path(b"$['RAW'][\"BTC\"][12]"),
+        Some(vec![Key(b"RAW".to_vec()), Key(b"BTC".to_vec()), Index(12)])
+    );
+    assert_eq!(NewOracle::parse_json_path(b"$..price"), None);
+    assert_eq!(NewOracle::parse_json_path(b"$.data[*]"), None);
+    assert_eq!(NewOracle::parse_json_path(b"$.data['x]"), None);
+    assert_eq!(NewOracle::parse_json_path(b"$data"), None);
+}
+
+#[test]
+fn token_decimals_work() {
+    let mut t = sp_io::TestExternalities::default();
+
+    t.execute_with(|| {
+        <CurrentPrice<Test>>::insert(b"SHIB".to_vec(), 1234);
+        <PriceHistory<Test>>::insert(b"SHIB".to_vec(), vec![(1, 0, 1234)]);
+        <PriceCandidates<Test>>::insert(b"SHIB".to_vec(), candidates("test", &[1234]));
+
+        assert_noop!(
+            NewOracle::set_token_decimals(system::RawOrigin::Root.into(), b"SHIB".to_vec(), 19),
+            Error::<Test>::TooManyDecimals
+        );
+        assert_ok!(NewOracle::set_token_decimals(
+            system::RawOrigin::Root.into(),
+            b"SHIB".to_vec(),
+            8
+        ));
+        assert_eq!(NewOracle::current_price(b"SHIB".to_vec()), 12340000);
+        assert_eq!(
+            NewOracle::price_history(b"SHIB".to_vec()),
+            vec![(1, 0, 12340000)]
+        );
+        assert!(!<PriceCandidates<Test>>::contains_key(b"SHIB".to_vec()));
+
+        assert_ok!(NewOracle::set_token_decimals(
+            system::RawOrigin::Root.into(),
+            b"SHIB".to_vec(),
+            2
+        ));
+        assert_eq!(NewOracle::current_price(b"SHIB".to_vec()), 12);
+        assert_eq!(NewOracle::decimals_of(&b"SHIB".to_vec()), 2);
+
+        // a BTC sized price doesn't fit a u64 with 18 decimals, nothing is rescaled
+        <CurrentPrice<Test>>::insert(b"BTC".to_vec(), 10000_0000);
+        <PriceHistory<Test>>::insert(b"BTC".to_vec(), vec![(1, 0, 10000_0000)]);
+        <PendingPrice<Test>>::insert(b"BTC".to_vec(), (12000_0000, 1));
+        assert_noop!(
+            NewOracle::set_token_decimals(system::RawOrigin::Root.into(), b"BTC".to_vec(), 18),
+            Error::<Test>::PriceOverflow
+        );
+        assert_eq!(NewOracle::decimals_of(&b"BTC".to_vec()), 4);
+        assert_eq!(NewOracle::rescale(10000_0000, 4, 18), None);
+        assert_eq!(
+            NewOracle::rescale(10000_0000, 4, 12),
+            Some(10000_00000000_0000)
+        );
+    });
+}
+
+#[test]
+fn sources_migration_works() {
+    let mut t = sp_io::TestExternalities::default();
+
+    t.execute_with(|| {
+        let legacy: Vec<LegacyPriceSource> = vec![(
+            b"coincap".to_vec(),
+            b"https://api.coincap.io/v2/assets/bitcoin".to_vec(),
+            vec![b"data".to_vec(), b"priceUsd".to_vec()],
+        )];
+        // where the sources were kept while `CryptoPriceSources` was keyed with `opaque_blake2_256`
+        let legacy_key = |token: &[u8]| {
+            let mut key = sp_io::hashing::twox_128(b"NewOracle").to_vec();
+            key.extend_from_slice(&sp_io::hashing::twox_128(b"CryptoPriceSources"));
+            key.extend_from_slice(&sp_io::hashing::blake2_256(&token.to_vec().encode()));
+            key
+        };
+        for token in &[&b"BTC"[..], b"ETH", b"DOT"] {
+            sp_io::storage::set(&legacy_key(token), &legacy.encode());
+        }
+        sp_io::storage::set(&legacy_key(b"XRP"), b"garbage");
+        <CurrentPrice<Test>>::insert(b"BTC".to_vec(), 10000);
+        <CurrentPrice<Test>>::insert(b"XRP".to_vec(), 10000);
+        // never priced, only its decimals were set
+        TokenDecimals::insert(b"ETH".to_vec(), 8);
+
+        NewOracle::on_initialize(1);
+        assert_eq!(NewOracle::sources_version(), SOURCES_VERSION);
+        let migrated = vec![PriceSource {
+            name: b"coincap".to_vec(),
+            url: b"https://api.coincap.io/v2/assets/bitcoin".to_vec(),
+            json_path: NewOracle::parse_json_path(b"$.data.priceUsd").unwrap(),
+            inverted: false,
+        }];
+        for token in &[&b"BTC"[..], b"ETH"] {
+            assert_eq!(sp_io::storage::get(&legacy_key(token)), None);
+            assert_eq!(NewOracle::crypto_price_sources(token.to_vec()), migrated);
+        }
+        // what doesn't decode is kept rather than dropped
+        assert_eq!(
+            sp_io::storage::get(&legacy_key(b"XRP")),
+            Some(b"garbage".to_vec())
+        );
+        assert!(!CryptoPriceSources::contains_key(b"XRP".to_vec()));
+
+        // DOT is known nowhere else, governance has to name it
+        assert_eq!(NewOracle::legacy_sources_left(), 2);
+        assert!(!CryptoPriceSources::contains_key(b"DOT".to_vec()));
+        assert_noop!(
+            NewOracle::migrate_legacy_sources(
+                system::RawOrigin::Root.into(),
+                vec![b"BTC".to_vec()]
+            ),
+            Error::<Test>::SourceNotFound
+        );
+        assert_ok!(NewOracle::migrate_legacy_sources(
+            system::RawOrigin::Root.into(),
+            vec![b"DOT".to_vec()]
+        ));
+        assert_eq!(sp_io::storage::get(&legacy_key(b"DOT")), None);
+        assert_eq!(NewOracle::crypto_price_sources(b"DOT".to_vec()), migrated);
+        assert_eq!(NewOracle::legacy_sources_left(), 1);
+    });
+}
+
+#[test]
+fn source_management_works() {
+    let mut t = sp_io::TestExternalities::default();
+
+    t.execute_with(|| {
+        let root = || -> Origin { system::RawOrigin::Root.into() };
+        let btc = b"BTC".to_vec();
+        let coincap = b"coincap".to_vec();
+        let url = b"https://api.coincap.io/v2/assets/bitcoin".to_vec();
+
+        assert_noop!(
+            NewOracle::add_source(
+                root(),
+                btc.clone(),
+                coincap.clone(),
+                url.clone(),
+                b"$..priceUsd".to_vec(),
+                false
+            ),
+            Error::<Test>::InvalidJsonPath
+        );
+        assert_ok!(NewOracle::add_source(
+            root(),
+            btc.clone(),
+            coincap.clone(),
+            url.clone(),
+            b"$.data.priceUsd".to_vec(),
+            false
+        ));
+        assert_noop!(
+            NewOracle::add_source(
+                root(),
+                btc.clone(),
+                coincap.clone(),
+                url.clone(),
+                b"$.data.priceUsd".to_vec(),
+                false
+            ),
+            Error::<Test>::SourceExists
+        );
+
+        SourceStats::insert(
+            &btc,
+            &coincap,
+            SourceHealth {
+                successes: 3,
+                failures: 1,
+                deviations: 0,
+                disabled: false,
+            },
+        );
+        assert_ok!(NewOracle::update_source(
+            root(),
+            btc.clone(),
+            coincap.clone(),
+            url.clone(),
+            b"$.data[0].priceUsd".to_vec(),
+            false
+        ));
+        assert_eq!(
+            NewOracle::crypto_price_sources(&btc)[0].json_path,
+            NewOracle::parse_json_path(b"$.data[0].priceUsd").unwrap()
+        );
+        assert_eq!(
+            NewOracle::source_stats(&btc, &coincap),
+            SourceHealth::default()
+        );
+
+        assert_ok!(NewOracle::disable_source(
+            root(),
+            btc.clone(),
+            coincap.clone()
+        ));
+        assert!(NewOracle::source_stats(&btc, &coincap).disabled);
+        assert_ok!(NewOracle::enable_source(
+            root(),
+            btc.clone(),
+            coincap.clone()
+        ));
+        assert!(!NewOracle::source_stats(&btc, &coincap).disabled);
+
+        assert_ok!(NewOracle::remove_source(
+            root(),
+            btc.clone(),
+            coincap.clone()
+        ));
+        assert!(!CryptoPriceSources::contains_key(&btc));
+        assert_noop!(
+            NewOracle::remove_source(root(), btc.clone(), coincap.clone()),
+            Error::<Test>::SourceNotFound
+        );
+    });
+}
+
+#[test]
+fn unhealthy_sources_are_disabled() {
+    let mut t = sp_io::TestExternalities::default();
+
+    t.execute_with(|| {
+        let alice = sp_core::sr25519::Public::from_raw([1u8; 32]);
+        let bob = sp_core::sr25519::Public::from_raw([2u8; 32]);
+        let btc = b"BTC".to_vec();
+        let (good, good2) = (b"good".to_vec(), b"good2".to_vec());
+        let (flaky, off) = (b"flaky".to_vec(), b"off".to_vec());
+        for name in &[&good, &good2, &flaky, &off] {
+            assert_ok!(NewOracle::add_source(
+                system::RawOrigin::Root.into(),
+                btc.clone(),
+                name.to_vec(),
+                b"https://example.com".to_vec(),
+                b"$.USD".to_vec(),
+                false
+            ));
+        }
+        for (i, who) in [alice, bob].iter().enumerate() {
+            assert_ok!(NewOracle::add_operator(
+                system::RawOrigin::Root.into(),
+                *who,
+                crypto::Public::from_raw([i as u8 + 9; 32])
+            ));
+        }
+        MaxDeviation::put(10);
+        MinQuorum::put(1);
+        assert_ok!(NewOracle::set_source_limits(
+            system::RawOrigin::Root.into(),
+            50,
+            50,
+            2,
+            2
+        ));
+
+        let round = |bn: u64, operators: &[sp_core::sr25519::Public]| {
+            system::Module::<Test>::set_block_number(bn);
+            for who in operators {
+                assert_ok!(NewOracle::stack_prices_signed(
+                    Origin::signed(*who),
+                    bn,
+                    vec![
+                        (btc.clone(), good.clone(), Some(100)),
+                        (btc.clone(), good2.clone(), Some(101)),
+                        (btc.clone(), flaky.clone(), None),
+                        (btc.clone(), off.clone(), Some(200)),
+                    ]
+                ));
+            }
+            NewOracle::_on_finalize_(bn);
+        };
+
+        // failures reported by a single operator don't count
+        round(5, &[alice]);
+        assert_eq!(
+            NewOracle::source_stats(&btc, &flaky),
+            SourceHealth::default()
+        );
+        assert_eq!(
+            NewOracle::source_stats(&btc, &off),
+            SourceHealth {
+                successes: 1,
+                failures: 0,
+                deviations: 1,
+                disabled: false
+            }
+        );
+
+        // a single failure is not judged yet
+        round(10, &[alice, bob]);
+        assert_eq!(
+            NewOracle::source_stats(&btc, &flaky),
+            SourceHealth {
+                successes: 0,
+                failures: 1,
+                deviations: 0,
+                disabled: false
+            }
+        );
+        assert!(NewOracle::source_stats(&btc, &off).disabled);
+
+        round(15, &[alice, bob]);
+        assert!(NewOracle::source_stats(&btc, &flaky).disabled);
+        assert_eq!(
+            NewOracle::source_stats(&btc, &good),
+            SourceHealth {
+                successes: 3,
+                failures: 0,
+                deviations: 0,
+                disabled: false
+            }
+        );
+
+        // reports of disabled sources are ignored
+        round(20, &[alice, bob]);
+        assert_eq!(NewOracle::source_stats(&btc, &flaky).failures, 2);
+        assert_eq!(NewOracle::current_price(&btc), 100);
+    });
+}
+
+#[test]
+fn dead_source_is_disabled_with_one_operator() {
+    let mut t = sp_io::TestExternalities::default();
+
+    t.execute_with(|| {
+        let alice = sp_core::sr25519::Public::from_raw([1u8; 32]);
+        let btc = b"BTC".to_vec();
+        let (good, dead) = (b"good".to_vec(), b"dead".to_vec());
+        for name in &[&good, &dead] {
+            assert_ok!(NewOracle::add_source(
+                system::RawOrigin::Root.into(),
+                btc.clone(),
+                name.to_vec(),
+                b"https://example.com".to_vec(),
+                b"$.USD".to_vec(),
+                false
+            ));
+        }
+        assert_ok!(NewOracle::add_operator(
+            system::RawOrigin::Root.into(),
+            alice,
+            crypto::Public::from_raw([9; 32])
+        ));
+        MinQuorum::put(1);
+        // two reporters asked for, but the only operator is enough
+        assert_ok!(NewOracle::set_source_limits(
+            system::RawOrigin::Root.into(),
+            50,
+            50,
+            2,
+            2
+        ));
+
+        for bn in &[5, 10] {
+            system::Module::<Test>::set_block_number(*bn);
+            assert_ok!(NewOracle::stack_prices_signed(
+                Origin::signed(alice),
+                *bn,
+                vec![
+                    (btc.clone(), good.clone(), Some(100)),
+                    (btc.clone(), dead.clone(), None),
+                ]
+            ));
+            NewOracle::_on_finalize_(*bn);
+        }
+        assert_eq!(
+            NewOracle::source_stats(&btc, &dead),
+            SourceHealth {
+                successes: 0,
+                failures: 2,
+                deviations: 0,
+                disabled: true
+            }
+        );
+        assert!(!NewOracle::source_stats(&btc, &good).disabled);
+    });
+}
+
+#[test]
+fn synthetic_tokens_and_trading_pairs_work() {
+    let mut t = sp_io::TestExternalities::default();
+
+    t.execute_with(|| {
+        let root = || -> Origin { system::RawOrigin::Root.into() };
+        let (btc, eth, dusd) = (b"BTC".to_vec(), b"ETH".to_vec(), b"DUSD".to_vec());
+        let half_eth = b"HALF_ETH".to_vec();
+        for token in &[&btc, &eth] {
+            assert_ok!(NewOracle::add_source(
+                root(),
+                token.to_vec(),
+                b"cryptocompare".to_vec(),
+                b"https://example.com".to_vec(),
+                b"$.USD".to_vec(),
+                false
+            ));
+        }
+        <CurrentPrice<Test>>::insert(&dusd, 10000);
+
+        // formulas are checked
+        let half = vec![
+            FormulaOp::Price(eth.clone()),
+            FormulaOp::Const(5000),
+            FormulaOp::Mul,
+        ];
+        assert_noop!(
+            NewOracle::set_synthetic_token(root(), btc.clone(), half.clone()),
+            Error::<Test>::TokenHasSources
+        );
+        assert_noop!(
+            NewOracle::set_synthetic_token(
+                root(),
+                half_eth.clone(),
+                vec![FormulaOp::Price(eth.clone()), FormulaOp::Add]
+            ),
+            Error::<Test>::InvalidFormula
+        );
+        assert_ok!(NewOracle::set_synthetic_token(
+            root(),
+            half_eth.clone(),
+            half
+        ));
+        assert_noop!(
+            NewOracle::set_synthetic_token(
+                root(),
+                b"QUARTER_ETH".to_vec(),
+                vec![
+                    FormulaOp::Price(half_eth.clone()),
+                    FormulaOp::Const(5000),
+                    FormulaOp::Mul
+                ]
+            ),
+            Error::<Test>::InvalidFormula
+        );
+        assert_noop!(
+            NewOracle::add_source(
+                root(),
+                half_eth.clone(),
+                b"cryptocompare".to_vec(),
+                b"https://example.com".to_vec(),
+                b"$.USD".to_vec(),
+                false
+            ),
+            Error::<Test>::SyntheticToken
+        );
+
+        assert_ok!(NewOracle::add_trading_pair(
+            root(),
+            eth.clone(),
+            btc.clone()
+        ));
+        assert_ok!(NewOracle::add_trading_pair(
+            root(),
+            btc.clone(),
+            dusd.clone()
+        ));
+        assert_noop!(
+            NewOracle::add_trading_pair(root(), eth.clone(), btc.clone()),
+            Error::<Test>::PairExists
+        );
+
+        // 10000 and 250 USDT
+        <PriceCandidates<Test>>::insert(&btc, candidates("test", &[100_000_000]));
+        <PriceCandidates<Test>>::insert(&eth, candidates("test", &[2_500_000]));
+        NewOracle::_on_finalize_(5);
+        assert_eq!(NewOracle::current_price(&half_eth), 1_250_000);
+        assert_eq!(NewOracle::pair_price(&eth, &btc), Some((2_500_000, 5)));
+        assert_eq!(
+            NewOracle::pair_price(&btc, &dusd),
+            Some((1_000_000_000_000, 5))
+        );
+
+        // derived prices wait for every token they use
+        <PriceCandidates<Test>>::insert(&btc, candidates("test", &[120_000_000]));
+        NewOracle::_on_finalize_(10);
+        assert_eq!(
+            NewOracle::price_updated_at(&half_eth).map(|(bn, _)| bn),
+            Some(5)
+        );
+        assert_eq!(NewOracle::pair_price(&eth, &btc), Some((2_500_000, 5)));
+        assert_eq!(
+            NewOracle::pair_price(&btc, &dusd),
+            Some((1_200_000_000_000, 10))
+        );
+
+        assert_eq!(
+            NewOracle::evaluate(
+                &[
+                    FormulaOp::Price(btc.clone()),
+                    FormulaOp::Const(0),
+                    FormulaOp::Div
+                ],
+                4
+            ),
+            None
+        );
+
+        assert_ok!(NewOracle::remove_trading_pair(
+            root(),
+            eth.clone(),
+            btc.clone()
+        ));
+        assert_eq!(NewOracle::pair_price(&eth, &btc), None);
+        assert_ok!(NewOracle::remove_synthetic_token(root(), half_eth.clone()));
+        assert_noop!(
+            NewOracle::remove_synthetic_token(root(), half_eth.clone()),
+            Error::<Test>::NotSyntheticToken
+        );
+    });
+}
+
+#[test]
+fn price_info_works() {
+    let mut t = sp_io::TestExternalities::default();
+
+    t.execute_with(|| {
+        let (btc, dusd) = (b"BTC".to_vec(), b"DUSD".to_vec());
+        assert_eq!(NewOracle::price_info(&btc), None);
+
+        <CurrentPrice<Test>>::insert(&dusd, 10000);
+        <PriceCandidates<Test>>::insert(&btc, candidates("test", &[100]));
+        NewOracle::_on_finalize_(5);
+        <PendingPrice<Test>>::insert(&btc, (150, 1));
+
+        assert_eq!(
+            NewOracle::price_info(&btc),
+            Some((100, Some((5, 0)), Some(150)))
+        );
+        let mut prices = NewOracle::prices();
+        prices.sort();
+        assert_eq!(
+            prices,
+            vec![
+                (btc, (100, Some((5, 0)), Some(150))),
+                (dusd, (10000, None, None)),
+            ]
+        );
+        assert_eq!(NewOracle::next_aggregate_at(), 10);
+    });
+}
+
+/// run the offchain worker of blocks `from..=to`, apply the transactions it submits and finalize each block
+fn run_blocks(pool_state: &Arc<RwLock<testing::PoolState>>, from: u64, to: u64) {
+    for bn in from..=to {
+        system::Module::<Test>::set_block_number(bn);
+        NewOracle::fetch_prices_and_submit_unsigned(bn);
+
+        let txs: Vec<_> = pool_state.write().transactions.drain(..).collect();
+        for tx in txs {
+            let tx = Extrinsic::decode(&mut &*tx).unwrap();
+            match tx.call {
+                Call::stack_prices_unsigned(payload, signature) => {
+                    let call = Call::stack_prices_unsigned(payload.clone(), signature.clone());
+                    assert!(NewOracle::validate_unsigned(&call).is_ok());
+                    assert_ok!(NewOracle::stack_prices_unsigned(
+                        system::RawOrigin::None.into(),
+                        payload,
+                        signature
+                    ));
+                }
+                _ => panic!("unexpected call"),
+            }
+        }
+        NewOracle::on_finalize(bn);
+    }
+}
+
+#[test]
+fn offchain_worker_feeds_prices_from_unreliable_exchanges() {
+    let (exchanges, exchanges_state) = FakeExchanges::new();
+    let (pool, pool_state) = testing::TestTransactionPoolExt::new();
+    let mut t = sp_io::TestExternalities::default();
+    t.register_extension(OffchainExt::new(exchanges));
+    t.register_extension(TransactionPoolExt::new(pool));
+    t.register_extension(KeystoreExt(KeyStore::new()));
+
+    let btc_cryptocompare = "https://min-api.cryptocompare.com/data/price?fsym=BTC&tsyms=USD";
+    let btc_coincap = "https://api.coincap.io/v2/assets/bitcoin";
+    let btc_binance = "https://api.binance.com/api/v3/ticker/price?symbol=BTCUSDT";
+    let eth_cryptocompare = "https://min-api.cryptocompare.com/data/price?fsym=ETH&tsyms=USD";
+    let eth_coincap = "https://api.coincap.io/v2/assets/ethereum";
+    let dot_cryptocompare = "https://min-api.cryptocompare.com/data/price?fsym=DOT&tsyms=USD";
+    {
+        let mut exchanges = exchanges_state.write();
+        exchanges.reply(btc_cryptocompare, vec![Reply::Ok(r#"{"USD": 10000.5}"#)]);
+        exchanges.reply(
+            btc_coincap,
+            vec![Reply::Ok(r#"{"data": {"priceUsd": "10001.5"}}"#)],
+        );
+        exchanges.reply(
+            btc_binance,
+            vec![
+                Reply::Timeout,
+                Reply::Ok(r#"{"symbol": "BTCUSDT", "price": "10002.5"}"#),
+            ],
+        );
+        exchanges.reply(
+            eth_cryptocompare,
+            vec![Reply::Status(500), Reply::Ok(r#"{"USD": 200}"#)],
+        );
+        exchanges.reply(
+            eth_coincap,
+            vec![
+                Reply::Ok(r#"{"data": {"priceUsd": 2"#),
+                Reply::Ok(r#"{"data": {"priceUsd": "201"}}"#),
+            ],
         );
+        exchanges.reply(dot_cryptocompare, vec![Reply::Status(404)]);
     }
+
+    t.execute_with(|| {
+        let root = || -> Origin { system::RawOrigin::Root.into() };
+        let operator = sp_core::sr25519::Public::from_raw([1u8; 32]);
+        assert_ok!(NewOracle::add_operator(
+            root(),
+            operator,
+            crypto::Public::generate_pair(None)
+        ));
+        let sources = vec![
+            ("BTC", "cryptocompare", btc_cryptocompare, "$.USD"),
+            ("BTC", "coincap", btc_coincap, "$.data.priceUsd"),
+            ("BTC", "binance", btc_binance, "$.price"),
+            ("ETH", "cryptocompare", eth_cryptocompare, "$.USD"),
+            ("ETH", "coincap", eth_coincap, "$.data.priceUsd"),
+            ("DOT", "cryptocompare", dot_cryptocompare, "$.USD"),
+        ];
+        for (token, name, url, path) in sources {
+            assert_ok!(NewOracle::add_source(
+                root(),
+                token.as_bytes().to_vec(),
+                name.as_bytes().to_vec(),
+                url.as_bytes().to_vec(),
+                path.as_bytes().to_vec(),
+                false
+            ));
+        }
+        MaxDeviation::put(10);
+        MinQuorum::put(1);
+        let (btc, eth, dot) = (b"BTC".to_vec(), b"ETH".to_vec(), b"DOT".to_vec());
+
+        // block 1 submits for the first round, every ETH and DOT source fails,
+        // blocks 2 to 4 belong to the same round and don't fetch
+        run_blocks(&pool_state, 1, 4);
+        assert_eq!(exchanges_state.read().requested.len(), 6);
+        // sources are queried from a random one on
+        let mut candidates: Vec<_> = NewOracle::price_candidates(&btc)
+            .into_iter()
+            .map(|(_, source, price)| (source, price))
+            .collect();
+        candidates.sort();
+        assert_eq!(
+            candidates,
+            vec![
+                (b"coincap".to_vec(), 100015000),
+                (b"cryptocompare".to_vec(), 100005000),
+            ]
+        );
+        assert!(NewOracle::price_candidates(&eth).is_empty());
+        assert_eq!(NewOracle::fetch_schedule(&eth), (3, 1));
+
+        // block 5 submits for the next round and aggregates both
+        run_blocks(&pool_state, 5, 5);
+        assert_eq!(exchanges_state.read().requested.len(), 12);
+        // the operator's median over both submissions
+        assert_eq!(NewOracle::current_price(&btc), 100015000);
+        assert_eq!(NewOracle::current_price(&eth), 2005000);
+        assert!(!NewOracle::is_token_known(&dot));
+        assert_eq!(NewOracle::fetch_schedule(&dot), (9, 2));
+        // sources are counted once per aggregation, a lone operator's failures aren't counted
+        let fetched_once = SourceHealth {
+            successes: 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            NewOracle::source_health(&btc),
+            vec![
+                (b"cryptocompare".to_vec(), fetched_once.clone()),
+                (b"coincap".to_vec(), fetched_once.clone()),
+                (b"binance".to_vec(), fetched_once),
+            ]
+        );
+        assert_eq!(
+            NewOracle::source_health(&dot),
+            vec![(b"cryptocompare".to_vec(), SourceHealth::default())]
+        );
+
+        // DOT is backing off until block 9, the others are fetched once per round
+        run_blocks(&pool_state, 6, 10);
+        let exchanges = exchanges_state.read();
+        assert_eq!(exchanges.requested.len(), 18);
+        assert_eq!(
+            exchanges
+                .requested
+                .iter()
+                .filter(|url| url.as_str() == dot_cryptocompare)
+                .count(),
+            3
+        );
+        assert_eq!(
+            NewOracle::price_updated_at(&btc).map(|(bn, _)| bn),
+            Some(10)
+        );
+        assert_eq!(NewOracle::current_price(&eth), 2005000);
+        assert!(!NewOracle::is_token_known(&dot));
+    });
 }
@@ -81,6 +81,10 @@ pub enum P2PLoanHealth {
     Liquidated,
     Dead,
     Completed,
+    Defaulted,
+    Auctioning,
+    /// ltv fell below margin_call_ltv, the borrower may top up before liquidation
+    MarginCall,
 }
 impl Default for P2PLoanHealth {
     fn default() -> Self {
@@ -100,6 +104,44 @@ impl Default for LiquidationType {
     }
 }
 
+/// a variable rate loan floats interest_rate as a spread over the reference rate
+#[derive(Debug, Encode, Decode, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+pub enum P2PRateType {
+    Fixed,
+    Variable,
+}
+impl Default for P2PRateType {
+    fn default() -> Self {
+        P2PRateType::Fixed
+    }
+}
+
+/// Bullet loans are repaid at once at due, the others in installments every 7 or 30 days
+#[derive(Debug, Encode, Decode, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+pub enum P2PRepaymentSchedule {
+    Bullet,
+    Weekly,
+    Monthly,
+}
+impl Default for P2PRepaymentSchedule {
+    fn default() -> Self {
+        P2PRepaymentSchedule::Bullet
+    }
+}
+
+impl P2PRepaymentSchedule {
+    /// days between installments
+    pub fn period(&self) -> Option<u64> {
+        match self {
+            P2PRepaymentSchedule::Bullet => None,
+            P2PRepaymentSchedule::Weekly => Some(7),
+            P2PRepaymentSchedule::Monthly => Some(30),
+        }
+    }
+}
+
 #[derive(Debug, Encode, Decode, Clone, Default, PartialEq, Eq)]
 #[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
 pub struct P2PLoan<AssetId, Balance, BlockNumber, AccountId> {
@@ -160,6 +202,8 @@ pub struct P2PLoan<AssetId, Balance, BlockNumber, AccountId> {
     pub status: P2PLoanHealth,
     pub interest_rate: u64,
     pub liquidation_type: LiquidationType,
+    pub rate_type: P2PRateType,
+    pub repayment: P2PRepaymentSchedule,
 }
 
 #[derive(Debug, Encode, Decode, Clone, Default, PartialEq, Eq)]
@@ -205,6 +249,9 @@ pub struct P2PBorrow<AssetId, Balance, BlockNumber, AccountId> {
     pub terms: u64, // days of our lives
     pub interest_rate: u64,
     pub dead_after: Option<BlockNumber>,
+    pub liquidation_type: LiquidationType,
+    pub rate_type: P2PRateType,
+    pub repayment: P2PRepaymentSchedule,
 
     #[cfg_attr(feature = "std", serde(serialize_with = "serialize_option_as_string"))]
     #[cfg_attr(
@@ -237,6 +284,37 @@ pub struct TradingPair<A> {
     pub borrow: A,
 }
 
+/// risk parameters of a trading pair, LTVs are scaled by LTV_SCALE
+#[derive(Debug, Encode, Decode, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+pub struct TradingPairConfig {
+    /// LTV must be greater than this value to create a new borrow
+    pub safe_ltv: u32,
+    /// a loan will be liquidated when LTV is below this
+    pub liquidate_ltv: u32,
+    /// count in natural days
+    pub min_borrow_terms: u64,
+    pub max_borrow_terms: u64,
+    pub min_borrow_interest_rate: u64,
+    /// disabled pairs accept no new borrows, existing loans are not affected
+    pub enabled: bool,
+    /// share of the loan amount paid to the platform when a loan is created, scaled by FEE_RATE_SCALE
+    pub origination_fee_rate: u32,
+    /// share of the interest paid to the platform when a loan is repaid, scaled by FEE_RATE_SCALE
+    pub interest_fee_rate: u32,
+    /// weighted_days of credit record a borrower needs to use reputation_safe_ltv, 0 disables it
+    pub reputation_tier: u32,
+    /// the lower initial LTV for borrowers at or above reputation_tier
+    pub reputation_safe_ltv: u32,
+    /// a loan is margin called when LTV is below this, 0 disables margin calls
+    pub margin_call_ltv: u32,
+    /// blocks a margin called loan is protected from liquidation
+    pub margin_call_window: u64,
+    /// loans to be liquidated are settled by the periodic check instead of waiting for a liquidator
+    pub auto_liquidation: bool,
+}
+
+/// prices of the two assets of a pair, with the same decimals
 #[derive(Debug, Encode, Decode, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
 pub struct TradingPairPrices {
@@ -251,4 +329,325 @@ pub struct P2PBorrowOptions<B, N> {
     pub terms: u64,
     pub interest_rate: u64,
     pub warranty: Option<N>,
+    pub liquidation_type: LiquidationType,
+    pub rate_type: P2PRateType,
+    pub repayment: P2PRepaymentSchedule,
+}
+
+/// a descending price auction selling the collateral of a SellCollateral loan,
+/// price drops linearly from start_price to floor_price between start_at and end_at
+#[derive(Debug, Encode, Decode, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+pub struct P2PAuction<AssetId, Balance, BlockNumber> {
+    #[cfg_attr(feature = "std", serde(serialize_with = "serialize_as_string"))]
+    #[cfg_attr(feature = "std", serde(deserialize_with = "deserialize_from_string"))]
+    pub loan_id: P2PLoanId,
+
+    pub collateral_asset_id: AssetId,
+
+    #[cfg_attr(
+        feature = "std",
+        serde(bound(serialize = "Balance: std::fmt::Display"))
+    )]
+    #[cfg_attr(feature = "std", serde(serialize_with = "serialize_as_string"))]
+    #[cfg_attr(
+        feature = "std",
+        serde(bound(deserialize = "Balance: std::str::FromStr"))
+    )]
+    #[cfg_attr(feature = "std", serde(deserialize_with = "deserialize_from_string"))]
+    pub collateral_balance: Balance,
+
+    pub bid_asset_id: AssetId,
+
+    #[cfg_attr(
+        feature = "std",
+        serde(bound(serialize = "Balance: std::fmt::Display"))
+    )]
+    #[cfg_attr(feature = "std", serde(serialize_with = "serialize_as_string"))]
+    #[cfg_attr(
+        feature = "std",
+        serde(bound(deserialize = "Balance: std::str::FromStr"))
+    )]
+    #[cfg_attr(feature = "std", serde(deserialize_with = "deserialize_from_string"))]
+    pub start_price: Balance,
+
+    #[cfg_attr(
+        feature = "std",
+        serde(bound(serialize = "Balance: std::fmt::Display"))
+    )]
+    #[cfg_attr(feature = "std", serde(serialize_with = "serialize_as_string"))]
+    #[cfg_attr(
+        feature = "std",
+        serde(bound(deserialize = "Balance: std::str::FromStr"))
+    )]
+    #[cfg_attr(feature = "std", serde(deserialize_with = "deserialize_from_string"))]
+    pub floor_price: Balance,
+
+    /// principal, interest and penalty interest owed to the lender
+    #[cfg_attr(
+        feature = "std",
+        serde(bound(serialize = "Balance: std::fmt::Display"))
+    )]
+    #[cfg_attr(feature = "std", serde(serialize_with = "serialize_as_string"))]
+    #[cfg_attr(
+        feature = "std",
+        serde(bound(deserialize = "Balance: std::str::FromStr"))
+    )]
+    #[cfg_attr(feature = "std", serde(deserialize_with = "deserialize_from_string"))]
+    pub debt: Balance,
+
+    pub start_at: BlockNumber,
+    pub end_at: BlockNumber,
+}
+
+/// interest of a variable rate loan accrued up to accrued_at,
+/// rate is the daily rate used from then on
+#[derive(Debug, Encode, Decode, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+pub struct P2PInterestAccrual<Balance, BlockNumber> {
+    pub rate: u64,
+
+    #[cfg_attr(
+        feature = "std",
+        serde(bound(serialize = "Balance: std::fmt::Display"))
+    )]
+    #[cfg_attr(feature = "std", serde(serialize_with = "serialize_as_string"))]
+    #[cfg_attr(
+        feature = "std",
+        serde(bound(deserialize = "Balance: std::str::FromStr"))
+    )]
+    #[cfg_attr(feature = "std", serde(deserialize_with = "deserialize_from_string"))]
+    pub interest: Balance,
+
+    pub accrued_at: BlockNumber,
+}
+
+/// one payment of an amortizing loan, interest is charged on the principal still outstanding
+#[derive(Debug, Encode, Decode, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+pub struct P2PInstallment<Balance, BlockNumber> {
+    pub due: BlockNumber,
+
+    #[cfg_attr(
+        feature = "std",
+        serde(bound(serialize = "Balance: std::fmt::Display"))
+    )]
+    #[cfg_attr(feature = "std", serde(serialize_with = "serialize_as_string"))]
+    #[cfg_attr(
+        feature = "std",
+        serde(bound(deserialize = "Balance: std::str::FromStr"))
+    )]
+    #[cfg_attr(feature = "std", serde(deserialize_with = "deserialize_from_string"))]
+    pub principal: Balance,
+
+    #[cfg_attr(
+        feature = "std",
+        serde(bound(serialize = "Balance: std::fmt::Display"))
+    )]
+    #[cfg_attr(feature = "std", serde(serialize_with = "serialize_as_string"))]
+    #[cfg_attr(
+        feature = "std",
+        serde(bound(deserialize = "Balance: std::str::FromStr"))
+    )]
+    #[cfg_attr(feature = "std", serde(deserialize_with = "deserialize_from_string"))]
+    pub interest: Balance,
+
+    pub paid: bool,
+}
+
+/// repayment history of a borrower, volume is valued in the oracle's price unit
+#[derive(Debug, Encode, Decode, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+pub struct P2PCreditRecord<Balance> {
+    /// number of loans repaid by due
+    pub repaid_on_time: u32,
+    /// number of loans repaid during the grace period
+    pub repaid_overdue: u32,
+    /// number of loans liquidated
+    pub liquidated: u32,
+    /// terms in days of the loans repaid on time, less those of the loans repaid overdue or liquidated,
+    /// so short loans can't build up a reputation quickly
+    pub weighted_days: u32,
+
+    #[cfg_attr(
+        feature = "std",
+        serde(bound(serialize = "Balance: std::fmt::Display"))
+    )]
+    #[cfg_attr(feature = "std", serde(serialize_with = "serialize_as_string"))]
+    #[cfg_attr(
+        feature = "std",
+        serde(bound(deserialize = "Balance: std::str::FromStr"))
+    )]
+    #[cfg_attr(feature = "std", serde(deserialize_with = "deserialize_from_string"))]
+    pub total_volume: Balance,
+}
+
+/// what a borrower pays in total for a loan, platform fees included
+#[derive(Debug, Encode, Decode, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+pub struct P2PBorrowQuote<Balance> {
+    /// interest owed at due, the interest fee is taken out of it
+    #[cfg_attr(
+        feature = "std",
+        serde(bound(serialize = "Balance: std::fmt::Display"))
+    )]
+    #[cfg_attr(feature = "std", serde(serialize_with = "serialize_as_string"))]
+    #[cfg_attr(
+        feature = "std",
+        serde(bound(deserialize = "Balance: std::str::FromStr"))
+    )]
+    #[cfg_attr(feature = "std", serde(deserialize_with = "deserialize_from_string"))]
+    pub interest: Balance,
+
+    /// kept from the loan amount when the borrow is taken
+    #[cfg_attr(
+        feature = "std",
+        serde(bound(serialize = "Balance: std::fmt::Display"))
+    )]
+    #[cfg_attr(feature = "std", serde(serialize_with = "serialize_as_string"))]
+    #[cfg_attr(
+        feature = "std",
+        serde(bound(deserialize = "Balance: std::str::FromStr"))
+    )]
+    #[cfg_attr(feature = "std", serde(deserialize_with = "deserialize_from_string"))]
+    pub origination_fee: Balance,
+
+    #[cfg_attr(
+        feature = "std",
+        serde(bound(serialize = "Balance: std::fmt::Display"))
+    )]
+    #[cfg_attr(feature = "std", serde(serialize_with = "serialize_as_string"))]
+    #[cfg_attr(
+        feature = "std",
+        serde(bound(deserialize = "Balance: std::str::FromStr"))
+    )]
+    #[cfg_attr(feature = "std", serde(deserialize_with = "deserialize_from_string"))]
+    pub interest_fee: Balance,
+
+    /// interest plus origination fee
+    #[cfg_attr(
+        feature = "std",
+        serde(bound(serialize = "Balance: std::fmt::Display"))
+    )]
+    #[cfg_attr(feature = "std", serde(serialize_with = "serialize_as_string"))]
+    #[cfg_attr(
+        feature = "std",
+        serde(bound(deserialize = "Balance: std::str::FromStr"))
+    )]
+    #[cfg_attr(feature = "std", serde(deserialize_with = "deserialize_from_string"))]
+    pub total_cost: Balance,
+}
+
+#[derive(Debug, Encode, Decode, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+pub enum P2PSortBy {
+    Id,
+    Amount,
+    InterestRate,
+    Terms,
+}
+impl Default for P2PSortBy {
+    fn default() -> Self {
+        Self::Id
+    }
+}
+
+#[derive(Debug, Encode, Decode, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+pub enum P2PSortOrder {
+    Asc,
+    Desc,
+}
+impl Default for P2PSortOrder {
+    fn default() -> Self {
+        Self::Desc
+    }
+}
+
+/// position of the last item of a page, the next page starts right after it
+#[derive(Debug, Encode, Decode, Copy, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+pub struct P2PCursor {
+    /// value of the sort field of the last item
+    #[cfg_attr(feature = "std", serde(serialize_with = "serialize_as_string"))]
+    #[cfg_attr(feature = "std", serde(deserialize_with = "deserialize_from_string"))]
+    pub key: u128,
+
+    #[cfg_attr(feature = "std", serde(serialize_with = "serialize_as_string"))]
+    #[cfg_attr(feature = "std", serde(deserialize_with = "deserialize_from_string"))]
+    pub id: u128,
+}
+
+/// filters and ordering of the borrow and loan listings, a filter left None matches everything.
+/// Status is P2PBorrowStatus for borrows and P2PLoanHealth for loans.
+#[derive(Debug, Encode, Decode, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+pub struct P2PQuery<AssetId, Balance, AccountId, Status> {
+    #[cfg_attr(feature = "std", serde(default))]
+    pub trading_pair: Option<TradingPair<AssetId>>,
+    #[cfg_attr(feature = "std", serde(default))]
+    pub status: Option<Status>,
+    /// owner of a borrow, borrower or lender of a loan
+    #[cfg_attr(feature = "std", serde(default))]
+    pub account: Option<AccountId>,
+
+    #[cfg_attr(
+        feature = "std",
+        serde(bound(serialize = "Balance: std::fmt::Display"))
+    )]
+    #[cfg_attr(feature = "std", serde(serialize_with = "serialize_option_as_string"))]
+    #[cfg_attr(
+        feature = "std",
+        serde(bound(deserialize = "Balance: std::str::FromStr"))
+    )]
+    #[cfg_attr(
+        feature = "std",
+        serde(deserialize_with = "deserialize_option_from_string")
+    )]
+    #[cfg_attr(feature = "std", serde(default))]
+    pub min_amount: Option<Balance>,
+
+    #[cfg_attr(
+        feature = "std",
+        serde(bound(serialize = "Balance: std::fmt::Display"))
+    )]
+    #[cfg_attr(feature = "std", serde(serialize_with = "serialize_option_as_string"))]
+    #[cfg_attr(
+        feature = "std",
+        serde(bound(deserialize = "Balance: std::str::FromStr"))
+    )]
+    #[cfg_attr(
+        feature = "std",
+        serde(deserialize_with = "deserialize_option_from_string")
+    )]
+    #[cfg_attr(feature = "std", serde(default))]
+    pub max_amount: Option<Balance>,
+
+    #[cfg_attr(feature = "std", serde(default))]
+    pub min_interest_rate: Option<u64>,
+    #[cfg_attr(feature = "std", serde(default))]
+    pub max_interest_rate: Option<u64>,
+    #[cfg_attr(feature = "std", serde(default))]
+    pub min_terms: Option<u64>,
+    #[cfg_attr(feature = "std", serde(default))]
+    pub max_terms: Option<u64>,
+
+    #[cfg_attr(feature = "std", serde(default))]
+    pub sort_by: P2PSortBy,
+    #[cfg_attr(feature = "std", serde(default))]
+    pub order: P2PSortOrder,
+    /// next_cursor of the previous page, None for the first page
+    #[cfg_attr(feature = "std", serde(default))]
+    pub cursor: Option<P2PCursor>,
+    /// page size, 10 by default
+    #[cfg_attr(feature = "std", serde(default))]
+    pub size: Option<u64>,
+}
+
+#[derive(Debug, Encode, Decode, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+pub struct P2PPage<Item> {
+    pub items: Vec<Item>,
+    /// None when there is nothing left
+    pub next_cursor: Option<P2PCursor>,
 }
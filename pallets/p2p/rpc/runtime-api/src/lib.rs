@@ -21,6 +21,18 @@ sp_api::decl_runtime_apis! {
         fn get_available_loans(size: Option<u64>, offset: Option<u64>) -> Vec<P2PLoan<AssetId, Balance, BlockNumber, AccountId>>;
         fn get_user_borrows(who: AccountId, size: Option<u64>, offset: Option<u64>) -> Vec<P2PBorrow<AssetId, Balance, BlockNumber, AccountId>>;
         fn get_user_loans(who: AccountId, size: Option<u64>, offset: Option<u64>) -> Vec<P2PLoan<AssetId, Balance, BlockNumber, AccountId>>;
+        fn query_borrows(query: P2PQuery<AssetId, Balance, AccountId, P2PBorrowStatus>) -> Option<P2PPage<P2PBorrow<AssetId, Balance, BlockNumber, AccountId>>>;
+        fn query_loans(query: P2PQuery<AssetId, Balance, AccountId, P2PLoanHealth>) -> Option<P2PPage<P2PLoan<AssetId, Balance, BlockNumber, AccountId>>>;
+        fn get_auctions(size: Option<u64>, offset: Option<u64>) -> Vec<P2PAuction<AssetId, Balance, BlockNumber>>;
+        fn get_borrow_quote(trading_pair: TradingPair<AssetId>, amount: Balance, terms: u64, interest_rate: u64) -> Option<P2PBorrowQuote<Balance>>;
+        fn get_credit_record(who: AccountId) -> P2PCreditRecord<Balance>;
         fn get_secs_per_block() -> Moment;
+        fn get_grace_period_end(loan_id: P2PLoanId) -> BlockNumber;
+        fn get_overdue_penalty(loan_id: P2PLoanId) -> Balance;
+        fn get_margin_call_deadline(loan_id: P2PLoanId) -> Option<BlockNumber>;
+        fn get_loan_interest(loan_id: P2PLoanId) -> Balance;
+        fn get_loan_interest_rate(loan_id: P2PLoanId) -> u64;
+        fn get_reference_rate() -> u64;
+        fn get_loan_installments(loan_id: P2PLoanId) -> Vec<P2PInstallment<Balance, BlockNumber>>;
     }
 }
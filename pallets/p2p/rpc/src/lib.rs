@@ -23,6 +23,7 @@ pub enum Error {
     RuntimeError,
     NoBorrows,
     NoLoans,
+    UnindexedSort,
 }
 impl From<Error> for i64 {
     fn from(e: Error) -> i64 {
@@ -30,6 +31,7 @@ impl From<Error> for i64 {
             Error::RuntimeError => 1,
             Error::NoBorrows => 2,
             Error::NoLoans => 3,
+            Error::UnindexedSort => 4,
         }
     }
 }
@@ -39,13 +41,34 @@ impl From<Error> for String {
             Error::RuntimeError => "runtime trapped".to_string(),
             Error::NoBorrows => "no borrows found".to_string(),
             Error::NoLoans => "no loans found".to_string(),
+            Error::UnindexedSort => {
+                "sorting by anything but id needs a trading pair, status or account filter"
+                    .to_string()
+            }
         }
     }
 }
 
 /// P2P RPC methods
 #[rpc]
-pub trait P2PApi<BlockHash, AccountId, Moment, BorrowsResult, LoansResult> {
+pub trait P2PApi<
+    BlockHash,
+    AccountId,
+    Moment,
+    BorrowsResult,
+    LoansResult,
+    AuctionsResult,
+    BorrowQuery,
+    LoanQuery,
+    BorrowsPage,
+    LoansPage,
+    AssetPair,
+    Amount,
+    QuoteResult,
+    CreditRecord,
+    Installments,
+>
+{
     #[rpc(name = "pToP_borrows")]
     fn borrows(
         &self,
@@ -95,6 +118,39 @@ pub trait P2PApi<BlockHash, AccountId, Moment, BorrowsResult, LoansResult> {
         offset: Option<u64>,
         at: Option<BlockHash>,
     ) -> Result<LoansResult>;
+
+    #[rpc(name = "pToP_auctions")]
+    fn auctions(
+        &self,
+        size: Option<u64>,
+        offset: Option<u64>,
+        at: Option<BlockHash>,
+    ) -> Result<AuctionsResult>;
+
+    #[rpc(name = "pToP_queryBorrows")]
+    fn query_borrows(&self, query: BorrowQuery, at: Option<BlockHash>) -> Result<BorrowsPage>;
+
+    #[rpc(name = "pToP_queryLoans")]
+    fn query_loans(&self, query: LoanQuery, at: Option<BlockHash>) -> Result<LoansPage>;
+
+    #[rpc(name = "pToP_borrowQuote")]
+    fn borrow_quote(
+        &self,
+        trading_pair: AssetPair,
+        amount: Amount,
+        terms: u64,
+        interest_rate: u64,
+        at: Option<BlockHash>,
+    ) -> Result<QuoteResult>;
+
+    #[rpc(name = "pToP_creditRecord")]
+    fn credit_record(&self, who: AccountId, at: Option<BlockHash>) -> Result<CreditRecord>;
+
+    #[rpc(name = "pToP_referenceRate")]
+    fn reference_rate(&self, at: Option<BlockHash>) -> Result<u64>;
+
+    #[rpc(name = "pToP_loanInstallments")]
+    fn loan_installments(&self, loan_id: P2PLoanId, at: Option<BlockHash>) -> Result<Installments>;
 }
 
 pub struct P2P<C, B> {
@@ -116,6 +172,16 @@ impl<C, Block, AssetId, Balance, BlockNumber, AccountId, Moment>
         Moment,
         Vec<P2PBorrow<AssetId, Balance, BlockNumber, AccountId>>,
         Vec<P2PLoanRPC<AssetId, Balance, BlockNumber, AccountId>>,
+        Vec<P2PAuction<AssetId, Balance, BlockNumber>>,
+        P2PQuery<AssetId, Balance, AccountId, P2PBorrowStatus>,
+        P2PQuery<AssetId, Balance, AccountId, P2PLoanHealth>,
+        P2PPage<P2PBorrow<AssetId, Balance, BlockNumber, AccountId>>,
+        P2PPage<P2PLoanRPC<AssetId, Balance, BlockNumber, AccountId>>,
+        TradingPair<AssetId>,
+        Balance,
+        Option<P2PBorrowQuote<Balance>>,
+        P2PCreditRecord<Balance>,
+        Vec<P2PInstallment<Balance, BlockNumber>>,
     > for P2P<C, Block>
 where
     Block: BlockT,
@@ -245,6 +311,130 @@ where
 
         self.p2p_loan_2_rpc_loan::<AssetId, Balance, BlockNumber, AccountId, Moment>(api, at, list)
     }
+
+    fn auctions(
+        &self,
+        size: Option<u64>,
+        offset: Option<u64>,
+        at: Option<<Block as BlockT>::Hash>,
+    ) -> Result<Vec<P2PAuction<AssetId, Balance, BlockNumber>>> {
+        let api = self.client.runtime_api();
+        let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+        let list = api
+            .get_auctions(&at, size, offset)
+            .map_err(|e| RPCError {
+                code: ErrorCode::ServerError(Error::RuntimeError.into()),
+                message: Error::RuntimeError.into(),
+                data: Some(format!("{:?}", e).into()),
+            })
+            .unwrap();
+        Ok(list)
+    }
+
+    fn query_borrows(
+        &self,
+        query: P2PQuery<AssetId, Balance, AccountId, P2PBorrowStatus>,
+        at: Option<<Block as BlockT>::Hash>,
+    ) -> Result<P2PPage<P2PBorrow<AssetId, Balance, BlockNumber, AccountId>>> {
+        let api = self.client.runtime_api();
+        let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+        api.query_borrows(&at, query)
+            .map_err(|e| RPCError {
+                code: ErrorCode::ServerError(Error::RuntimeError.into()),
+                message: Error::RuntimeError.into(),
+                data: Some(format!("{:?}", e).into()),
+            })?
+            .ok_or(RPCError {
+                code: ErrorCode::ServerError(Error::UnindexedSort.into()),
+                message: Error::UnindexedSort.into(),
+                data: None,
+            })
+    }
+
+    fn query_loans(
+        &self,
+        query: P2PQuery<AssetId, Balance, AccountId, P2PLoanHealth>,
+        at: Option<<Block as BlockT>::Hash>,
+    ) -> Result<P2PPage<P2PLoanRPC<AssetId, Balance, BlockNumber, AccountId>>> {
+        let api = self.client.runtime_api();
+        let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+        let page = api
+            .query_loans(&at, query)
+            .map_err(|e| RPCError {
+                code: ErrorCode::ServerError(Error::RuntimeError.into()),
+                message: Error::RuntimeError.into(),
+                data: Some(format!("{:?}", e).into()),
+            })?
+            .ok_or(RPCError {
+                code: ErrorCode::ServerError(Error::UnindexedSort.into()),
+                message: Error::UnindexedSort.into(),
+                data: None,
+            })?;
+
+        Ok(P2PPage {
+            items: self.p2p_loan_2_rpc_loan::<AssetId, Balance, BlockNumber, AccountId, Moment>(
+                api, at, page.items,
+            )?,
+            next_cursor: page.next_cursor,
+        })
+    }
+
+    fn borrow_quote(
+        &self,
+        trading_pair: TradingPair<AssetId>,
+        amount: Balance,
+        terms: u64,
+        interest_rate: u64,
+        at: Option<<Block as BlockT>::Hash>,
+    ) -> Result<Option<P2PBorrowQuote<Balance>>> {
+        let api = self.client.runtime_api();
+        let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+        api.get_borrow_quote(&at, trading_pair, amount, terms, interest_rate)
+            .map_err(|e| RPCError {
+                code: ErrorCode::ServerError(Error::RuntimeError.into()),
+                message: Error::RuntimeError.into(),
+                data: Some(format!("{:?}", e).into()),
+            })
+    }
+
+    fn credit_record(
+        &self,
+        who: AccountId,
+        at: Option<<Block as BlockT>::Hash>,
+    ) -> Result<P2PCreditRecord<Balance>> {
+        let api = self.client.runtime_api();
+        let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+        api.get_credit_record(&at, who).map_err(|e| RPCError {
+            code: ErrorCode::ServerError(Error::RuntimeError.into()),
+            message: Error::RuntimeError.into(),
+            data: Some(format!("{:?}", e).into()),
+        })
+    }
+
+    fn reference_rate(&self, at: Option<<Block as BlockT>::Hash>) -> Result<u64> {
+        let api = self.client.runtime_api();
+        let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+        api.get_reference_rate(&at).map_err(|e| RPCError {
+            code: ErrorCode::ServerError(Error::RuntimeError.into()),
+            message: Error::RuntimeError.into(),
+            data: Some(format!("{:?}", e).into()),
+        })
+    }
+
+    fn loan_installments(
+        &self,
+        loan_id: P2PLoanId,
+        at: Option<<Block as BlockT>::Hash>,
+    ) -> Result<Vec<P2PInstallment<Balance, BlockNumber>>> {
+        let api = self.client.runtime_api();
+        let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+        api.get_loan_installments(&at, loan_id)
+            .map_err(|e| RPCError {
+                code: ErrorCode::ServerError(Error::RuntimeError.into()),
+                message: Error::RuntimeError.into(),
+                data: Some(format!("{:?}", e).into()),
+            })
+    }
 }
 
 impl<C, Block> P2P<C, Block>
@@ -300,6 +490,17 @@ where
                     } else {
                         due - block_number
                     };
+                    let grace_period_end = api.get_grace_period_end(&at, v.id).unwrap();
+                    let grace_end = TryInto::<u64>::try_into(grace_period_end).ok().unwrap();
+                    let grace_blocks_left = if grace_end <= block_number {
+                        0u64
+                    } else {
+                        grace_end - block_number
+                    };
+                    let margin_call_deadline = api.get_margin_call_deadline(&at, v.id).unwrap();
+                    let margin_call_blocks_left = margin_call_deadline
+                        .map(|deadline| TryInto::<u64>::try_into(deadline).ok().unwrap())
+                        .map_or(0u64, |deadline| deadline.saturating_sub(block_number));
 
                     P2PLoanRPC {
                         id: v.id,
@@ -308,6 +509,9 @@ where
                         loaner_id: v.loaner_id.clone(),
                         due_height: v.due,
                         secs_left: blocks_left * secs_per_block,
+                        grace_period_end,
+                        grace_secs_left: grace_blocks_left * secs_per_block,
+                        overdue_penalty: api.get_overdue_penalty(&at, v.id).unwrap(),
                         collateral_asset_id: v.collateral_asset_id,
                         collateral_balance: v.collateral_balance,
                         loan_asset_id: v.loan_asset_id,
@@ -316,12 +520,18 @@ where
                         interest_rate: v.interest_rate,
                         liquidation_type: v.liquidation_type,
                         can_be_liquidate: if v.status == P2PLoanHealth::ToBeLiquidated
-                            || v.status == P2PLoanHealth::Overdue
+                            || v.status == P2PLoanHealth::Defaulted
                         {
                             true
                         } else {
                             false
                         },
+                        can_be_claimed: v.status == P2PLoanHealth::Defaulted,
+                        margin_call_deadline,
+                        margin_call_secs_left: margin_call_blocks_left * secs_per_block,
+                        rate_type: v.rate_type,
+                        current_interest_rate: api.get_loan_interest_rate(&at, v.id).unwrap(),
+                        interest: api.get_loan_interest(&at, v.id).unwrap(),
                     }
                 },
             )
@@ -365,5 +575,29 @@ pub struct P2PLoanRPC<AssetId, Balance, BlockNumber, AccountId> {
     pub interest_rate: u64,
     pub liquidation_type: LiquidationType,
     pub can_be_liquidate: bool,
+    pub can_be_claimed: bool,
     pub due_height: BlockNumber,
+    pub grace_period_end: BlockNumber,
+    pub grace_secs_left: u64,
+
+    #[serde(bound(serialize = "Balance: std::fmt::Display"))]
+    #[serde(serialize_with = "serialize_as_string")]
+    #[serde(bound(deserialize = "Balance: std::str::FromStr"))]
+    #[serde(deserialize_with = "deserialize_from_string")]
+    pub overdue_penalty: Balance,
+
+    /// liquidation is held off until this block while the loan is margin called
+    pub margin_call_deadline: Option<BlockNumber>,
+    pub margin_call_secs_left: u64,
+
+    pub rate_type: P2PRateType,
+    /// the daily rate charged right now, for variable rate loans the last sampled one
+    pub current_interest_rate: u64,
+
+    /// owed so far, the whole term's for fixed rate loans
+    #[serde(bound(serialize = "Balance: std::fmt::Display"))]
+    #[serde(serialize_with = "serialize_as_string")]
+    #[serde(bound(deserialize = "Balance: std::str::FromStr"))]
+    #[serde(deserialize_with = "deserialize_from_string")]
+    pub interest: Balance,
 }
@@ -57,12 +57,13 @@ use support::{
         Contains, Currency, Get, Imbalance, LockIdentifier, LockableCurrency, ReservableCurrency,
         WithdrawReason, WithdrawReasons,
     },
-    weights::{DispatchInfo, SimpleDispatchInfo},
+    weights::{DispatchInfo, SimpleDispatchInfo, WeighData, Weight},
     IsSubType, IterableStorageMap,
 };
 #[allow(unused_imports)]
 use system::{ensure_root, ensure_signed};
 
+use new_oracle_primitives::{at_common_decimals, PriceProvider};
 pub use p2p_primitives::*;
 
 mod mock;
@@ -72,17 +73,66 @@ const LOCK_ID: LockIdentifier = *b"dfxlsbrw";
 
 pub const INTEREST_RATE_PRECISION: u64 = 10000_0000;
 pub const LTV_SCALE: u32 = 10000;
+pub const FEE_RATE_SCALE: u32 = 10000;
+/// weight charged against LoanCheckWeightBudget for checking one loan
+pub const LOAN_CHECK_WEIGHT: Weight = 1_000_000;
+/// live loan ids are kept in pages of this many consecutive ids
+pub const LIVE_LOAN_PAGE_SIZE: P2PLoanId = 64;
+/// a borrow check goes through at most this many blocks of BorrowIdsByDeadAfter, the rest is left for the following blocks
+pub const BORROW_CHECK_MAX_BLOCKS: u32 = 256;
+/// layout of stored borrows, loans and their indices, bumped when they have to be migrated
+pub const STORAGE_VERSION: u32 = 1;
+
+/// how a borrow was stored before `STORAGE_VERSION` 1, without liquidation_type, rate_type and repayment
+#[derive(Decode)]
+struct LegacyP2PBorrow<AssetId, Balance, BlockNumber, AccountId> {
+    id: P2PBorrowId,
+    lock_id: u128,
+    who: AccountId,
+    status: P2PBorrowStatus,
+    borrow_asset_id: AssetId,
+    collateral_asset_id: AssetId,
+    borrow_balance: Balance,
+    collateral_balance: Balance,
+    terms: u64,
+    interest_rate: u64,
+    dead_after: Option<BlockNumber>,
+    loan_id: Option<P2PLoanId>,
+}
+
+/// how a loan was stored before `STORAGE_VERSION` 1, without rate_type and repayment
+#[derive(Decode)]
+struct LegacyP2PLoan<AssetId, Balance, BlockNumber, AccountId> {
+    id: P2PLoanId,
+    borrow_id: P2PBorrowId,
+    borrower_id: AccountId,
+    loaner_id: AccountId,
+    due: BlockNumber,
+    collateral_asset_id: AssetId,
+    collateral_balance: Balance,
+    loan_balance: Balance,
+    loan_asset_id: AssetId,
+    status: P2PLoanHealth,
+    interest_rate: u64,
+    liquidation_type: LiquidationType,
+}
 
 /// The module's configuration trait.
-pub trait Trait:
-    generic_asset::Trait + timestamp::Trait + system::Trait + new_oracle::Trait
-{
+pub trait Trait: generic_asset::Trait + timestamp::Trait + system::Trait {
     /// The overarching event type.
     type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
+    /// prices of assets in USDT, with the decimals the provider gives for each asset
+    type PriceProvider: PriceProvider<Self::AssetId, BlockNumber = Self::BlockNumber>;
     type Call: Parameter
         + Dispatchable<Origin = <Self as system::Trait>::Origin>
         + IsSubType<Module<Self>, Self>;
     type Days: Get<Self::BlockNumber>;
+    /// how long the collateral of a SellCollateral loan stays on auction
+    type AuctionDuration: Get<Self::BlockNumber>;
+    /// pool priority of transactions liquidating loans marked ToBeLiquidated
+    type LiquidationPriority: Get<TransactionPriority>;
+    /// daily rate scaled by INTEREST_RATE_PRECISION that variable rate loans float over
+    type ReferenceRate: Get<u64>;
 }
 
 // This module's storage items.
@@ -90,6 +140,8 @@ decl_storage! {
     trait Store for Module<T: Trait> as P2p {
         /// module level switch
         pub Paused get(paused) : bool = false;
+        /// layout version of borrows, loans and their indices, older layouts are migrated on the next block
+        StorageVersion get(fn storage_version) build(|_: &GenesisConfig<T>| STORAGE_VERSION) : u32;
         /// hold borrowers' collateral temporarily
         pub MoneyPool get(money_pool) config() : T::AccountId;
         /// Platform is just a account receiving potential fees
@@ -98,33 +150,83 @@ decl_storage! {
         pub ChargePenalty get(fn charge_penalty) config() : bool;
         /// liquidation penalty, percentage
         pub LiquidationPenaly get(fn liquidation_penalty) config() : u32;
-        /// liquidator's discount for buying the collateral asset, percentage
+        /// floor price of a collateral auction relative to the collateral's worth, percentage
         pub LiquidatorDiscount get(fn liquidator_discount) config() : u32;
         /// TradingPairs contains all supported trading pairs, oracle should provide price information for all trading pairs.
-        pub TradingPairs get(trading_pairs) config() : Vec<TradingPair<T::AssetId>>;
-        /// LTV must be greater than this value to create a new borrow
-        pub SafeLTV get(safe_ltv) config() : u32;
-        /// a loan will be liquidated when LTV is below this
-        pub LiquidateLTV get(liquidate_ltv) config() : u32;
-        /// minimium borrow terms, count in natural days
-        pub MinBorrowTerms get(min_borrow_terms) config() : u64; // days of our lives
-        /// minimium interest rate
-        pub MinBorrowInterestRate get(min_borrow_interest_rate) config() : u64;
+        pub TradingPairs get(trading_pairs) build(|config: &GenesisConfig<T>| {
+            config.trading_pairs.iter().map(|(tp, _)| tp.clone()).collect::<Vec<_>>()
+        }) : Vec<TradingPair<T::AssetId>>;
+        /// risk parameters of every trading pair ever registered, a pair is never removed but disabled
+        pub TradingPairConfigs get(fn trading_pair_config) build(|config: &GenesisConfig<T>| {
+            config.trading_pairs.iter().map(|(tp, c)| (tp.clone(), Some(c.clone()))).collect::<Vec<_>>()
+        }) : map hasher(twox_64_concat) TradingPair<T::AssetId> => Option<TradingPairConfig>;
+        /// after due, the borrower still has this many natural days to repay the loan with penalty interest
+        pub OverdueGracePeriod get(fn overdue_grace_period) config() : u64; // days of our lives
+        /// penalty interest rate charged for each overdue day, same precision as interest rate
+        pub OverduePenaltyRate get(fn overdue_penalty_rate) config() : u64;
+        /// prices older than this many blocks can't open or liquidate loans, 0 turns the check off
+        pub MaxPriceAge get(fn max_price_age) config() : T::BlockNumber;
+        /// prices are averaged over this many blocks of oracle history, 0 uses the spot price
+        pub PriceTwapWindow get(fn price_twap_window) config() : T::BlockNumber;
         /// borrow id counter
         pub NextBorrowId get(next_borrow_id) : P2PBorrowId = 1;
         /// loan id counter
         pub NextLoanId get(next_loan_id) : P2PLoanId = 1;
 
-        /// an account can only have one available borrow at a time
+        /// dead borrows are checked every this many blocks, 0 turns the check off
+        pub BorrowCheckInterval get(fn borrow_check_interval) config() : T::BlockNumber;
+        /// a new pass over live loans starts every this many blocks, 0 turns the check off
+        pub LoanCheckInterval get(fn loan_check_interval) config() : T::BlockNumber;
+        /// a pass over live loans spends at most this much weight per block, the rest is left for the following blocks
+        pub LoanCheckWeightBudget get(fn loan_check_weight_budget) config() : Weight;
+        /// the next loan id to be checked while a pass over live loans is in progress
+        pub LoanCheckCursor get(fn loan_check_cursor) : Option<P2PLoanId>;
+        /// borrows expiring at a block, buckets are dropped once checked
+        pub BorrowIdsByDeadAfter get(fn borrow_ids_by_dead_after) : map hasher(twox_64_concat) T::BlockNumber => Vec<P2PBorrowId>;
+        /// buckets of BorrowIdsByDeadAfter up to this block have been checked
+        pub BorrowsCheckedUntil get(fn borrows_checked_until) : T::BlockNumber;
+
+        /// an account can have at most this many available borrows at a time
+        pub MaxAvailableBorrowsPerAccount get(fn max_available_borrows_per_account) config() : u32;
+
         pub Borrows get(borrows) : map hasher(twox_64_concat) P2PBorrowId => P2PBorrow<T::AssetId, T::Balance, T::BlockNumber, T::AccountId>;
         pub BorrowIdsByAccountId get(borrow_ids_by_account_id) : map hasher(opaque_blake2_256) T::AccountId => Vec<P2PBorrowId>;
+        /// only borrows waiting to be taken are kept here, so it is bounded by the per account limit
         pub AvailableBorrowIds get(available_borrow_ids) : Vec<P2PBorrowId>;
+        pub AvailableBorrowIdsByAccountId get(fn available_borrow_ids_by_account_id) : map hasher(opaque_blake2_256) T::AccountId => Vec<P2PBorrowId>;
+        /// secondary indices for the listing queries
+        pub BorrowIdsByTradingPair get(fn borrow_ids_by_trading_pair) : map hasher(twox_64_concat) TradingPair<T::AssetId> => Vec<P2PBorrowId>;
+        /// the id is the value too, as a double map can only be iterated by value
+        pub BorrowIdsByStatus : double_map hasher(twox_64_concat) P2PBorrowStatus, hasher(twox_64_concat) P2PBorrowId => Option<P2PBorrowId>;
 
         /// on the other hand, an account can have multiple available loans
         pub Loans get(loans) : map hasher(twox_64_concat) P2PLoanId => P2PLoan<T::AssetId, T::Balance, T::BlockNumber, T::AccountId>;
         pub LoanIdsByAccountId get(loan_ids_by_account_id) : map hasher(opaque_blake2_256) T::AccountId => Vec<P2PLoanId>;
+        pub LoanIdsByBorrowerId get(fn loan_ids_by_borrower_id) : map hasher(opaque_blake2_256) T::AccountId => Vec<P2PLoanId>;
+        pub LoanIdsByTradingPair get(fn loan_ids_by_trading_pair) : map hasher(twox_64_concat) TradingPair<T::AssetId> => Vec<P2PLoanId>;
+        pub LoanIdsByStatus : double_map hasher(twox_64_concat) P2PLoanHealth, hasher(twox_64_concat) P2PLoanId => Option<P2PLoanId>;
         pub AvailableLoanIdsByAccountId get(available_loan_ids_by_account_id) : map hasher(opaque_blake2_256) T::AccountId => Vec<P2PLoanId>;
-        pub AccountIdsWithLiveLoans get(account_ids_with_loans) : Vec<T::AccountId>;
+        /// ids of loans not yet repaid or liquidated, by page of LIVE_LOAN_PAGE_SIZE ids, in ascending order
+        pub LiveLoanIds get(fn live_loan_ids) : map hasher(twox_64_concat) P2PLoanId => Vec<P2PLoanId>;
+
+        /// repayment schedules of live installment loans
+        pub LoanInstallments get(fn loan_installments) : map hasher(twox_64_concat) P2PLoanId => Vec<P2PInstallment<T::Balance, T::BlockNumber>>;
+
+        /// interest accrued by live variable rate loans
+        pub LoanAccruals get(fn loan_accrual) : map hasher(twox_64_concat) P2PLoanId => Option<P2PInterestAccrual<T::Balance, T::BlockNumber>>;
+
+        /// liquidation is held off until this block for margin called loans
+        pub MarginCallDeadlines get(fn margin_call_deadline) : map hasher(twox_64_concat) P2PLoanId => Option<T::BlockNumber>;
+
+        /// repayment history of every borrower
+        pub CreditRecords get(fn credit_record) : map hasher(opaque_blake2_256) T::AccountId => P2PCreditRecord<T::Balance>;
+
+        /// collateral auctions keyed by the loan being liquidated
+        pub Auctions get(fn auctions) : map hasher(twox_64_concat) P2PLoanId => Option<P2PAuction<T::AssetId, T::Balance, T::BlockNumber>>;
+        pub AuctionLoanIds get(fn auction_loan_ids) : Vec<P2PLoanId>;
+    }
+    add_extra_genesis {
+        config(trading_pairs): Vec<(TradingPair<T::AssetId>, TradingPairConfig)>;
     }
 }
 
@@ -132,9 +234,10 @@ decl_error! {
     pub enum Error for Module<T: Trait> {
         Paused,
         MinBorrowTerms,
+        MaxBorrowTerms,
         MinBorrowInterestRate,
         CanNotReserve,
-        MultipleAvailableBorrows,
+        TooManyAvailableBorrows,
         BorrowNotAvailable,
         TradingPairNotAllowed,
         NotOwnerOfBorrow,
@@ -144,6 +247,8 @@ decl_error! {
         InitialCollateralRateFail,
         NotEnoughBalance,
         TradingPairPriceMissing,
+        PriceStale,
+        PriceOnHold,
         BorrowNotLoaned,
         LTVNotMeet,
         ShouldNotBeLiquidated,
@@ -152,7 +257,21 @@ decl_error! {
         AddCollateralNotAllowed,
         FailToReserve,
         CanNotLiquidateYourself,
+        CanNotLendToYourself,
         CanNotCancelBorrow,
+        GracePeriodExpired,
+        NotOwnerOfLoan,
+        LoanNotDefaulted,
+        TradingPairExists,
+        UnknownTradingPair,
+        InvalidTradingPairConfig,
+        UnknownAuction,
+        AuctionEnded,
+        CollateralMustBeAuctioned,
+        MarginCallPending,
+        InstallmentsNeedFixedRate,
+        NoInstallmentDue,
+        MoneyPoolShortOfCollateral,
     }
 }
 
@@ -161,20 +280,39 @@ decl_module! {
     /// The module declaration.
     pub struct Module<T: Trait> for enum Call where origin: T::Origin {
         const LTV_SCALE: u32 = LTV_SCALE;
+        const FEE_RATE_SCALE: u32 = FEE_RATE_SCALE;
         const INTEREST_SCALE: u64 = INTEREST_RATE_PRECISION;
+        const LOAN_CHECK_WEIGHT: Weight = LOAN_CHECK_WEIGHT;
 
         type Error = Error<T>;
 
         fn deposit_event() = default;
 
+        fn on_initialize(block_number: T::BlockNumber) -> Weight {
+            if Self::storage_version() < STORAGE_VERSION {
+                Self::migrate_storage(block_number);
+            }
+            SimpleDispatchInfo::default().weigh_data(())
+        }
+
         fn on_finalize(block_number: T::BlockNumber) {
             if !Self::paused() {
-                if (block_number % 2.into()).is_zero() && !((block_number + 1.into()) % 5.into()).is_zero() {
+                let borrow_check_interval = Self::borrow_check_interval();
+                // an unfinished check goes on regardless of the interval
+                if Self::is_check_due(block_number, borrow_check_interval)
+                    || (!borrow_check_interval.is_zero()
+                        && Self::borrows_checked_until() + borrow_check_interval < block_number)
+                {
                     Self::periodic_check_borrows(block_number);
                 }
-                if ((block_number + 1.into()) % 5.into()).is_zero()  {
+                let loan_check_due = Self::is_check_due(block_number, Self::loan_check_interval());
+                // an unfinished pass goes on regardless of the interval
+                if loan_check_due || Self::loan_check_cursor().is_some() {
                     Self::periodic_check_loans(block_number);
                 }
+                if loan_check_due {
+                    Self::periodic_check_auctions(block_number);
+                }
             }
         }
 
@@ -207,30 +345,101 @@ decl_module! {
         }
 
         #[weight = SimpleDispatchInfo::MaxOperational]
-        pub fn change_safe_ltv(origin, ltv: u32) -> DispatchResult {
+        pub fn add_trading_pair(origin, trading_pair: TradingPair<T::AssetId>, config: TradingPairConfig) -> DispatchResult {
+            ensure_root(origin)?;
+            ensure!(
+                !<TradingPairConfigs<T>>::contains_key(&trading_pair),
+                Error::<T>::TradingPairExists
+            );
+            Self::ensure_trading_pair_config_valid(&config)?;
+            <TradingPairConfigs<T>>::insert(&trading_pair, config);
+            <TradingPairs<T>>::append_or_put(vec![trading_pair.clone()]);
+            Self::deposit_event(RawEvent::TradingPairAdded(trading_pair));
+            Ok(())
+        }
+
+        #[weight = SimpleDispatchInfo::MaxOperational]
+        pub fn update_trading_pair(origin, trading_pair: TradingPair<T::AssetId>, config: TradingPairConfig) -> DispatchResult {
+            ensure_root(origin)?;
+            ensure!(
+                <TradingPairConfigs<T>>::contains_key(&trading_pair),
+                Error::<T>::UnknownTradingPair
+            );
+            Self::ensure_trading_pair_config_valid(&config)?;
+            <TradingPairConfigs<T>>::insert(&trading_pair, config);
+            Self::deposit_event(RawEvent::TradingPairUpdated(trading_pair));
+            Ok(())
+        }
+
+        #[weight = SimpleDispatchInfo::MaxOperational]
+        pub fn disable_trading_pair(origin, trading_pair: TradingPair<T::AssetId>) -> DispatchResult {
+            ensure_root(origin)?;
+            ensure!(
+                <TradingPairConfigs<T>>::contains_key(&trading_pair),
+                Error::<T>::UnknownTradingPair
+            );
+            <TradingPairConfigs<T>>::mutate(&trading_pair, |v| {
+                if let Some(config) = v {
+                    config.enabled = false;
+                }
+            });
+            Self::deposit_event(RawEvent::TradingPairDisabled(trading_pair));
+            Ok(())
+        }
+
+        #[weight = SimpleDispatchInfo::MaxOperational]
+        pub fn change_max_available_borrows_per_account(origin, n: u32) -> DispatchResult {
+            ensure_root(origin)?;
+            MaxAvailableBorrowsPerAccount::put(n);
+            Ok(())
+        }
+
+        #[weight = SimpleDispatchInfo::MaxOperational]
+        pub fn change_borrow_check_interval(origin, interval: T::BlockNumber) -> DispatchResult {
+            ensure_root(origin)?;
+            <BorrowCheckInterval<T>>::put(interval);
+            Ok(())
+        }
+
+        #[weight = SimpleDispatchInfo::MaxOperational]
+        pub fn change_loan_check_interval(origin, interval: T::BlockNumber) -> DispatchResult {
             ensure_root(origin)?;
-            SafeLTV::put(ltv);
+            <LoanCheckInterval<T>>::put(interval);
             Ok(())
         }
 
         #[weight = SimpleDispatchInfo::MaxOperational]
-        pub fn change_liquidate_ltv(origin, ltv: u32) -> DispatchResult {
+        pub fn change_max_price_age(origin, max_age: T::BlockNumber) -> DispatchResult {
             ensure_root(origin)?;
-            LiquidateLTV::put(ltv);
+            <MaxPriceAge<T>>::put(max_age);
             Ok(())
         }
 
         #[weight = SimpleDispatchInfo::MaxOperational]
-        pub fn change_min_borrow_terms(origin, t: u64) -> DispatchResult {
+        pub fn change_price_twap_window(origin, window: T::BlockNumber) -> DispatchResult {
             ensure_root(origin)?;
-            MinBorrowTerms::put(t);
+            <PriceTwapWindow<T>>::put(window);
             Ok(())
         }
 
         #[weight = SimpleDispatchInfo::MaxOperational]
-        pub fn change_min_borrow_interest_rate(origin, r: u64) -> DispatchResult {
+        pub fn change_loan_check_weight_budget(origin, budget: Weight) -> DispatchResult {
             ensure_root(origin)?;
-            MinBorrowInterestRate::put(r);
+            LoanCheckWeightBudget::put(budget);
+            Ok(())
+        }
+
+        #[weight = SimpleDispatchInfo::MaxOperational]
+        pub fn change_overdue_grace_period(origin, days: u64) -> DispatchResult {
+            ensure_root(origin)?;
+            OverdueGracePeriod::put(days);
+            Ok(())
+        }
+
+        #[weight = SimpleDispatchInfo::MaxOperational]
+        pub fn change_overdue_penalty_rate(origin, r: u64) -> DispatchResult {
+            ensure_root(origin)?;
+            OverduePenaltyRate::put(r);
             Ok(())
         }
 
@@ -281,6 +490,30 @@ decl_module! {
             let who = ensure_signed(origin)?;
             Self::repay_loan(who, borrow_id)
         }
+
+        /// pays the next installment of an installment loan, the last one repays the loan
+        #[weight = SimpleDispatchInfo::FixedNormal(5_00_000)]
+        pub fn pay_installment(origin, borrow_id: P2PBorrowId) -> DispatchResult {
+            ensure!(!Self::paused(), Error::<T>::Paused);
+            let who = ensure_signed(origin)?;
+            Self::pay_loan_installment(who, borrow_id)
+        }
+
+        /// after the grace period, the lender of a defaulted loan can take the collateral
+        #[weight = SimpleDispatchInfo::FixedNormal(500_000)]
+        pub fn claim(origin, loan_id: P2PLoanId) -> DispatchResult {
+            ensure!(!Self::paused(), Error::<T>::Paused);
+            let who = ensure_signed(origin)?;
+            Self::claim_collateral(who, loan_id)
+        }
+
+        /// buy the collateral of a SellCollateral loan on auction at the current price
+        #[weight = SimpleDispatchInfo::FixedNormal(500_000)]
+        pub fn bid(origin, loan_id: P2PLoanId) -> DispatchResult {
+            ensure!(!Self::paused(), Error::<T>::Paused);
+            let who = ensure_signed(origin)?;
+            Self::bid_auction(who, loan_id)
+        }
     }
 }
 
@@ -288,10 +521,12 @@ decl_event!(
     #[rustfmt::skip]
     pub enum Event<T>
     where
-        // AccountId = <T as system::Trait>::AccountId,
-        // Balance = <T as generic_asset::Trait>::Balance,
+        AccountId = <T as system::Trait>::AccountId,
+        Balance = <T as generic_asset::Trait>::Balance,
+        BlockNumber = <T as system::Trait>::BlockNumber,
         P2PLoan = P2PLoan<<T as generic_asset::Trait>::AssetId, <T as generic_asset::Trait>::Balance, <T as system::Trait>::BlockNumber, <T as system::Trait>::AccountId>,
         P2PBorrow = P2PBorrow<<T as generic_asset::Trait>::AssetId, <T as generic_asset::Trait>::Balance, <T as system::Trait>::BlockNumber, <T as system::Trait>::AccountId>,
+        TradingPair = TradingPair<<T as generic_asset::Trait>::AssetId>,
     {
         CheckingAvailableBorrows,
         CheckingAvailableLoans,
@@ -303,8 +538,13 @@ decl_event!(
         LoanCreated(P2PLoan),
         LoanLiquidated(P2PLoanId),
         LoanRepaid(P2PLoanId),
+        CollateralClaimed(P2PLoanId),
         CollateralAdded(P2PBorrowId),
 
+        TradingPairAdded(TradingPair),
+        TradingPairUpdated(TradingPair),
+        TradingPairDisabled(TradingPair),
+
         // issue when the current block number is greater than the dead_after of a borrow
         BorrowDied(P2PBorrowId),
 
@@ -313,6 +553,33 @@ decl_event!(
 
         // issue when status of a loan changed from P2PLoanHealth::Well to P2PLoanHealth::ToBeLiquidated
         LoanToBeLiquidated(P2PLoanId),
+
+        // issue when the ltv of a loan falls below margin_call_ltv, loan id and the end of the top up window
+        LoanMarginCalled(P2PLoanId, BlockNumber),
+        // issue when a margin called loan is back above margin_call_ltv
+        MarginCallCleared(P2PLoanId),
+
+        // issue when an overdue loan is still not repaid after the grace period
+        LoanDefaulted(P2PLoanId),
+
+        // loan id and the index of the installment paid
+        InstallmentPaid(P2PLoanId, u32),
+
+        // issue when an overdue loan is repaid with penalty interest
+        OverduePenaltyPaid(P2PLoanId, Balance),
+
+        // fees paid to the platform
+        OriginationFeePaid(P2PLoanId, Balance),
+        InterestFeePaid(P2PLoanId, Balance),
+
+        // issue when the collateral of a SellCollateral loan is put on auction
+        AuctionStarted(P2PLoanId),
+        // loan id, bidder, price paid
+        AuctionSold(P2PLoanId, AccountId, Balance),
+        // nobody bid in time, the collateral goes to the lender
+        AuctionExpired(P2PLoanId),
+        // settled by the periodic check, loan id and the collateral given to the lender
+        LoanAutoLiquidated(P2PLoanId, Balance),
     }
 );
 
@@ -363,13 +630,16 @@ impl<T: Trait> Module<T> {
         let size = size.unwrap_or(10) as usize;
         let mut res = Vec::with_capacity(size);
 
-        for account_id in <AccountIdsWithLiveLoans<T>>::get() {
-            for id in <AvailableLoanIdsByAccountId<T>>::get(&account_id) {
-                res.push(<Loans<T>>::get(&id));
-            }
+        let last_page = Self::next_loan_id() / LIVE_LOAN_PAGE_SIZE;
+        for id in (0..=last_page)
+            .flat_map(LiveLoanIds::get)
+            .skip(offset)
+            .take(size)
+        {
+            res.push(<Loans<T>>::get(id));
         }
 
-        res.into_iter().skip(offset).take(size).collect()
+        res
     }
 
     /// the user's loan list with no rev
@@ -425,6 +695,238 @@ impl<T: Trait> Module<T> {
         res
     }
 
+    /// ongoing collateral auctions, the oldest first
+    pub fn get_auctions(
+        size: Option<u64>,
+        offset: Option<u64>,
+    ) -> Vec<P2PAuction<T::AssetId, T::Balance, T::BlockNumber>> {
+        let offset = offset.unwrap_or(0) as usize;
+        let size = size.unwrap_or(10) as usize;
+
+        AuctionLoanIds::get()
+            .iter()
+            .skip(offset)
+            .take(size)
+            .filter_map(|id| Self::auctions(id))
+            .collect()
+    }
+
+    /// borrows matching the query, candidates are taken from the most selective secondary index
+    /// so Borrows is never iterated. None if the query is sorted by anything but id without an indexed filter.
+    pub fn query_borrows(
+        query: P2PQuery<T::AssetId, T::Balance, T::AccountId, P2PBorrowStatus>,
+    ) -> Option<P2PPage<P2PBorrow<T::AssetId, T::Balance, T::BlockNumber, T::AccountId>>> {
+        let mut indices = vec![];
+        if let Some(trading_pair) = &query.trading_pair {
+            indices.push(<BorrowIdsByTradingPair<T>>::get(trading_pair));
+        }
+        if let Some(status) = &query.status {
+            indices.push(Self::borrow_ids_by_status(status.clone()));
+        }
+        if let Some(who) = &query.account {
+            indices.push(<BorrowIdsByAccountId<T>>::get(who));
+        }
+        let candidates = Self::query_candidates(indices, query.sort_by, Self::next_borrow_id())?;
+
+        Some(Self::paginate(candidates, &query, |id| {
+            let borrow = <Borrows<T>>::get(id);
+            let matched = query.trading_pair.as_ref().map_or(true, |tp| {
+                tp.collateral == borrow.collateral_asset_id && tp.borrow == borrow.borrow_asset_id
+            }) && query.status.as_ref().map_or(true, |s| *s == borrow.status)
+                && query
+                    .account
+                    .as_ref()
+                    .map_or(true, |who| *who == borrow.who)
+                && Self::in_range(borrow.borrow_balance, query.min_amount, query.max_amount)
+                && Self::in_range(
+                    borrow.interest_rate,
+                    query.min_interest_rate,
+                    query.max_interest_rate,
+                )
+                && Self::in_range(borrow.terms, query.min_terms, query.max_terms);
+            if !matched {
+                return None;
+            }
+            let key = Self::sort_key(
+                query.sort_by,
+                id,
+                borrow.borrow_balance,
+                borrow.interest_rate,
+                borrow.terms,
+            );
+            Some((key, borrow))
+        }))
+    }
+
+    /// loans matching the query, the account filter matches both the borrower and the lender
+    pub fn query_loans(
+        query: P2PQuery<T::AssetId, T::Balance, T::AccountId, P2PLoanHealth>,
+    ) -> Option<P2PPage<P2PLoan<T::AssetId, T::Balance, T::BlockNumber, T::AccountId>>> {
+        let mut indices = vec![];
+        if let Some(trading_pair) = &query.trading_pair {
+            indices.push(<LoanIdsByTradingPair<T>>::get(trading_pair));
+        }
+        if let Some(status) = &query.status {
+            indices.push(Self::loan_ids_by_status(*status));
+        }
+        if let Some(who) = &query.account {
+            let mut ids = <LoanIdsByAccountId<T>>::get(who);
+            ids.extend(<LoanIdsByBorrowerId<T>>::get(who));
+            ids.sort();
+            ids.dedup();
+            indices.push(ids);
+        }
+        let candidates = Self::query_candidates(indices, query.sort_by, Self::next_loan_id())?;
+        let need_terms = query.min_terms.is_some()
+            || query.max_terms.is_some()
+            || query.sort_by == P2PSortBy::Terms;
+
+        Some(Self::paginate(candidates, &query, |id| {
+            let loan = <Loans<T>>::get(id);
+            let matched = query.trading_pair.as_ref().map_or(true, |tp| {
+                tp.collateral == loan.collateral_asset_id && tp.borrow == loan.loan_asset_id
+            }) && query.status.as_ref().map_or(true, |s| *s == loan.status)
+                && query.account.as_ref().map_or(true, |who| {
+                    *who == loan.borrower_id || *who == loan.loaner_id
+                })
+                && Self::in_range(loan.loan_balance, query.min_amount, query.max_amount)
+                && Self::in_range(
+                    loan.interest_rate,
+                    query.min_interest_rate,
+                    query.max_interest_rate,
+                );
+            if !matched {
+                return None;
+            }
+            // terms only live in the borrow
+            let terms = if need_terms {
+                <Borrows<T>>::get(loan.borrow_id).terms
+            } else {
+                0
+            };
+            if need_terms && !Self::in_range(terms, query.min_terms, query.max_terms) {
+                return None;
+            }
+            let key = Self::sort_key(
+                query.sort_by,
+                id,
+                loan.loan_balance,
+                loan.interest_rate,
+                terms,
+            );
+            Some((key, loan))
+        }))
+    }
+
+    /// the shortest of the given index lists, or every id ever generated if there is none.
+    /// Without an index only sorting by id is allowed, it stops loading at the end of the page.
+    fn query_candidates(
+        indices: Vec<Vec<u128>>,
+        sort_by: P2PSortBy,
+        next_id: u128,
+    ) -> Option<Vec<u128>> {
+        match indices.into_iter().min_by_key(|ids| ids.len()) {
+            Some(ids) => Some(ids),
+            None if sort_by == P2PSortBy::Id => Some((1..next_id).collect()),
+            None => None,
+        }
+    }
+
+    pub fn borrow_ids_by_status(status: P2PBorrowStatus) -> Vec<P2PBorrowId> {
+        let mut ids = BorrowIdsByStatus::iter_prefix(status).collect::<Vec<_>>();
+        ids.sort();
+        ids
+    }
+
+    pub fn loan_ids_by_status(status: P2PLoanHealth) -> Vec<P2PLoanId> {
+        let mut ids = LoanIdsByStatus::iter_prefix(status).collect::<Vec<_>>();
+        ids.sort();
+        ids
+    }
+
+    fn in_range<V: PartialOrd>(v: V, min: Option<V>, max: Option<V>) -> bool {
+        min.map_or(true, |min| v >= min) && max.map_or(true, |max| v <= max)
+    }
+
+    fn sort_key(
+        sort_by: P2PSortBy,
+        id: u128,
+        amount: T::Balance,
+        interest_rate: u64,
+        terms: u64,
+    ) -> u128 {
+        match sort_by {
+            P2PSortBy::Id => id,
+            P2PSortBy::Amount => TryInto::<u128>::try_into(amount)
+                .ok()
+                .unwrap_or(u128::max_value()),
+            P2PSortBy::InterestRate => interest_rate.into(),
+            P2PSortBy::Terms => terms.into(),
+        }
+    }
+
+    /// orders the matched candidates by (sort key, id) and cuts the page after the cursor,
+    /// when sorting by id only candidates up to the end of the page are loaded.
+    fn paginate<Status, Item>(
+        mut candidates: Vec<u128>,
+        query: &P2PQuery<T::AssetId, T::Balance, T::AccountId, Status>,
+        load: impl Fn(u128) -> Option<(u128, Item)>,
+    ) -> P2PPage<Item> {
+        let size = query.size.unwrap_or(10).max(1) as usize;
+        let desc = query.order == P2PSortOrder::Desc;
+        let after_cursor = |key: u128, id: u128| match query.cursor {
+            None => true,
+            Some(cursor) if desc => (key, id) < (cursor.key, cursor.id),
+            Some(cursor) => (key, id) > (cursor.key, cursor.id),
+        };
+
+        candidates.sort();
+        if desc {
+            candidates.reverse();
+        }
+
+        // one more than the page size tells if there is a next page
+        let mut matched = Vec::with_capacity(size + 1);
+        if query.sort_by == P2PSortBy::Id {
+            for id in candidates {
+                if !after_cursor(id, id) {
+                    continue;
+                }
+                if let Some((key, item)) = load(id) {
+                    matched.push((key, id, item));
+                    if matched.len() > size {
+                        break;
+                    }
+                }
+            }
+        } else {
+            for id in candidates {
+                if let Some((key, item)) = load(id) {
+                    if after_cursor(key, id) {
+                        matched.push((key, id, item));
+                    }
+                }
+            }
+            matched.sort_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)));
+            if desc {
+                matched.reverse();
+            }
+        }
+
+        let next_cursor = if matched.len() > size {
+            let (key, id, _) = &matched[size - 1];
+            Some(P2PCursor { key: *key, id: *id })
+        } else {
+            None
+        };
+        matched.truncate(size);
+
+        P2PPage {
+            items: matched.into_iter().map(|(_, _, item)| item).collect(),
+            next_cursor,
+        }
+    }
+
     fn generate_borrow_id() -> P2PBorrowId {
         let id = Self::next_borrow_id();
         NextBorrowId::mutate(|v| *v += 1);
@@ -441,16 +943,33 @@ impl<T: Trait> Module<T> {
         borrow_asset_id: T::AssetId,
         collateral_asset_id: T::AssetId,
     ) -> Option<TradingPairPrices> {
-        let collateral_price = Self::fetch_price(collateral_asset_id);
-        let borrow_price = Self::fetch_price(borrow_asset_id);
-        if collateral_price.is_some() && borrow_price.is_some() {
-            Some(TradingPairPrices {
-                borrow_asset_price: borrow_price.unwrap(),
-                collateral_asset_price: collateral_price.unwrap(),
-            })
-        } else {
-            None
-        }
+        // only the ratio of the two prices matters, so they are brought to the same decimals
+        let (borrow_asset_price, collateral_asset_price) = at_common_decimals(
+            Self::fetch_price(borrow_asset_id)?,
+            Self::fetch_price(collateral_asset_id)?,
+        )?;
+        Some(TradingPairPrices {
+            borrow_asset_price,
+            collateral_asset_price,
+        })
+    }
+
+    /// prices to open or liquidate a loan with, refuses prices older than `MaxPriceAge` or held by the oracle's circuit breaker
+    pub fn fetch_fresh_trading_pair_prices(
+        borrow_asset_id: T::AssetId,
+        collateral_asset_id: T::AssetId,
+    ) -> Result<TradingPairPrices, Error<T>> {
+        let prices = Self::fetch_trading_pair_prices(borrow_asset_id, collateral_asset_id)
+            .ok_or(Error::<T>::TradingPairPriceMissing)?;
+        ensure!(
+            Self::is_price_fresh(borrow_asset_id) && Self::is_price_fresh(collateral_asset_id),
+            Error::<T>::PriceStale
+        );
+        ensure!(
+            !Self::is_price_held(borrow_asset_id) && !Self::is_price_held(collateral_asset_id),
+            Error::<T>::PriceOnHold
+        );
+        Ok(prices)
     }
 
     pub fn add_collateral(
@@ -463,6 +982,7 @@ impl<T: Trait> Module<T> {
             Error::<T>::UnknownBorrowId
         );
         let borrow = <Borrows<T>>::get(borrow_id);
+        ensure!(borrow.who == who, Error::<T>::NotOwnerOfBorrow);
 
         // different borrow status
         match borrow.status {
@@ -483,6 +1003,11 @@ impl<T: Trait> Module<T> {
                 Ok(())
             }
             P2PBorrowStatus::Taken => {
+                // collateral on auction is no longer the borrower's to top up
+                ensure!(
+                    <Loans<T>>::get(borrow.loan_id.unwrap()).status != P2PLoanHealth::Auctioning,
+                    Error::<T>::AddCollateralNotAllowed
+                );
                 // after been taken, the collateral asset has been transfered into the money pool
                 // so this addition should also go to the pool directly
                 ensure!(
@@ -502,6 +1027,7 @@ impl<T: Trait> Module<T> {
                 <Loans<T>>::mutate(borrow.loan_id.unwrap(), |v| {
                     v.collateral_balance = v.collateral_balance.checked_add(&amount).unwrap();
                 });
+                Self::try_clear_margin_call(borrow.loan_id.unwrap());
                 Self::deposit_event(RawEvent::CollateralAdded(borrow_id));
                 Ok(())
             }
@@ -527,40 +1053,54 @@ impl<T: Trait> Module<T> {
         );
         let loan_id = borrow.loan_id.unwrap();
         let loan = <Loans<T>>::get(loan_id);
-        ensure!(loan.status == P2PLoanHealth::Well, Error::<T>::LoanNotWell);
+        ensure!(
+            loan.status == P2PLoanHealth::Well
+                || loan.status == P2PLoanHealth::Overdue
+                || loan.status == P2PLoanHealth::MarginCall,
+            Error::<T>::LoanNotWell
+        );
+        let trading_pair_config =
+            Self::loan_trading_pair_config(&loan).ok_or(Error::<T>::UnknownTradingPair)?;
+        let block_number = <system::Module<T>>::block_number();
+        ensure!(
+            !Self::is_grace_period_expired(&loan, block_number),
+            Error::<T>::GracePeriodExpired
+        );
 
-        if Self::ltv_meet_liquidation(
-            &trading_pair_prices,
-            loan.loan_balance,
-            loan.collateral_balance,
-        ) {
-            <Loans<T>>::mutate(&loan.id, |v| {
-                v.status = P2PLoanHealth::ToBeLiquidated;
-            });
+        if !Self::is_margin_call_pending(&loan, &trading_pair_config, block_number)
+            && Self::ltv_meet_liquidation(
+                &trading_pair_prices,
+                loan.loan_balance,
+                loan.collateral_balance,
+                trading_pair_config.liquidate_ltv,
+            )
+        {
+            Self::update_loan_status(loan.id, P2PLoanHealth::ToBeLiquidated);
             return Err(Error::<T>::ShouldBeLiquidated.into());
         }
 
-        let expected_interest = Self::calculate_expected_interest(
-            borrow.interest_rate,
-            borrow.terms,
-            borrow.borrow_balance,
-        );
-        let need_to_pay = borrow
-            .borrow_balance
-            .checked_add(&expected_interest)
-            .unwrap();
+        // penalty interest is only charged when repaid during the grace period
+        let overdue_penalty = Self::calculate_overdue_penalty(&loan, block_number);
+        let need_to_pay = Self::calculate_repay_amount(&borrow, &loan, block_number);
         // ensure borrower can afford the expected interest
         ensure!(
             <generic_asset::Module<T>>::free_balance(&borrow.borrow_asset_id, &who) >= need_to_pay,
             Error::<T>::NotEnoughBalance
         );
 
+        // the platform takes its cut of the interest
+        let interest_fee = Self::calculate_interest_fee(
+            &trading_pair_config,
+            Self::calculate_loan_interest(&borrow, &loan, block_number),
+        );
+        let to_loaner = need_to_pay - interest_fee;
+
         // transfer borrowed assert + interest into loaner's account
         <generic_asset::Module<T>>::make_transfer_with_event(
             &borrow.borrow_asset_id,
             &who,
             &loan.loaner_id,
-            need_to_pay,
+            to_loaner,
         )?;
         // transfer former collateralized asset back into borrower's account
         <generic_asset::Module<T>>::make_transfer_with_event(
@@ -574,18 +1114,132 @@ impl<T: Trait> Module<T> {
                 &borrow.borrow_asset_id,
                 &loan.loaner_id,
                 &who,
-                need_to_pay,
+                to_loaner,
             )?;
             Err(err)
         })?;
+        if !interest_fee.is_zero() {
+            <generic_asset::Module<T>>::make_transfer_with_event(
+                &borrow.borrow_asset_id,
+                &who,
+                &Self::platform(),
+                interest_fee,
+            )?;
+            Self::deposit_event(RawEvent::InterestFeePaid(loan_id, interest_fee));
+        }
 
-        Self::repay_cleanup(borrow, loan);
+        Self::repay_cleanup(borrow, loan, block_number);
 
+        if !overdue_penalty.is_zero() {
+            Self::deposit_event(RawEvent::OverduePenaltyPaid(loan_id, overdue_penalty));
+        }
         Self::deposit_event(RawEvent::LoanRepaid(loan_id));
 
         Ok(())
     }
 
+    pub fn pay_loan_installment(who: T::AccountId, borrow_id: P2PBorrowId) -> DispatchResult {
+        let loan_id = Self::validate_pay_installment(&who, borrow_id)?;
+        let installments = Self::loan_installments(loan_id);
+        let index = installments
+            .iter()
+            .position(|installment| !installment.paid)
+            .ok_or(Error::<T>::NoInstallmentDue)?;
+        // the last installment is what repaying the loan costs
+        if index == installments.len() - 1 {
+            return Self::repay_loan(who, borrow_id);
+        }
+
+        let installment = installments[index].clone();
+        let loan = <Loans<T>>::get(loan_id);
+        let trading_pair_config =
+            Self::loan_trading_pair_config(&loan).ok_or(Error::<T>::UnknownTradingPair)?;
+        let block_number = <system::Module<T>>::block_number();
+        let overdue_penalty = Self::calculate_overdue_penalty(&loan, block_number);
+        let interest_fee = Self::calculate_interest_fee(&trading_pair_config, installment.interest);
+        let to_loaner =
+            installment.principal + installment.interest + overdue_penalty - interest_fee;
+        // both legs are checked up front, so the fee transfer can't fail after the lender is paid
+        Self::ensure_can_pay(
+            &loan.loan_asset_id,
+            &who,
+            to_loaner + interest_fee,
+            Error::<T>::NotEnoughBalance,
+        )?;
+
+        <generic_asset::Module<T>>::make_transfer_with_event(
+            &loan.loan_asset_id,
+            &who,
+            &loan.loaner_id,
+            to_loaner,
+        )?;
+        if !interest_fee.is_zero() {
+            <generic_asset::Module<T>>::make_transfer_with_event(
+                &loan.loan_asset_id,
+                &who,
+                &Self::platform(),
+                interest_fee,
+            )?;
+            Self::deposit_event(RawEvent::InterestFeePaid(loan_id, interest_fee));
+        }
+
+        <LoanInstallments<T>>::mutate(loan_id, |v| v[index].paid = true);
+        <Loans<T>>::mutate(loan_id, |v| {
+            v.loan_balance = v.loan_balance - installment.principal;
+        });
+        // catching up on a missed installment makes the loan well again
+        let loan = <Loans<T>>::get(loan_id);
+        if loan.status == P2PLoanHealth::Overdue && block_number <= Self::current_due(&loan) {
+            Self::update_loan_status(loan_id, P2PLoanHealth::Well);
+        }
+
+        if !overdue_penalty.is_zero() {
+            Self::deposit_event(RawEvent::OverduePenaltyPaid(loan_id, overdue_penalty));
+        }
+        Self::deposit_event(RawEvent::InstallmentPaid(loan_id, index as u32));
+        Ok(())
+    }
+
+    pub fn calculate_origination_fee(config: &TradingPairConfig, amount: T::Balance) -> T::Balance {
+        amount * config.origination_fee_rate.into() / FEE_RATE_SCALE.into()
+    }
+
+    pub fn calculate_interest_fee(config: &TradingPairConfig, interest: T::Balance) -> T::Balance {
+        interest * config.interest_fee_rate.into() / FEE_RATE_SCALE.into()
+    }
+
+    /// the all-in cost of a borrow under the current config of its trading pair
+    pub fn quote_borrow_cost(
+        trading_pair: &TradingPair<T::AssetId>,
+        amount: T::Balance,
+        terms: u64,
+        interest_rate: u64,
+    ) -> Option<P2PBorrowQuote<T::Balance>> {
+        let config = Self::trading_pair_config(trading_pair)?;
+        let interest = Self::calculate_expected_interest(interest_rate, terms, amount);
+        let origination_fee = Self::calculate_origination_fee(&config, amount);
+        Some(P2PBorrowQuote {
+            interest,
+            origination_fee,
+            interest_fee: Self::calculate_interest_fee(&config, interest),
+            total_cost: interest + origination_fee,
+        })
+    }
+
+    /// principal, interest and overdue penalty if any
+    pub fn calculate_repay_amount(
+        borrow: &P2PBorrow<T::AssetId, T::Balance, T::BlockNumber, T::AccountId>,
+        loan: &P2PLoan<T::AssetId, T::Balance, T::BlockNumber, T::AccountId>,
+        block_number: T::BlockNumber,
+    ) -> T::Balance {
+        let expected_interest = Self::calculate_loan_interest(borrow, loan, block_number);
+        loan.loan_balance
+            .checked_add(&expected_interest)
+            .unwrap()
+            .checked_add(&Self::calculate_overdue_penalty(loan, block_number))
+            .unwrap()
+    }
+
     pub fn calculate_expected_interest(
         interest_rate: u64,
         terms: u64,
@@ -601,48 +1255,242 @@ impl<T: Trait> Module<T> {
                 .unwrap()
     }
 
-    pub fn create_borrow(
-        who: T::AccountId,
-        collateral_balance: T::Balance,
-        trading_pair: TradingPair<T::AssetId>,
-        borrow_options: P2PBorrowOptions<T::Balance, T::BlockNumber>,
-    ) -> DispatchResult {
-        ensure!(
-            borrow_options.terms >= Self::min_borrow_terms(),
-            Error::<T>::MinBorrowTerms
-        );
-        ensure!(
-            borrow_options.interest_rate >= Self::min_borrow_interest_rate(),
-            Error::<T>::MinBorrowInterestRate
-        );
-        ensure!(
-            Self::is_trading_pair_allowed(&trading_pair),
-            Error::<T>::TradingPairNotAllowed
-        );
-        // ensure one user can only have one borrow available at a time
-        if let Some(id) = Self::borrow_ids_by_account_id(&who).last() {
-            ensure!(
-                !Self::available_borrow_ids().contains(id),
-                Error::<T>::MultipleAvailableBorrows
-            );
+    /// fixed rate loans owe the interest of the whole term or of their unpaid installments,
+    /// variable rate loans what has accrued so far
+    pub fn calculate_loan_interest(
+        borrow: &P2PBorrow<T::AssetId, T::Balance, T::BlockNumber, T::AccountId>,
+        loan: &P2PLoan<T::AssetId, T::Balance, T::BlockNumber, T::AccountId>,
+        block_number: T::BlockNumber,
+    ) -> T::Balance {
+        match loan.rate_type {
+            P2PRateType::Fixed if loan.repayment != P2PRepaymentSchedule::Bullet => {
+                Self::loan_installments(loan.id)
+                    .iter()
+                    .filter(|installment| !installment.paid)
+                    .fold(Zero::zero(), |acc, installment| acc + installment.interest)
+            }
+            P2PRateType::Fixed => Self::calculate_expected_interest(
+                borrow.interest_rate,
+                borrow.terms,
+                borrow.borrow_balance,
+            ),
+            P2PRateType::Variable => Self::calculate_accrued_interest(loan, block_number),
         }
-        // ensure essential price info is provided
-        let trading_pair_prices =
-            Self::fetch_trading_pair_prices(trading_pair.borrow, trading_pair.collateral)
-                .ok_or(Error::<T>::TradingPairPriceMissing)?;
+    }
 
-        // collateral - expected_interest meet safty ltv
-        // let expected_interest = Self::calculate_expected_interest(
-        //     borrow_options.interest_rate,
-        //     borrow_options.terms,
-        //     borrow_options.amount,
-        // );
+    /// the daily rate a loan of the given type and interest_rate is charged right now
+    pub fn current_interest_rate(rate_type: P2PRateType, interest_rate: u64) -> u64 {
+        match rate_type {
+            P2PRateType::Fixed => interest_rate,
+            P2PRateType::Variable => T::ReferenceRate::get().saturating_add(interest_rate),
+        }
+    }
+
+    /// the daily rate a loan is charged, variable rate loans use the last sampled rate
+    pub fn loan_interest_rate(
+        loan: &P2PLoan<T::AssetId, T::Balance, T::BlockNumber, T::AccountId>,
+    ) -> u64 {
+        Self::loan_accrual(loan.id).map_or(loan.interest_rate, |accrual| accrual.rate)
+    }
+
+    /// interest of amount at a daily rate over a number of blocks
+    pub fn calculate_block_interest(
+        interest_rate: u64,
+        blocks: T::BlockNumber,
+        amount: T::Balance,
+    ) -> T::Balance {
+        let blocks = TryInto::<u64>::try_into(blocks).ok().unwrap();
+        let days = TryInto::<u64>::try_into(T::Days::get()).ok().unwrap();
+        <T::Balance as TryFrom<u64>>::try_from(interest_rate)
+            .ok()
+            .unwrap()
+            * <T::Balance as TryFrom<u64>>::try_from(blocks).ok().unwrap()
+            * amount
+            / (<T::Balance as TryFrom<u64>>::try_from(INTEREST_RATE_PRECISION)
+                .ok()
+                .unwrap()
+                * <T::Balance as TryFrom<u64>>::try_from(days).ok().unwrap())
+    }
+
+    pub fn calculate_accrued_interest(
+        loan: &P2PLoan<T::AssetId, T::Balance, T::BlockNumber, T::AccountId>,
+        block_number: T::BlockNumber,
+    ) -> T::Balance {
+        match Self::loan_accrual(loan.id) {
+            Some(accrual) => {
+                accrual.interest
+                    + Self::calculate_block_interest(
+                        accrual.rate,
+                        block_number.saturating_sub(accrual.accrued_at),
+                        loan.loan_balance,
+                    )
+            }
+            None => Zero::zero(),
+        }
+    }
+
+    /// books the interest accrued so far and samples a new rate from the reference
+    fn accrue_interest(
+        loan: &P2PLoan<T::AssetId, T::Balance, T::BlockNumber, T::AccountId>,
+        block_number: T::BlockNumber,
+    ) {
+        if Self::loan_accrual(loan.id).is_none() {
+            return;
+        }
+        let accrual = P2PInterestAccrual {
+            rate: Self::current_interest_rate(loan.rate_type, loan.interest_rate),
+            interest: Self::calculate_accrued_interest(loan, block_number),
+            accrued_at: block_number,
+        };
+        <LoanAccruals<T>>::insert(loan.id, accrual);
+    }
+
+    /// penalty interest accrues for every started overdue day, but never beyond the grace period
+    pub fn calculate_overdue_penalty(
+        loan: &P2PLoan<T::AssetId, T::Balance, T::BlockNumber, T::AccountId>,
+        block_number: T::BlockNumber,
+    ) -> T::Balance {
+        let due = Self::current_due(loan);
+        if block_number <= due {
+            return Zero::zero();
+        }
+        let days = T::Days::get();
+        let overdue_blocks = block_number - due;
+        let mut overdue_days = overdue_blocks / days;
+        if !(overdue_blocks % days).is_zero() {
+            overdue_days += One::one();
+        }
+        let overdue_days = TryInto::<u64>::try_into(overdue_days)
+            .unwrap_or(u64::max_value())
+            .min(Self::overdue_grace_period());
+
+        Self::calculate_expected_interest(
+            Self::overdue_penalty_rate(),
+            overdue_days,
+            loan.loan_balance,
+        )
+    }
+
+    /// the due of the first unpaid installment, a missed installment makes the loan overdue
+    pub fn current_due(
+        loan: &P2PLoan<T::AssetId, T::Balance, T::BlockNumber, T::AccountId>,
+    ) -> T::BlockNumber {
+        if loan.repayment == P2PRepaymentSchedule::Bullet {
+            return loan.due;
+        }
+        Self::loan_installments(loan.id)
+            .iter()
+            .find(|installment| !installment.paid)
+            .map_or(loan.due, |installment| installment.due)
+    }
+
+    /// equal principal every period, the last installment takes what is left and ends at due
+    pub fn installment_schedule(
+        borrow: &P2PBorrow<T::AssetId, T::Balance, T::BlockNumber, T::AccountId>,
+        start: T::BlockNumber,
+    ) -> Vec<P2PInstallment<T::Balance, T::BlockNumber>> {
+        let period = match borrow.repayment.period() {
+            Some(period) => period,
+            None => return Vec::new(),
+        };
+        let count = (borrow.terms + period - 1) / period;
+        let principal =
+            borrow.borrow_balance / <T::Balance as TryFrom<u64>>::try_from(count).ok().unwrap();
+        let mut outstanding = borrow.borrow_balance;
+        let mut last_day = 0u64;
+        (1..=count)
+            .map(|n| {
+                let day = (n * period).min(borrow.terms);
+                let installment = P2PInstallment {
+                    due: start
+                        + T::Days::get()
+                            * <T::BlockNumber as TryFrom<u64>>::try_from(day)
+                                .ok()
+                                .unwrap(),
+                    principal: if n == count { outstanding } else { principal },
+                    interest: Self::calculate_expected_interest(
+                        borrow.interest_rate,
+                        day - last_day,
+                        outstanding,
+                    ),
+                    paid: false,
+                };
+                outstanding -= installment.principal;
+                last_day = day;
+                installment
+            })
+            .collect()
+    }
+
+    /// the last block at which an overdue loan can still be repaid
+    pub fn grace_period_end(
+        loan: &P2PLoan<T::AssetId, T::Balance, T::BlockNumber, T::AccountId>,
+    ) -> T::BlockNumber {
+        Self::current_due(loan).saturating_add(
+            T::Days::get()
+                * <T::BlockNumber as TryFrom<u64>>::try_from(Self::overdue_grace_period())
+                    .ok()
+                    .unwrap(),
+        )
+    }
+
+    pub fn is_grace_period_expired(
+        loan: &P2PLoan<T::AssetId, T::Balance, T::BlockNumber, T::AccountId>,
+        block_number: T::BlockNumber,
+    ) -> bool {
+        block_number > Self::grace_period_end(loan)
+    }
+
+    pub fn create_borrow(
+        who: T::AccountId,
+        collateral_balance: T::Balance,
+        trading_pair: TradingPair<T::AssetId>,
+        borrow_options: P2PBorrowOptions<T::Balance, T::BlockNumber>,
+    ) -> DispatchResult {
+        ensure!(
+            Self::is_trading_pair_allowed(&trading_pair),
+            Error::<T>::TradingPairNotAllowed
+        );
+        let trading_pair_config = Self::trading_pair_config(&trading_pair).unwrap();
+        ensure!(
+            borrow_options.terms >= trading_pair_config.min_borrow_terms,
+            Error::<T>::MinBorrowTerms
+        );
+        ensure!(
+            borrow_options.terms <= trading_pair_config.max_borrow_terms,
+            Error::<T>::MaxBorrowTerms
+        );
+        ensure!(
+            Self::current_interest_rate(borrow_options.rate_type, borrow_options.interest_rate)
+                >= trading_pair_config.min_borrow_interest_rate,
+            Error::<T>::MinBorrowInterestRate
+        );
+        ensure!(
+            borrow_options.repayment == P2PRepaymentSchedule::Bullet
+                || borrow_options.rate_type == P2PRateType::Fixed,
+            Error::<T>::InstallmentsNeedFixedRate
+        );
+        ensure!(
+            Self::can_make_borrow(&who),
+            Error::<T>::TooManyAvailableBorrows
+        );
+        // ensure essential price info is provided
+        let trading_pair_prices =
+            Self::fetch_fresh_trading_pair_prices(trading_pair.borrow, trading_pair.collateral)?;
+
+        // collateral - expected_interest meet safty ltv
+        // let expected_interest = Self::calculate_expected_interest(
+        //     borrow_options.interest_rate,
+        //     borrow_options.terms,
+        //     borrow_options.amount,
+        // );
 
         ensure!(
             Self::ltv_meet_safty(
                 &trading_pair_prices,
                 borrow_options.amount,
-                collateral_balance
+                collateral_balance,
+                Self::initial_safe_ltv(&trading_pair_config, &who)
             ),
             Error::<T>::InitialCollateralRateFail
         );
@@ -669,11 +1517,20 @@ impl<T: Trait> Module<T> {
             } else {
                 None
             },
+            liquidation_type: borrow_options.liquidation_type,
+            rate_type: borrow_options.rate_type,
+            repayment: borrow_options.repayment,
             loan_id: None,
         };
         <Borrows<T>>::insert(&borrow_id, b.clone());
+        if let Some(dead_after) = b.dead_after {
+            <BorrowIdsByDeadAfter<T>>::append_or_insert(dead_after, vec![borrow_id.clone()]);
+        }
         AvailableBorrowIds::append_or_put(vec![borrow_id.clone()]);
+        <AvailableBorrowIdsByAccountId<T>>::append_or_insert(&who, vec![borrow_id.clone()]);
         <BorrowIdsByAccountId<T>>::append_or_insert(&who, vec![borrow_id.clone()]);
+        <BorrowIdsByTradingPair<T>>::append_or_insert(&trading_pair, vec![borrow_id.clone()]);
+        BorrowIdsByStatus::insert(&b.status, borrow_id, borrow_id);
 
         Self::deposit_event(RawEvent::BorrowCreated(b));
         Ok(())
@@ -688,16 +1545,15 @@ impl<T: Trait> Module<T> {
             <BorrowIdsByAccountId<T>>::get(&who).contains(&borrow_id),
             Error::<T>::NotOwnerOfBorrow
         );
-        ensure!(
-            AvailableBorrowIds::get().contains(&borrow_id),
-            Error::<T>::BorrowNotAvailable
-        );
-
         let borrow = <Borrows<T>>::get(borrow_id);
         ensure!(
             borrow.status == P2PBorrowStatus::Available,
             Error::<T>::CanNotCancelBorrow
         );
+        ensure!(
+            Self::available_borrow_ids_by_account_id(&who).contains(&borrow_id),
+            Error::<T>::BorrowNotAvailable
+        );
         <generic_asset::Module<T>>::unreserve(
             &borrow.collateral_asset_id,
             &who,
@@ -705,16 +1561,8 @@ impl<T: Trait> Module<T> {
             Some(borrow.lock_id),
         )?;
 
-        AvailableBorrowIds::mutate(|v| {
-            *v = v
-                .clone()
-                .into_iter()
-                .filter(|v| *v != borrow_id)
-                .collect::<Vec<_>>();
-        });
-        <Borrows<T>>::mutate(borrow_id, |v| {
-            v.status = P2PBorrowStatus::Canceled;
-        });
+        Self::remove_available_borrow(&who, borrow_id);
+        Self::update_borrow_status(borrow_id, P2PBorrowStatus::Canceled);
 
         Self::deposit_event(RawEvent::BorrowCanceled(borrow_id));
         Ok(())
@@ -722,6 +1570,8 @@ impl<T: Trait> Module<T> {
 
     pub fn create_loan(loaner: T::AccountId, borrow_id: P2PBorrowId) -> DispatchResult {
         let borrow = Self::ensure_borrow_available_for_loan(borrow_id)?;
+        // lending to oneself would build up a credit record for free
+        ensure!(loaner != borrow.who, Error::<T>::CanNotLendToYourself);
 
         // get collateral amount from locked balance
         // to make sure that amount of asset is indeed reserved
@@ -736,28 +1586,80 @@ impl<T: Trait> Module<T> {
                 return Err(Error::<T>::NoLockedBalance.into());
             }
             Some(collateral_balance) => {
+                // every check comes before the first transfer and the transfers before any storage write,
+                // so a failure leaves neither funds moved nor a loan behind
+                Self::ensure_can_pay(
+                    &borrow.borrow_asset_id,
+                    &loaner,
+                    borrow.borrow_balance,
+                    Error::<T>::NotEnoughBalance,
+                )?;
                 ensure!(
-                    <generic_asset::Module<T>>::free_balance(&borrow.borrow_asset_id, &loaner)
-                        >= borrow.borrow_balance,
-                    Error::<T>::NotEnoughBalance
+                    <generic_asset::Module<T>>::reserved_balance(
+                        &borrow.collateral_asset_id,
+                        &borrow.who
+                    ) >= collateral_balance,
+                    Error::<T>::NoLockedBalance
                 );
                 debug::info!("enough balance");
 
-                let trading_pair_prices = Self::fetch_trading_pair_prices(
+                let trading_pair = TradingPair {
+                    collateral: borrow.collateral_asset_id,
+                    borrow: borrow.borrow_asset_id,
+                };
+                ensure!(
+                    Self::is_trading_pair_allowed(&trading_pair),
+                    Error::<T>::TradingPairNotAllowed
+                );
+                let trading_pair_config = Self::trading_pair_config(&trading_pair).unwrap();
+
+                let trading_pair_prices = Self::fetch_fresh_trading_pair_prices(
                     borrow.borrow_asset_id,
                     borrow.collateral_asset_id,
-                )
-                .ok_or(Error::<T>::TradingPairPriceMissing)?;
+                )?;
                 ensure!(
                     Self::ltv_meet_safty(
                         &trading_pair_prices,
                         borrow.borrow_balance,
-                        collateral_balance
+                        collateral_balance,
+                        Self::initial_safe_ltv(&trading_pair_config, &borrow.who)
                     ),
                     Error::<T>::InitialCollateralRateFail
                 );
                 debug::info!("meet init collateral rate");
 
+                // unreserve the locked balance
+                <generic_asset::Module<T>>::unreserve(
+                    &borrow.collateral_asset_id,
+                    &borrow.who,
+                    collateral_balance,
+                    Some(borrow.lock_id),
+                )?;
+                // transfer the collateral balance into money pool
+                <generic_asset::Module<T>>::make_transfer_with_event(
+                    &borrow.collateral_asset_id,
+                    &borrow.who,
+                    &<MoneyPool<T>>::get(),
+                    collateral_balance,
+                )?;
+                // transfer loan into borrower's account, less the origination fee
+                let origination_fee =
+                    Self::calculate_origination_fee(&trading_pair_config, borrow.borrow_balance);
+                <generic_asset::Module<T>>::make_transfer_with_event(
+                    &borrow.borrow_asset_id,
+                    &loaner,
+                    &borrow.who,
+                    borrow.borrow_balance - origination_fee,
+                )?;
+                if !origination_fee.is_zero() {
+                    <generic_asset::Module<T>>::make_transfer_with_event(
+                        &borrow.borrow_asset_id,
+                        &loaner,
+                        &Self::platform(),
+                        origination_fee,
+                    )?;
+                }
+
                 let current_block_number = <system::Module<T>>::block_number();
 
                 // generate a loan
@@ -777,46 +1679,46 @@ impl<T: Trait> Module<T> {
                     loan_balance: borrow.borrow_balance,
                     status: P2PLoanHealth::Well,
                     interest_rate: borrow.interest_rate,
-                    liquidation_type: Default::default(),
+                    liquidation_type: borrow.liquidation_type,
+                    rate_type: borrow.rate_type,
+                    repayment: borrow.repayment,
                 };
 
                 let loan_id = loan.id;
                 <Loans<T>>::insert(loan_id, loan.clone());
                 <LoanIdsByAccountId<T>>::append_or_insert(&loaner, vec![loan_id]);
                 <AvailableLoanIdsByAccountId<T>>::append_or_insert(&loaner, vec![loan_id]);
-
-                let lenders = <AccountIdsWithLiveLoans<T>>::get();
-                if !lenders.contains(&loaner) {
-                    <AccountIdsWithLiveLoans<T>>::append_or_put(vec![loaner.clone()]);
+                <LoanIdsByBorrowerId<T>>::append_or_insert(&borrow.who, vec![loan_id]);
+                <LoanIdsByTradingPair<T>>::append_or_insert(&trading_pair, vec![loan_id]);
+                LoanIdsByStatus::insert(&loan.status, loan_id, loan_id);
+                // loan ids are increasing, so appending keeps the page sorted
+                LiveLoanIds::append_or_insert(loan_id / LIVE_LOAN_PAGE_SIZE, vec![loan_id]);
+                if loan.repayment != P2PRepaymentSchedule::Bullet {
+                    <LoanInstallments<T>>::insert(
+                        loan_id,
+                        Self::installment_schedule(&borrow, current_block_number),
+                    );
+                }
+                if loan.rate_type == P2PRateType::Variable {
+                    <LoanAccruals<T>>::insert(
+                        loan_id,
+                        P2PInterestAccrual {
+                            rate: Self::current_interest_rate(loan.rate_type, loan.interest_rate),
+                            interest: Zero::zero(),
+                            accrued_at: current_block_number,
+                        },
+                    );
+                }
+                if !origination_fee.is_zero() {
+                    Self::deposit_event(RawEvent::OriginationFeePaid(loan_id, origination_fee));
                 }
-
-                // unreserve the locked balance
-                <generic_asset::Module<T>>::unreserve(
-                    &borrow.collateral_asset_id,
-                    &borrow.who,
-                    collateral_balance,
-                    Some(borrow.lock_id),
-                )?;
-                // transfer the collateral balance into money pool
-                <generic_asset::Module<T>>::make_transfer_with_event(
-                    &borrow.collateral_asset_id,
-                    &borrow.who,
-                    &<MoneyPool<T>>::get(),
-                    collateral_balance,
-                )?;
-                // transfer loan into borrower's account
-                <generic_asset::Module<T>>::make_transfer_with_event(
-                    &borrow.borrow_asset_id,
-                    &loaner,
-                    &borrow.who,
-                    borrow.borrow_balance,
-                )?;
 
                 // mark borrow taken and save the borrow
                 <Borrows<T>>::mutate(&borrow_id, |v| {
-                    v.status = P2PBorrowStatus::Taken;
                     v.loan_id = Some(loan_id);
                 });
+                Self::update_borrow_status(borrow_id, P2PBorrowStatus::Taken);
+                Self::remove_available_borrow(&borrow.who, borrow_id);
 
                 Self::deposit_event(RawEvent::LoanCreated(loan));
 
@@ -829,6 +1731,7 @@ impl<T: Trait> Module<T> {
         prices: &TradingPairPrices,
         borrow_balance: T::Balance,
         collateral_balance: T::Balance,
+        liquidate_ltv: u32,
     ) -> bool {
         (<T::Balance as TryFrom<u64>>::try_from(prices.collateral_asset_price)
             .ok()
@@ -839,13 +1742,14 @@ impl<T: Trait> Module<T> {
                 .ok()
                 .unwrap()
                 * borrow_balance)
-            <= Self::liquidate_ltv().into()
+            <= liquidate_ltv.into()
     }
 
     pub fn ltv_meet_safty(
         prices: &TradingPairPrices,
         borrow_balance: T::Balance,
         collateral_balance: T::Balance,
+        safe_ltv: u32,
     ) -> bool {
         (<T::Balance as TryFrom<u64>>::try_from(prices.collateral_asset_price)
             .ok()
@@ -856,7 +1760,7 @@ impl<T: Trait> Module<T> {
                 .ok()
                 .unwrap()
                 * borrow_balance)
-            >= Self::safe_ltv().into()
+            >= safe_ltv.into()
     }
 
     pub fn liquidate_loan(liquidator: T::AccountId, loan_id: P2PLoanId) -> DispatchResult {
@@ -864,7 +1768,9 @@ impl<T: Trait> Module<T> {
         ensure!(
             loan.status == P2PLoanHealth::Overdue
                 || loan.status == P2PLoanHealth::Well
-                || loan.status == P2PLoanHealth::ToBeLiquidated,
+                || loan.status == P2PLoanHealth::ToBeLiquidated
+                || loan.status == P2PLoanHealth::Defaulted
+                || loan.status == P2PLoanHealth::MarginCall,
             Error::<T>::ShouldNotBeLiquidated
         );
         // borrower is not allowed to liquidate his own borrows on purpose
@@ -873,148 +1779,227 @@ impl<T: Trait> Module<T> {
             Error::<T>::CanNotLiquidateYourself
         );
 
+        let trading_pair_config =
+            Self::loan_trading_pair_config(&loan).ok_or(Error::<T>::UnknownTradingPair)?;
         let trading_pair_prices =
-            Self::fetch_trading_pair_prices(loan.loan_asset_id, loan.collateral_asset_id)
-                .ok_or(Error::<T>::TradingPairPriceMissing)?;
-        if loan.status != P2PLoanHealth::Overdue {
+            Self::fetch_fresh_trading_pair_prices(loan.loan_asset_id, loan.collateral_asset_id)?;
+        // overdue loans within the grace period can only be liquidated for their ltv
+        let block_number = <system::Module<T>>::block_number();
+        if loan.status != P2PLoanHealth::Defaulted
+            && !(loan.status == P2PLoanHealth::Overdue
+                && Self::is_grace_period_expired(&loan, block_number))
+        {
+            ensure!(
+                !Self::is_margin_call_pending(&loan, &trading_pair_config, block_number),
+                Error::<T>::MarginCallPending
+            );
             ensure!(
                 Self::ltv_meet_liquidation(
                     &trading_pair_prices,
                     loan.loan_balance,
-                    loan.collateral_balance
+                    loan.collateral_balance,
+                    trading_pair_config.liquidate_ltv
                 ),
                 Error::<T>::LTVNotMeet
             );
         }
 
-        // no matter what, the expected interest should be fixed and given to loaner
+        match loan.liquidation_type {
+            LiquidationType::JustCollateral => {
+                Self::give_collateral_to_lender(&loan)?;
+                Self::liquidation_cleanup(loan);
+                Self::deposit_event(RawEvent::LoanLiquidated(loan_id));
+            }
+            LiquidationType::SellCollateral => {
+                Self::start_auction(loan, &trading_pair_prices, block_number);
+                Self::deposit_event(RawEvent::AuctionStarted(loan_id));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// no matter what, the expected interest should be fixed and given to loaner
+    fn loan_debt(
+        loan: &P2PLoan<T::AssetId, T::Balance, T::BlockNumber, T::AccountId>,
+        block_number: T::BlockNumber,
+    ) -> T::Balance {
         let borrow = <Borrows<T>>::get(loan.borrow_id);
-        let expected_interest = Self::calculate_expected_interest(
-            borrow.interest_rate,
-            borrow.terms,
-            borrow.borrow_balance,
-        );
-        let need_to_pay = loan.loan_balance + expected_interest;
+        let expected_interest = Self::calculate_loan_interest(&borrow, loan, block_number);
+        loan.loan_balance + expected_interest + Self::calculate_overdue_penalty(loan, block_number)
+    }
+
+    fn give_collateral_to_lender(
+        loan: &P2PLoan<T::AssetId, T::Balance, T::BlockNumber, T::AccountId>,
+    ) -> DispatchResult {
+        <generic_asset::Module<T>>::make_transfer_with_event(
+            &loan.collateral_asset_id,
+            &Self::money_pool(),
+            &loan.loaner_id,
+            loan.collateral_balance,
+        )
+    }
+
+    /// the auction starts at the collateral's oracle worth and drops to LiquidatorDiscount of it
+    fn start_auction(
+        loan: P2PLoan<T::AssetId, T::Balance, T::BlockNumber, T::AccountId>,
+        prices: &TradingPairPrices,
+        block_number: T::BlockNumber,
+    ) {
         let collateral_worth =
-            <T::Balance as TryFrom<u64>>::try_from(trading_pair_prices.collateral_asset_price)
+            <T::Balance as TryFrom<u64>>::try_from(prices.collateral_asset_price)
                 .ok()
                 .unwrap()
                 * loan.collateral_balance
-                / <T::Balance as TryFrom<u64>>::try_from(trading_pair_prices.borrow_asset_price)
+                / <T::Balance as TryFrom<u64>>::try_from(prices.borrow_asset_price)
                     .ok()
                     .unwrap();
+        let auction = P2PAuction {
+            loan_id: loan.id,
+            collateral_asset_id: loan.collateral_asset_id,
+            collateral_balance: loan.collateral_balance,
+            bid_asset_id: loan.loan_asset_id,
+            start_price: collateral_worth,
+            floor_price: collateral_worth * Self::liquidator_discount().into() / 100u32.into(),
+            debt: Self::loan_debt(&loan, block_number),
+            start_at: block_number,
+            end_at: block_number + T::AuctionDuration::get(),
+        };
 
-        // collateral_net_worth is the price that we sell to liquidator
-        let collateral_net_worth: T::Balance =
-            collateral_worth * Self::liquidator_discount().into() / 100u32.into();
+        <Auctions<T>>::insert(loan.id, auction);
+        AuctionLoanIds::append_or_put(vec![loan.id]);
+        Self::update_loan_status(loan.id, P2PLoanHealth::Auctioning);
+    }
 
-        // make sure the liquidator has enough to buy the collateral with a decent discount
-        ensure!(
-            <generic_asset::Module<T>>::free_balance(&loan.loan_asset_id, &liquidator)
-                >= collateral_net_worth,
-            Error::<T>::NotEnoughBalance
-        );
+    pub fn auction_price(
+        auction: &P2PAuction<T::AssetId, T::Balance, T::BlockNumber>,
+        block_number: T::BlockNumber,
+    ) -> T::Balance {
+        if block_number >= auction.end_at {
+            return auction.floor_price;
+        }
+        let elapsed = TryInto::<u64>::try_into(block_number - auction.start_at)
+            .ok()
+            .unwrap();
+        let duration = TryInto::<u64>::try_into(auction.end_at - auction.start_at)
+            .ok()
+            .unwrap();
+        auction.start_price
+            - (auction.start_price - auction.floor_price)
+                * <T::Balance as TryFrom<u64>>::try_from(elapsed)
+                    .ok()
+                    .unwrap()
+                / <T::Balance as TryFrom<u64>>::try_from(duration)
+                    .ok()
+                    .unwrap()
+    }
+
+    pub fn bid_auction(bidder: T::AccountId, loan_id: P2PLoanId) -> DispatchResult {
+        // every leg of the settlement is checked here, so none of the transfers below can fail
+        Self::validate_bid(&bidder, loan_id)?;
+        let auction = Self::auctions(loan_id).ok_or(Error::<T>::UnknownAuction)?;
+        let loan = <Loans<T>>::get(loan_id);
+        let block_number = <system::Module<T>>::block_number();
+        let price = Self::auction_price(&auction, block_number);
 
-        if need_to_pay >= collateral_net_worth {
-            // TODO:: consider doing nothing
-            //
-            // move 95% of collateral to loaner and give 5% to liquidator
-            // let balance_to_loaner = loan.collateral_balance * 95.into() / 100.into();
-            // let balance_to_liquidator = loan.collateral_balance - balance_to_loaner;
-            // <generic_asset::Module<T>>::make_transfer_with_event(
-            //     &loan.collateral_asset_id,
-            //     &Self::money_pool(),
-            //     &loan.loaner_id,
-            //     balance_to_loaner,
-            // )?;
-            // <generic_asset::Module<T>>::make_transfer_with_event(
-            //     &loan.collateral_asset_id,
-            //     &Self::money_pool(),
-            //     &liquidator,
-            //     balance_to_liquidator,
-            // )
-            // .or_else(|err| -> DispatchResult {
-            //     <generic_asset::Module<T>>::make_transfer_with_event(
-            //         &loan.collateral_asset_id,
-            //         &loan.loaner_id,
-            //         &Self::money_pool(),
-            //         balance_to_loaner,
-            //     )?;
-            //     Err(err)
-            // })?;
+        // proceeds repay the lender first
+        let to_lender = price.min(auction.debt);
+        let rest = price - to_lender;
+        let penalty: T::Balance = if Self::charge_penalty() {
+            rest * Self::liquidation_penalty().into() / 100u32.into()
         } else {
-            // first exchange with the liquidator
-            <generic_asset::Module<T>>::make_transfer_with_event(
-                &loan.loan_asset_id,
-                &liquidator,
-                &Self::money_pool(),
-                collateral_net_worth,
-            )?;
-            <generic_asset::Module<T>>::make_transfer_with_event(
-                &loan.collateral_asset_id,
-                &Self::money_pool(),
-                &liquidator,
-                loan.collateral_balance,
-            )
-            .or_else(|err| -> DispatchResult {
-                <generic_asset::Module<T>>::make_transfer_with_event(
-                    &loan.loan_asset_id,
-                    &Self::money_pool(),
-                    &liquidator,
-                    collateral_net_worth,
-                )?;
-                Err(err)
-            })?;
+            Zero::zero()
+        };
 
-            // give back what belongs to loaner
+        <generic_asset::Module<T>>::make_transfer_with_event(
+            &auction.bid_asset_id,
+            &bidder,
+            &loan.loaner_id,
+            to_lender,
+        )?;
+        if !penalty.is_zero() {
             <generic_asset::Module<T>>::make_transfer_with_event(
-                &loan.loan_asset_id,
-                &Self::money_pool(),
-                &loan.loaner_id,
-                need_to_pay,
+                &auction.bid_asset_id,
+                &bidder,
+                &Self::platform(),
+                penalty,
             )?;
-
-            let rest = collateral_net_worth - need_to_pay;
-            let penalty: T::Balance = rest * Self::liquidation_penalty().into() / 100u32.into();
-            // penalty taken, return the rest to the poor borrower
+        }
+        // penalty taken, return the rest to the poor borrower
+        if !(rest - penalty).is_zero() {
             <generic_asset::Module<T>>::make_transfer_with_event(
-                &loan.loan_asset_id,
-                &Self::money_pool(),
+                &auction.bid_asset_id,
+                &bidder,
                 &loan.borrower_id,
                 rest - penalty,
             )?;
-
-            if Self::charge_penalty() {
-                <generic_asset::Module<T>>::make_transfer_with_event(
-                    &loan.loan_asset_id,
-                    &Self::money_pool(),
-                    &Self::platform(),
-                    penalty,
-                )?;
-            }
         }
+        <generic_asset::Module<T>>::make_transfer_with_event(
+            &auction.collateral_asset_id,
+            &Self::money_pool(),
+            &bidder,
+            auction.collateral_balance,
+        )?;
 
+        Self::remove_auction(loan_id);
         Self::liquidation_cleanup(loan);
 
+        Self::deposit_event(RawEvent::AuctionSold(loan_id, bidder, price));
         Self::deposit_event(RawEvent::LoanLiquidated(loan_id));
 
         Ok(())
     }
 
+    fn remove_auction(loan_id: P2PLoanId) {
+        <Auctions<T>>::remove(loan_id);
+        AuctionLoanIds::mutate(|v| v.retain(|id| *id != loan_id));
+    }
+
+    pub fn claim_collateral(loaner: T::AccountId, loan_id: P2PLoanId) -> DispatchResult {
+        ensure!(<Loans<T>>::contains_key(loan_id), Error::<T>::UnknownLoanId);
+        let loan = <Loans<T>>::get(loan_id);
+        ensure!(loan.loaner_id == loaner, Error::<T>::NotOwnerOfLoan);
+        ensure!(
+            loan.liquidation_type == LiquidationType::JustCollateral,
+            Error::<T>::CollateralMustBeAuctioned
+        );
+
+        // the periodic check may not have marked the loan yet
+        let block_number = <system::Module<T>>::block_number();
+        ensure!(
+            loan.status == P2PLoanHealth::Defaulted
+                || (loan.status == P2PLoanHealth::Overdue
+                    && Self::is_grace_period_expired(&loan, block_number)),
+            Error::<T>::LoanNotDefaulted
+        );
+
+        Self::give_collateral_to_lender(&loan)?;
+
+        Self::liquidation_cleanup(loan);
+
+        Self::deposit_event(RawEvent::CollateralClaimed(loan_id));
+
+        Ok(())
+    }
+
     fn repay_cleanup(
         borrow: P2PBorrow<T::AssetId, T::Balance, T::BlockNumber, T::AccountId>,
         loan: P2PLoan<T::AssetId, T::Balance, T::BlockNumber, T::AccountId>,
+        block_number: T::BlockNumber,
     ) {
-        <Borrows<T>>::mutate(loan.borrow_id, |v| {
-            v.status = P2PBorrowStatus::Completed;
-        });
-        AvailableBorrowIds::mutate(|v| {
-            *v = v
-                .clone()
-                .into_iter()
-                .filter(|id| *id != loan.borrow_id)
-                .collect::<Vec<_>>();
+        // the periodic check may not have marked the loan overdue yet
+        let overdue = block_number > Self::current_due(&loan);
+        Self::update_credit_record(&loan, |record, days| {
+            if overdue {
+                record.repaid_overdue = record.repaid_overdue.saturating_add(1);
+                record.weighted_days = record.weighted_days.saturating_sub(days);
+            } else {
+                record.repaid_on_time = record.repaid_on_time.saturating_add(1);
+                record.weighted_days = record.weighted_days.saturating_add(days);
+            }
         });
+        Self::update_borrow_status(loan.borrow_id, P2PBorrowStatus::Completed);
+        Self::remove_available_borrow(&loan.borrower_id, loan.borrow_id);
         <AvailableLoanIdsByAccountId<T>>::mutate(&loan.loaner_id, |v| {
             *v = v
                 .clone()
@@ -1022,55 +2007,163 @@ impl<T: Trait> Module<T> {
                 .filter(|id| *id != loan.id)
                 .collect::<Vec<_>>();
         });
-        if <AvailableLoanIdsByAccountId<T>>::get(&loan.loaner_id).len() == 0 {
-            <AccountIdsWithLiveLoans<T>>::mutate(|v| {
-                *v = v
-                    .clone()
-                    .into_iter()
-                    .filter(|id| *id != loan.loaner_id)
-                    .collect::<Vec<_>>();
-            });
-        }
-        <Loans<T>>::mutate(loan.id, |v| {
-            v.status = P2PLoanHealth::Completed;
+        LiveLoanIds::mutate(loan.id / LIVE_LOAN_PAGE_SIZE, |v| {
+            v.retain(|id| *id != loan.id)
         });
+        <LoanAccruals<T>>::remove(loan.id);
+        <LoanInstallments<T>>::remove(loan.id);
+        Self::update_loan_status(loan.id, P2PLoanHealth::Completed);
     }
 
     // make sure all the internal states are consistent
     fn liquidation_cleanup(loan: P2PLoan<T::AssetId, T::Balance, T::BlockNumber, T::AccountId>) {
-        <Borrows<T>>::mutate(loan.borrow_id, |v| {
-            v.status = P2PBorrowStatus::Liquidated;
+        Self::update_credit_record(&loan, |record, days| {
+            record.liquidated = record.liquidated.saturating_add(1);
+            record.weighted_days = record.weighted_days.saturating_sub(days);
         });
-        AvailableBorrowIds::mutate(|v| {
-            *v = v
-                .clone()
-                .into_iter()
-                .filter(|id| *id != loan.borrow_id)
-                .collect::<Vec<_>>();
-        });
-        <AvailableLoanIdsByAccountId<T>>::mutate(&loan.loaner_id, |v| {
+        Self::update_borrow_status(loan.borrow_id, P2PBorrowStatus::Liquidated);
+        Self::remove_available_borrow(&loan.borrower_id, loan.borrow_id);
+        <AvailableLoanIdsByAccountId<T>>::mutate(&loan.loaner_id, |v| {
             *v = v
                 .clone()
                 .into_iter()
                 .filter(|id| *id != loan.id)
                 .collect::<Vec<_>>();
         });
-        if <AvailableLoanIdsByAccountId<T>>::get(&loan.loaner_id).len() == 0 {
-            <AccountIdsWithLiveLoans<T>>::mutate(|v| {
-                *v = v
-                    .clone()
-                    .into_iter()
-                    .filter(|id| *id != loan.loaner_id)
-                    .collect::<Vec<T::AccountId>>();
-            });
+        LiveLoanIds::mutate(loan.id / LIVE_LOAN_PAGE_SIZE, |v| {
+            v.retain(|id| *id != loan.id)
+        });
+        <LoanAccruals<T>>::remove(loan.id);
+        <LoanInstallments<T>>::remove(loan.id);
+        Self::update_loan_status(loan.id, P2PLoanHealth::Liquidated);
+    }
+
+    /// counts a finished loan into its borrower's credit record, `f` is given the loan's terms in days for weighted_days
+    fn update_credit_record(
+        loan: &P2PLoan<T::AssetId, T::Balance, T::BlockNumber, T::AccountId>,
+        f: impl FnOnce(&mut P2PCreditRecord<T::Balance>, u32),
+    ) {
+        let days =
+            u32::try_from(<Borrows<T>>::get(loan.borrow_id).terms).unwrap_or(u32::max_value());
+        // a missing price only leaves the volume behind, the counters are still kept
+        let volume = Self::fetch_price(loan.loan_asset_id)
+            .and_then(|(price, decimals)| {
+                let price = <T::Balance as TryFrom<u64>>::try_from(price).ok()?;
+                let scale =
+                    <T::Balance as TryFrom<u64>>::try_from(10u64.checked_pow(decimals)?).ok()?;
+                Some(loan.loan_balance * price / scale)
+            })
+            .unwrap_or_else(Zero::zero);
+        <CreditRecords<T>>::mutate(&loan.borrower_id, |record| {
+            f(record, days);
+            record.total_volume = record.total_volume.saturating_add(volume);
+        });
+    }
+
+    /// borrowers whose weighted_days reach the pair's reputation tier get the lower initial LTV
+    pub fn initial_safe_ltv(config: &TradingPairConfig, who: &T::AccountId) -> u32 {
+        if config.reputation_tier > 0
+            && Self::credit_record(who).weighted_days >= config.reputation_tier
+        {
+            config.reputation_safe_ltv
+        } else {
+            config.safe_ltv
+        }
+    }
+
+    pub fn can_make_borrow(who: &T::AccountId) -> bool {
+        (Self::available_borrow_ids_by_account_id(who).len() as u32)
+            < Self::max_available_borrows_per_account()
+    }
+
+    fn remove_available_borrow(who: &T::AccountId, borrow_id: P2PBorrowId) {
+        AvailableBorrowIds::mutate(|v| v.retain(|id| *id != borrow_id));
+        <AvailableBorrowIdsByAccountId<T>>::mutate(who, |v| v.retain(|id| *id != borrow_id));
+    }
+
+    /// status changes go through here to keep BorrowIdsByStatus in sync
+    fn update_borrow_status(borrow_id: P2PBorrowId, status: P2PBorrowStatus) {
+        let old_status = <Borrows<T>>::get(borrow_id).status;
+        if old_status == status {
+            return;
+        }
+        BorrowIdsByStatus::remove(&old_status, borrow_id);
+        BorrowIdsByStatus::insert(&status, borrow_id, borrow_id);
+        <Borrows<T>>::mutate(borrow_id, |v| {
+            v.status = status;
+        });
+    }
+
+    /// status changes go through here to keep LoanIdsByStatus in sync
+    fn update_loan_status(loan_id: P2PLoanId, status: P2PLoanHealth) {
+        let old_status = <Loans<T>>::get(loan_id).status;
+        if old_status == status {
+            return;
         }
-        <Loans<T>>::mutate(loan.id, |v| {
-            v.status = P2PLoanHealth::Liquidated;
+        if old_status == P2PLoanHealth::MarginCall {
+            <MarginCallDeadlines<T>>::remove(loan_id);
+        }
+        LoanIdsByStatus::remove(&old_status, loan_id);
+        LoanIdsByStatus::insert(&status, loan_id, loan_id);
+        <Loans<T>>::mutate(loan_id, |v| {
+            v.status = status;
         });
     }
 
+    /// the collateral of a dead borrow is given back to its owner through the borrow's own lock
+    fn kill_borrow(borrow: &P2PBorrow<T::AssetId, T::Balance, T::BlockNumber, T::AccountId>) {
+        if let Err(e) = <generic_asset::Module<T>>::unreserve(
+            &borrow.collateral_asset_id,
+            &borrow.who,
+            borrow.collateral_balance,
+            Some(borrow.lock_id),
+        ) {
+            debug::warn!(
+                "fail to release lock {} of borrow {}: {}",
+                borrow.lock_id,
+                borrow.id,
+                e
+            );
+        }
+        Self::update_borrow_status(borrow.id, P2PBorrowStatus::Dead);
+        Self::remove_available_borrow(&borrow.who, borrow.id);
+    }
+
     pub fn is_trading_pair_allowed(trading_pair: &TradingPair<T::AssetId>) -> bool {
-        <TradingPairs<T>>::get().contains(trading_pair)
+        match Self::trading_pair_config(trading_pair) {
+            Some(config) => config.enabled,
+            None => false,
+        }
+    }
+
+    /// a loan keeps following its pair's config even after the pair is disabled
+    pub fn loan_trading_pair_config(
+        loan: &P2PLoan<T::AssetId, T::Balance, T::BlockNumber, T::AccountId>,
+    ) -> Option<TradingPairConfig> {
+        Self::trading_pair_config(TradingPair {
+            collateral: loan.collateral_asset_id,
+            borrow: loan.loan_asset_id,
+        })
+    }
+
+    fn ensure_trading_pair_config_valid(config: &TradingPairConfig) -> DispatchResult {
+        ensure!(
+            config.safe_ltv > config.liquidate_ltv
+                && config.liquidate_ltv >= LTV_SCALE
+                && config.min_borrow_terms > 0
+                && config.min_borrow_terms <= config.max_borrow_terms
+                && config.origination_fee_rate <= FEE_RATE_SCALE
+                && config.interest_fee_rate <= FEE_RATE_SCALE
+                && (config.margin_call_ltv == 0
+                    || (config.margin_call_ltv > config.liquidate_ltv
+                        && config.margin_call_ltv < config.safe_ltv))
+                && (config.reputation_tier == 0
+                    || (config.reputation_safe_ltv
+                        > config.liquidate_ltv.max(config.margin_call_ltv)
+                        && config.reputation_safe_ltv <= config.safe_ltv)),
+            Error::<T>::InvalidTradingPairConfig
+        );
+        Ok(())
     }
 
     // when found a unavailable borrow, write the new borrow status
@@ -1079,110 +2172,769 @@ impl<T: Trait> Module<T> {
     ) -> Result<P2PBorrow<T::AssetId, T::Balance, T::BlockNumber, T::AccountId>, DispatchError>
     {
         ensure!(
-            AvailableBorrowIds::get().contains(&borrow_id),
+            <Borrows<T>>::contains_key(borrow_id),
             Error::<T>::BorrowNotAvailable
         );
 
         let block_number = <system::Module<T>>::block_number();
         let borrow = <Borrows<T>>::get(borrow_id);
-        if borrow.dead_after.is_some() && borrow.dead_after.unwrap() <= block_number {
-            <Borrows<T>>::mutate(borrow_id, |v| {
-                v.status = P2PBorrowStatus::Dead;
-            });
-            let new_availables = AvailableBorrowIds::take()
-                .into_iter()
-                .filter(|v| *v != borrow_id)
-                .collect::<Vec<_>>();
-            AvailableBorrowIds::put(new_availables);
-
+        if borrow.status != P2PBorrowStatus::Available {
             return Err(Error::<T>::BorrowNotAvailable.into());
         }
 
-        if borrow.status != P2PBorrowStatus::Available {
+        if borrow.dead_after.is_some() && borrow.dead_after.unwrap() <= block_number {
+            Self::kill_borrow(&borrow);
+            Self::deposit_event(RawEvent::BorrowDied(borrow_id));
+
             return Err(Error::<T>::BorrowNotAvailable.into());
         }
 
         Ok(borrow)
     }
 
-    /// this will go through all borrows currently available,
-    /// mark those who have reached the end of lives to be dead.
+    /// the following validate_* functions mirror the checks of their dispatchables without touching the storage,
+    /// they are used by P2PTxChecker to keep doomed transactions out of the pool.
+
+    pub fn validate_cancel(who: &T::AccountId, borrow_id: P2PBorrowId) -> Result<(), Error<T>> {
+        ensure!(
+            <Borrows<T>>::contains_key(borrow_id),
+            Error::<T>::UnknownBorrowId
+        );
+        let borrow = <Borrows<T>>::get(borrow_id);
+        ensure!(&borrow.who == who, Error::<T>::NotOwnerOfBorrow);
+        ensure!(
+            borrow.status == P2PBorrowStatus::Available,
+            Error::<T>::CanNotCancelBorrow
+        );
+        Ok(())
+    }
+
+    pub fn validate_take(loaner: &T::AccountId, borrow_id: P2PBorrowId) -> Result<(), Error<T>> {
+        ensure!(
+            <Borrows<T>>::contains_key(borrow_id),
+            Error::<T>::BorrowNotAvailable
+        );
+        let borrow = <Borrows<T>>::get(borrow_id);
+        ensure!(
+            borrow.status == P2PBorrowStatus::Available,
+            Error::<T>::BorrowNotAvailable
+        );
+        let block_number = <system::Module<T>>::block_number();
+        ensure!(
+            borrow
+                .dead_after
+                .map_or(true, |dead_after| dead_after > block_number),
+            Error::<T>::BorrowNotAvailable
+        );
+        ensure!(
+            <generic_asset::Module<T>>::free_balance(&borrow.borrow_asset_id, loaner)
+                >= borrow.borrow_balance,
+            Error::<T>::NotEnoughBalance
+        );
+        let trading_pair = TradingPair {
+            collateral: borrow.collateral_asset_id,
+            borrow: borrow.borrow_asset_id,
+        };
+        let trading_pair_config = Self::trading_pair_config(&trading_pair)
+            .filter(|config| config.enabled)
+            .ok_or(Error::<T>::TradingPairNotAllowed)?;
+        let trading_pair_prices = Self::fetch_fresh_trading_pair_prices(
+            borrow.borrow_asset_id,
+            borrow.collateral_asset_id,
+        )?;
+        ensure!(
+            Self::ltv_meet_safty(
+                &trading_pair_prices,
+                borrow.borrow_balance,
+                borrow.collateral_balance,
+                Self::initial_safe_ltv(&trading_pair_config, &borrow.who)
+            ),
+            Error::<T>::InitialCollateralRateFail
+        );
+        Ok(())
+    }
+
+    pub fn validate_add(
+        who: &T::AccountId,
+        borrow_id: P2PBorrowId,
+        amount: T::Balance,
+    ) -> Result<(), Error<T>> {
+        ensure!(
+            <Borrows<T>>::contains_key(borrow_id),
+            Error::<T>::UnknownBorrowId
+        );
+        let borrow = <Borrows<T>>::get(borrow_id);
+        ensure!(&borrow.who == who, Error::<T>::NotOwnerOfBorrow);
+        match borrow.status {
+            P2PBorrowStatus::Available => {}
+            P2PBorrowStatus::Taken => {
+                ensure!(
+                    <Loans<T>>::get(borrow.loan_id.unwrap()).status != P2PLoanHealth::Auctioning,
+                    Error::<T>::AddCollateralNotAllowed
+                );
+            }
+            _ => return Err(Error::<T>::AddCollateralNotAllowed),
+        }
+        ensure!(
+            <generic_asset::Module<T>>::free_balance(&borrow.collateral_asset_id, who) >= amount,
+            Error::<T>::NotEnoughBalance
+        );
+        Ok(())
+    }
+
+    /// returns the id of the loan to be repaid
+    pub fn validate_repay(
+        who: &T::AccountId,
+        borrow_id: P2PBorrowId,
+    ) -> Result<P2PLoanId, Error<T>> {
+        ensure!(
+            <Borrows<T>>::contains_key(borrow_id),
+            Error::<T>::UnknownBorrowId
+        );
+        let borrow = <Borrows<T>>::get(borrow_id);
+        ensure!(&borrow.who == who, Error::<T>::NotOwnerOfBorrow);
+        let loan_id = borrow.loan_id.ok_or(Error::<T>::BorrowNotLoaned)?;
+        ensure!(<Loans<T>>::contains_key(loan_id), Error::<T>::UnknownLoanId);
+        let loan = <Loans<T>>::get(loan_id);
+        ensure!(
+            loan.status == P2PLoanHealth::Well
+                || loan.status == P2PLoanHealth::Overdue
+                || loan.status == P2PLoanHealth::MarginCall,
+            Error::<T>::LoanNotWell
+        );
+        let trading_pair_config =
+            Self::loan_trading_pair_config(&loan).ok_or(Error::<T>::UnknownTradingPair)?;
+        let block_number = <system::Module<T>>::block_number();
+        ensure!(
+            !Self::is_grace_period_expired(&loan, block_number),
+            Error::<T>::GracePeriodExpired
+        );
+        let trading_pair_prices =
+            Self::fetch_trading_pair_prices(borrow.borrow_asset_id, borrow.collateral_asset_id)
+                .ok_or(Error::<T>::TradingPairPriceMissing)?;
+        ensure!(
+            Self::is_margin_call_pending(&loan, &trading_pair_config, block_number)
+                || !Self::ltv_meet_liquidation(
+                    &trading_pair_prices,
+                    loan.loan_balance,
+                    loan.collateral_balance,
+                    trading_pair_config.liquidate_ltv
+                ),
+            Error::<T>::ShouldBeLiquidated
+        );
+        ensure!(
+            <generic_asset::Module<T>>::free_balance(&borrow.borrow_asset_id, who)
+                >= Self::calculate_repay_amount(&borrow, &loan, block_number),
+            Error::<T>::NotEnoughBalance
+        );
+        Ok(loan_id)
+    }
+
+    /// returns the id of the loan whose installment is paid
+    pub fn validate_pay_installment(
+        who: &T::AccountId,
+        borrow_id: P2PBorrowId,
+    ) -> Result<P2PLoanId, Error<T>> {
+        ensure!(
+            <Borrows<T>>::contains_key(borrow_id),
+            Error::<T>::UnknownBorrowId
+        );
+        let borrow = <Borrows<T>>::get(borrow_id);
+        ensure!(&borrow.who == who, Error::<T>::NotOwnerOfBorrow);
+        let loan_id = borrow.loan_id.ok_or(Error::<T>::BorrowNotLoaned)?;
+        let installments = Self::loan_installments(loan_id);
+        let index = installments
+            .iter()
+            .position(|installment| !installment.paid)
+            .ok_or(Error::<T>::NoInstallmentDue)?;
+        if index == installments.len() - 1 {
+            return Self::validate_repay(who, borrow_id);
+        }
+        let loan = <Loans<T>>::get(loan_id);
+        ensure!(
+            loan.status == P2PLoanHealth::Well
+                || loan.status == P2PLoanHealth::Overdue
+                || loan.status == P2PLoanHealth::MarginCall,
+            Error::<T>::LoanNotWell
+        );
+        let block_number = <system::Module<T>>::block_number();
+        ensure!(
+            !Self::is_grace_period_expired(&loan, block_number),
+            Error::<T>::GracePeriodExpired
+        );
+        let installment = &installments[index];
+        ensure!(
+            <generic_asset::Module<T>>::free_balance(&loan.loan_asset_id, who)
+                >= installment.principal
+                    + installment.interest
+                    + Self::calculate_overdue_penalty(&loan, block_number),
+            Error::<T>::NotEnoughBalance
+        );
+        Ok(loan_id)
+    }
+
+    /// returns the loan to be liquidated
+    pub fn validate_liquidate(
+        liquidator: &T::AccountId,
+        loan_id: P2PLoanId,
+    ) -> Result<P2PLoan<T::AssetId, T::Balance, T::BlockNumber, T::AccountId>, Error<T>> {
+        ensure!(<Loans<T>>::contains_key(loan_id), Error::<T>::UnknownLoanId);
+        let loan = <Loans<T>>::get(loan_id);
+        ensure!(
+            loan.status == P2PLoanHealth::Overdue
+                || loan.status == P2PLoanHealth::Well
+                || loan.status == P2PLoanHealth::ToBeLiquidated
+                || loan.status == P2PLoanHealth::Defaulted
+                || loan.status == P2PLoanHealth::MarginCall,
+            Error::<T>::ShouldNotBeLiquidated
+        );
+        ensure!(
+            liquidator != &loan.borrower_id,
+            Error::<T>::CanNotLiquidateYourself
+        );
+        let trading_pair_config =
+            Self::loan_trading_pair_config(&loan).ok_or(Error::<T>::UnknownTradingPair)?;
+        let trading_pair_prices =
+            Self::fetch_fresh_trading_pair_prices(loan.loan_asset_id, loan.collateral_asset_id)?;
+        let block_number = <system::Module<T>>::block_number();
+        if loan.status != P2PLoanHealth::Defaulted
+            && !(loan.status == P2PLoanHealth::Overdue
+                && Self::is_grace_period_expired(&loan, block_number))
+        {
+            ensure!(
+                !Self::is_margin_call_pending(&loan, &trading_pair_config, block_number),
+                Error::<T>::MarginCallPending
+            );
+            ensure!(
+                Self::ltv_meet_liquidation(
+                    &trading_pair_prices,
+                    loan.loan_balance,
+                    loan.collateral_balance,
+                    trading_pair_config.liquidate_ltv
+                ),
+                Error::<T>::LTVNotMeet
+            );
+        }
+        Ok(loan)
+    }
+
+    pub fn validate_claim(loaner: &T::AccountId, loan_id: P2PLoanId) -> Result<(), Error<T>> {
+        ensure!(<Loans<T>>::contains_key(loan_id), Error::<T>::UnknownLoanId);
+        let loan = <Loans<T>>::get(loan_id);
+        ensure!(&loan.loaner_id == loaner, Error::<T>::NotOwnerOfLoan);
+        ensure!(
+            loan.liquidation_type == LiquidationType::JustCollateral,
+            Error::<T>::CollateralMustBeAuctioned
+        );
+        let block_number = <system::Module<T>>::block_number();
+        ensure!(
+            loan.status == P2PLoanHealth::Defaulted
+                || (loan.status == P2PLoanHealth::Overdue
+                    && Self::is_grace_period_expired(&loan, block_number)),
+            Error::<T>::LoanNotDefaulted
+        );
+        Ok(())
+    }
+
+    pub fn validate_bid(bidder: &T::AccountId, loan_id: P2PLoanId) -> Result<(), Error<T>> {
+        let auction = Self::auctions(loan_id).ok_or(Error::<T>::UnknownAuction)?;
+        let loan = <Loans<T>>::get(loan_id);
+        ensure!(
+            bidder != &loan.borrower_id,
+            Error::<T>::CanNotLiquidateYourself
+        );
+        let block_number = <system::Module<T>>::block_number();
+        ensure!(block_number < auction.end_at, Error::<T>::AuctionEnded);
+        ensure!(
+            <generic_asset::Module<T>>::free_balance(&auction.bid_asset_id, bidder)
+                >= Self::auction_price(&auction, block_number),
+            Error::<T>::NotEnoughBalance
+        );
+        ensure!(
+            <generic_asset::Module<T>>::free_balance(
+                &auction.collateral_asset_id,
+                &Self::money_pool()
+            ) >= auction.collateral_balance,
+            Error::<T>::MoneyPoolShortOfCollateral
+        );
+        Ok(())
+    }
+
+    /// storage key of a plain value of this module, also of those no longer declared
+    fn value_key(item: &[u8]) -> Vec<u8> {
+        let mut key = sp_io::hashing::twox_128(b"P2p").to_vec();
+        key.extend_from_slice(&sp_io::hashing::twox_128(item));
+        key
+    }
+
+    /// a value from before `STORAGE_VERSION` 1, cleared once decoded
+    fn take_legacy_value<V: Decode>(item: &[u8]) -> Option<V> {
+        let key = Self::value_key(item);
+        let value = V::decode(&mut &sp_io::storage::get(&key)?[..]).ok()?;
+        sp_io::storage::clear(&key);
+        Some(value)
+    }
+
+    /// bring storage from before `STORAGE_VERSION` 1 up to date. Borrows and loans get the fields
+    /// added since, as what they implicitly were: JustCollateral borrows at a fixed rate repaid at once.
+    /// The indices added since are built from them, and the global risk parameters become the
+    /// config of every trading pair. Settings added since keep their defaults until governance changes them.
+    fn migrate_storage(block_number: T::BlockNumber) {
+        let legacy_config = TradingPairConfig {
+            safe_ltv: Self::take_legacy_value(b"SafeLTV").unwrap_or_default(),
+            liquidate_ltv: Self::take_legacy_value(b"LiquidateLTV").unwrap_or_default(),
+            min_borrow_terms: Self::take_legacy_value(b"MinBorrowTerms").unwrap_or_default(),
+            max_borrow_terms: u64::max_value(),
+            min_borrow_interest_rate: Self::take_legacy_value(b"MinBorrowInterestRate")
+                .unwrap_or_default(),
+            enabled: true,
+            ..Default::default()
+        };
+        for trading_pair in Self::trading_pairs() {
+            if !<TradingPairConfigs<T>>::contains_key(&trading_pair) {
+                <TradingPairConfigs<T>>::insert(&trading_pair, legacy_config.clone());
+            }
+        }
+        // live loans are indexed in LiveLoanIds from now on
+        sp_io::storage::clear(&Self::value_key(b"AccountIdsWithLiveLoans"));
+
+        // borrows already past dead_after go into the first bucket checked
+        <BorrowsCheckedUntil<T>>::put(block_number.saturating_sub(One::one()));
+        for borrow_id in 1..Self::next_borrow_id() {
+            let key = <Borrows<T>>::hashed_key_for(borrow_id);
+            let legacy = match sp_io::storage::get(&key).map(|raw| {
+                LegacyP2PBorrow::<T::AssetId, T::Balance, T::BlockNumber, T::AccountId>::decode(
+                    &mut &raw[..],
+                )
+            }) {
+                Some(Ok(legacy)) => legacy,
+                Some(Err(_)) => {
+                    debug::warn!("borrow {} doesn't decode, kept", borrow_id);
+                    continue;
+                }
+                None => continue,
+            };
+            let trading_pair = TradingPair {
+                collateral: legacy.collateral_asset_id,
+                borrow: legacy.borrow_asset_id,
+            };
+            <BorrowIdsByTradingPair<T>>::append_or_insert(&trading_pair, vec![borrow_id]);
+            BorrowIdsByStatus::insert(&legacy.status, borrow_id, borrow_id);
+            if legacy.status == P2PBorrowStatus::Available {
+                <AvailableBorrowIdsByAccountId<T>>::append_or_insert(&legacy.who, vec![borrow_id]);
+                if let Some(dead_after) = legacy.dead_after {
+                    <BorrowIdsByDeadAfter<T>>::append_or_insert(
+                        dead_after.max(block_number),
+                        vec![borrow_id],
+                    );
+                }
+            }
+            <Borrows<T>>::insert(
+                borrow_id,
+                P2PBorrow {
+                    id: legacy.id,
+                    lock_id: legacy.lock_id,
+                    who: legacy.who,
+                    status: legacy.status,
+                    borrow_asset_id: legacy.borrow_asset_id,
+                    collateral_asset_id: legacy.collateral_asset_id,
+                    borrow_balance: legacy.borrow_balance,
+                    collateral_balance: legacy.collateral_balance,
+                    terms: legacy.terms,
+                    interest_rate: legacy.interest_rate,
+                    dead_after: legacy.dead_after,
+                    liquidation_type: LiquidationType::JustCollateral,
+                    rate_type: P2PRateType::Fixed,
+                    repayment: P2PRepaymentSchedule::Bullet,
+                    loan_id: legacy.loan_id,
+                },
+            );
+        }
+
+        for loan_id in 1..Self::next_loan_id() {
+            let key = <Loans<T>>::hashed_key_for(loan_id);
+            let legacy = match sp_io::storage::get(&key).map(|raw| {
+                LegacyP2PLoan::<T::AssetId, T::Balance, T::BlockNumber, T::AccountId>::decode(
+                    &mut &raw[..],
+                )
+            }) {
+                Some(Ok(legacy)) => legacy,
+                Some(Err(_)) => {
+                    debug::warn!("loan {} doesn't decode, kept", loan_id);
+                    continue;
+                }
+                None => continue,
+            };
+            let trading_pair = TradingPair {
+                collateral: legacy.collateral_asset_id,
+                borrow: legacy.loan_asset_id,
+            };
+            <LoanIdsByBorrowerId<T>>::append_or_insert(&legacy.borrower_id, vec![loan_id]);
+            <LoanIdsByTradingPair<T>>::append_or_insert(&trading_pair, vec![loan_id]);
+            LoanIdsByStatus::insert(&legacy.status, loan_id, loan_id);
+            match legacy.status {
+                P2PLoanHealth::Completed | P2PLoanHealth::Liquidated | P2PLoanHealth::Dead => {}
+                // ids are visited in ascending order, so appending keeps the page sorted
+                _ => LiveLoanIds::append_or_insert(loan_id / LIVE_LOAN_PAGE_SIZE, vec![loan_id]),
+            }
+            <Loans<T>>::insert(
+                loan_id,
+                P2PLoan {
+                    id: legacy.id,
+                    borrow_id: legacy.borrow_id,
+                    borrower_id: legacy.borrower_id,
+                    loaner_id: legacy.loaner_id,
+                    due: legacy.due,
+                    collateral_asset_id: legacy.collateral_asset_id,
+                    collateral_balance: legacy.collateral_balance,
+                    loan_balance: legacy.loan_balance,
+                    loan_asset_id: legacy.loan_asset_id,
+                    status: legacy.status,
+                    interest_rate: legacy.interest_rate,
+                    liquidation_type: legacy.liquidation_type,
+                    rate_type: P2PRateType::Fixed,
+                    repayment: P2PRepaymentSchedule::Bullet,
+                },
+            );
+        }
+
+        StorageVersion::put(STORAGE_VERSION);
+    }
+
+    /// dispatch isn't transactional, so a call moving funds out of `who` in several transfers
+    /// checks the total here before the first of them
+    fn ensure_can_pay(
+        asset_id: &T::AssetId,
+        who: &T::AccountId,
+        amount: T::Balance,
+        error: Error<T>,
+    ) -> DispatchResult {
+        let free_balance = <generic_asset::Module<T>>::free_balance(asset_id, who);
+        ensure!(free_balance >= amount, error);
+        <generic_asset::Module<T>>::ensure_can_withdraw(
+            asset_id,
+            who,
+            amount,
+            WithdrawReason::Transfer.into(),
+            free_balance - amount,
+        )
+    }
+
+    fn is_check_due(block_number: T::BlockNumber, interval: T::BlockNumber) -> bool {
+        !interval.is_zero() && (block_number % interval).is_zero()
+    }
+
+    /// this will go through the borrows expiring since the last check, at most BORROW_CHECK_MAX_BLOCKS blocks of them,
+    /// mark those still available to be dead.
     pub fn periodic_check_borrows(block_number: T::BlockNumber) {
         Self::deposit_event(RawEvent::CheckingAvailableBorrows);
 
-        // check available borrows
-        let mut new_availables: Vec<P2PBorrowId> = Vec::new();
-        AvailableBorrowIds::take()
-            .into_iter()
-            .for_each(|borrow_id| {
+        let checked_until = Self::borrows_checked_until();
+        let until = block_number.min(checked_until + BORROW_CHECK_MAX_BLOCKS.into());
+        let mut n = checked_until + One::one();
+        while n <= until {
+            for borrow_id in <BorrowIdsByDeadAfter<T>>::take(n) {
                 let borrow = <Borrows<T>>::get(borrow_id);
-                if borrow.dead_after.is_some() && borrow.dead_after.unwrap() <= block_number {
-                    <Borrows<T>>::mutate(borrow_id, |v| {
-                        v.status = P2PBorrowStatus::Dead;
-                    });
-                    Self::deposit_event(RawEvent::BorrowDied(borrow_id.clone()));
-                } else {
-                    new_availables.push(borrow_id.clone());
+                // the borrow may have been taken or canceled in the meantime
+                if borrow.status == P2PBorrowStatus::Available {
+                    Self::kill_borrow(&borrow);
+                    Self::deposit_event(RawEvent::BorrowDied(borrow_id));
                 }
-            });
-        AvailableBorrowIds::put(new_availables);
+            }
+            n += One::one();
+        }
+        <BorrowsCheckedUntil<T>>::put(until);
 
         Self::deposit_event(RawEvent::CheckingAvailableBorrowsDone);
     }
 
-    /// this will go through all loans currently available,
+    /// this will go through live loans page by page, starting from the cursor,
     /// calculate ltv instantly and mark loans 'ToBeLiquidated' if any whos ltv is below LTVLiquidate.
+    /// overdue loans whose grace period has passed are marked 'Defaulted'.
     pub fn periodic_check_loans(block_number: T::BlockNumber) {
-        Self::deposit_event(RawEvent::CheckingAvailableLoans);
-
-        // check available loans
-        let account_ids = <AccountIdsWithLiveLoans<T>>::get();
-        for account_id in account_ids {
-            let loan_ids = <AvailableLoanIdsByAccountId<T>>::get(account_id);
-            for loan_id in loan_ids {
-                let mut loan = <Loans<T>>::get(&loan_id);
-                if loan.status == P2PLoanHealth::Well {
-                    let trading_pair_prices = Self::fetch_trading_pair_prices(
-                        loan.loan_asset_id,
-                        loan.collateral_asset_id,
-                    );
-                    trading_pair_prices.map(|trading_pair_prices| {
-                        if Self::ltv_meet_liquidation(
-                            &trading_pair_prices,
-                            loan.loan_balance,
-                            loan.collateral_balance,
-                        ) {
-                            loan.status = P2PLoanHealth::ToBeLiquidated;
-                            <Loans<T>>::insert(&loan_id, loan);
-                            Self::deposit_event(RawEvent::LoanToBeLiquidated(loan_id.clone()));
-                        } else if block_number > loan.due {
-                            loan.status = P2PLoanHealth::Overdue;
-                            <Loans<T>>::insert(&loan_id, loan);
-                            Self::deposit_event(RawEvent::LoanOverdue(loan_id.clone()));
-                        }
-                    });
+        let cursor = match Self::loan_check_cursor() {
+            Some(cursor) => cursor,
+            None => {
+                Self::deposit_event(RawEvent::CheckingAvailableLoans);
+                0
+            }
+        };
+        let page_size = (Self::loan_check_weight_budget() / LOAN_CHECK_WEIGHT).max(1) as usize;
+
+        let last_page = Self::next_loan_id() / LIVE_LOAN_PAGE_SIZE;
+        let mut page = cursor / LIVE_LOAN_PAGE_SIZE;
+        let mut checked = 0;
+        // empty pages count against the budget too, so a long run of them is spread over blocks
+        let mut pages_read = 0;
+        while page <= last_page && pages_read < page_size {
+            pages_read += 1;
+            for loan_id in LiveLoanIds::get(page)
+                .into_iter()
+                .filter(|id| *id >= cursor)
+            {
+                if checked == page_size {
+                    LoanCheckCursor::put(loan_id);
+                    return;
                 }
+                Self::check_loan(loan_id, block_number);
+                checked += 1;
             }
+            page += 1;
         }
 
-        Self::deposit_event(RawEvent::CheckingAvailableLoansDone);
+        if page <= last_page {
+            LoanCheckCursor::put(page * LIVE_LOAN_PAGE_SIZE);
+        } else {
+            LoanCheckCursor::kill();
+            Self::deposit_event(RawEvent::CheckingAvailableLoansDone);
+        }
     }
 
-    fn fetch_price(asset_id: T::AssetId) -> Option<u64> {
-        if !<generic_asset::Module<T>>::asset_id_exists(asset_id) {
-            return None;
+    fn check_loan(loan_id: P2PLoanId, block_number: T::BlockNumber) {
+        let loan = <Loans<T>>::get(&loan_id);
+        // the debt of an auctioned loan is fixed when the auction starts
+        if loan.rate_type == P2PRateType::Variable && loan.status != P2PLoanHealth::Auctioning {
+            Self::accrue_interest(&loan, block_number);
         }
-        let token = <generic_asset::Module<T>>::symbols(asset_id);
-        if !<new_oracle::Module<T>>::is_token_known(&token) {
-            return None;
+        if loan.status == P2PLoanHealth::Overdue
+            && Self::is_grace_period_expired(&loan, block_number)
+        {
+            Self::update_loan_status(loan_id, P2PLoanHealth::Defaulted);
+            Self::deposit_event(RawEvent::LoanDefaulted(loan_id));
+        } else if loan.status == P2PLoanHealth::Well || loan.status == P2PLoanHealth::MarginCall {
+            let config = match Self::loan_trading_pair_config(&loan) {
+                Some(config) => config,
+                None => return,
+            };
+            // no margin call or liquidation against a missing or stale price
+            let trading_pair_prices = match Self::fetch_fresh_trading_pair_prices(
+                loan.loan_asset_id,
+                loan.collateral_asset_id,
+            ) {
+                Ok(trading_pair_prices) => trading_pair_prices,
+                Err(_) => return,
+            };
+            if config.margin_call_ltv > 0 {
+                let below_margin_call = Self::ltv_meet_liquidation(
+                    &trading_pair_prices,
+                    loan.loan_balance,
+                    loan.collateral_balance,
+                    config.margin_call_ltv,
+                );
+                if loan.status == P2PLoanHealth::Well && below_margin_call {
+                    Self::start_margin_call(loan_id, &config, block_number);
+                    return;
+                }
+                if loan.status == P2PLoanHealth::MarginCall && !below_margin_call {
+                    Self::update_loan_status(loan_id, P2PLoanHealth::Well);
+                    Self::deposit_event(RawEvent::MarginCallCleared(loan_id));
+                }
+            }
+            if !Self::is_margin_call_pending(&loan, &config, block_number)
+                && Self::ltv_meet_liquidation(
+                    &trading_pair_prices,
+                    loan.loan_balance,
+                    loan.collateral_balance,
+                    config.liquidate_ltv,
+                )
+            {
+                Self::update_loan_status(loan_id, P2PLoanHealth::ToBeLiquidated);
+                Self::deposit_event(RawEvent::LoanToBeLiquidated(loan_id));
+            } else if block_number > Self::current_due(&loan) {
+                Self::update_loan_status(loan_id, P2PLoanHealth::Overdue);
+                Self::deposit_event(RawEvent::LoanOverdue(loan_id));
+            }
+        }
+
+        let loan = <Loans<T>>::get(&loan_id);
+        if loan.status == P2PLoanHealth::ToBeLiquidated
+            && Self::loan_trading_pair_config(&loan).map_or(false, |config| config.auto_liquidation)
+        {
+            if let Err(e) = Self::auto_liquidate(loan, block_number) {
+                debug::warn!("fail to auto liquidate loan {}: {:?}", loan_id, e);
+            }
         }
-        let current_price = <new_oracle::Module<T>>::current_price(&token);
-        let price: u64 = TryInto::<u64>::try_into(current_price).unwrap_or(0);
-        if price == 0 {
-            return None;
+    }
+
+    /// JustCollateral loans hand all the collateral to the lender,
+    /// SellCollateral loans hand the lender the debt's worth of collateral bought at LiquidatorDiscount
+    fn auto_liquidate(
+        loan: P2PLoan<T::AssetId, T::Balance, T::BlockNumber, T::AccountId>,
+        block_number: T::BlockNumber,
+    ) -> DispatchResult {
+        let to_lender = match loan.liquidation_type {
+            LiquidationType::JustCollateral => loan.collateral_balance,
+            LiquidationType::SellCollateral => {
+                let prices = Self::fetch_fresh_trading_pair_prices(
+                    loan.loan_asset_id,
+                    loan.collateral_asset_id,
+                )?;
+                Self::lender_share_of_collateral(&loan, &prices, block_number)
+            }
+        };
+        let rest = loan.collateral_balance - to_lender;
+        let penalty: T::Balance = if Self::charge_penalty() {
+            rest * Self::liquidation_penalty().into() / 100u32.into()
         } else {
-            return Some(price);
+            Zero::zero()
+        };
+        // the whole collateral leaves the money pool, a shortfall is found before the lender is paid
+        // and leaves the loan ToBeLiquidated for the next check
+        Self::ensure_can_pay(
+            &loan.collateral_asset_id,
+            &Self::money_pool(),
+            loan.collateral_balance,
+            Error::<T>::MoneyPoolShortOfCollateral,
+        )?;
+
+        <generic_asset::Module<T>>::make_transfer_with_event(
+            &loan.collateral_asset_id,
+            &Self::money_pool(),
+            &loan.loaner_id,
+            to_lender,
+        )?;
+        if !penalty.is_zero() {
+            <generic_asset::Module<T>>::make_transfer_with_event(
+                &loan.collateral_asset_id,
+                &Self::money_pool(),
+                &Self::platform(),
+                penalty,
+            )?;
+        }
+        if !(rest - penalty).is_zero() {
+            <generic_asset::Module<T>>::make_transfer_with_event(
+                &loan.collateral_asset_id,
+                &Self::money_pool(),
+                &loan.borrower_id,
+                rest - penalty,
+            )?;
+        }
+
+        let loan_id = loan.id;
+        Self::liquidation_cleanup(loan);
+        Self::deposit_event(RawEvent::LoanAutoLiquidated(loan_id, to_lender));
+        Self::deposit_event(RawEvent::LoanLiquidated(loan_id));
+        Ok(())
+    }
+
+    /// collateral worth the loan's debt at the oracle price, marked up by LiquidatorDiscount
+    pub fn lender_share_of_collateral(
+        loan: &P2PLoan<T::AssetId, T::Balance, T::BlockNumber, T::AccountId>,
+        prices: &TradingPairPrices,
+        block_number: T::BlockNumber,
+    ) -> T::Balance {
+        let discount = Self::liquidator_discount();
+        if discount == 0 {
+            return loan.collateral_balance;
+        }
+        let share = Self::loan_debt(loan, block_number)
+            * <T::Balance as TryFrom<u64>>::try_from(prices.borrow_asset_price)
+                .ok()
+                .unwrap()
+            * 100u32.into()
+            / (<T::Balance as TryFrom<u64>>::try_from(prices.collateral_asset_price)
+                .ok()
+                .unwrap()
+                * discount.into());
+        share.min(loan.collateral_balance)
+    }
+
+    fn start_margin_call(
+        loan_id: P2PLoanId,
+        config: &TradingPairConfig,
+        block_number: T::BlockNumber,
+    ) {
+        let deadline = block_number.saturating_add(
+            <T::BlockNumber as TryFrom<u64>>::try_from(config.margin_call_window)
+                .ok()
+                .unwrap(),
+        );
+        Self::update_loan_status(loan_id, P2PLoanHealth::MarginCall);
+        <MarginCallDeadlines<T>>::insert(loan_id, deadline);
+        Self::deposit_event(RawEvent::LoanMarginCalled(loan_id, deadline));
+    }
+
+    /// a topped up loan leaves the margin call right away instead of waiting for the next check
+    fn try_clear_margin_call(loan_id: P2PLoanId) {
+        let loan = <Loans<T>>::get(loan_id);
+        if loan.status != P2PLoanHealth::MarginCall {
+            return;
+        }
+        let config = Self::loan_trading_pair_config(&loan);
+        let prices = Self::fetch_trading_pair_prices(loan.loan_asset_id, loan.collateral_asset_id);
+        if let (Some(config), Some(prices)) = (config, prices) {
+            if !Self::ltv_meet_liquidation(
+                &prices,
+                loan.loan_balance,
+                loan.collateral_balance,
+                config.margin_call_ltv,
+            ) {
+                Self::update_loan_status(loan_id, P2PLoanHealth::Well);
+                Self::deposit_event(RawEvent::MarginCallCleared(loan_id));
+            }
+        }
+    }
+
+    /// well loans of a pair with margin calls have to be margin called before they can be liquidated
+    pub fn is_margin_call_pending(
+        loan: &P2PLoan<T::AssetId, T::Balance, T::BlockNumber, T::AccountId>,
+        config: &TradingPairConfig,
+        block_number: T::BlockNumber,
+    ) -> bool {
+        match loan.status {
+            P2PLoanHealth::Well => config.margin_call_ltv > 0,
+            P2PLoanHealth::MarginCall => Self::margin_call_deadline(loan.id)
+                .map_or(false, |deadline| block_number <= deadline),
+            _ => false,
+        }
+    }
+
+    /// auctions nobody bid on before they end hand the collateral to the lender
+    pub fn periodic_check_auctions(block_number: T::BlockNumber) {
+        for loan_id in AuctionLoanIds::get() {
+            let auction = match Self::auctions(loan_id) {
+                Some(auction) => auction,
+                None => continue,
+            };
+            if block_number < auction.end_at {
+                continue;
+            }
+            let loan = <Loans<T>>::get(loan_id);
+            if let Err(e) = Self::give_collateral_to_lender(&loan) {
+                debug::warn!(
+                    "fail to settle expired auction of loan {}: {:?}",
+                    loan_id,
+                    e
+                );
+                continue;
+            }
+            Self::remove_auction(loan_id);
+            Self::liquidation_cleanup(loan);
+            Self::deposit_event(RawEvent::AuctionExpired(loan_id));
+        }
+    }
+
+    fn is_price_held(asset_id: T::AssetId) -> bool {
+        T::PriceProvider::is_held(asset_id)
+    }
+
+    fn is_price_fresh(asset_id: T::AssetId) -> bool {
+        let max_age = Self::max_price_age();
+        if max_age.is_zero() {
+            return true;
         }
+        T::PriceProvider::is_fresh(asset_id, max_age)
+    }
+
+    /// the price of an asset with its decimals
+    fn fetch_price(asset_id: T::AssetId) -> Option<(u64, u32)> {
+        let window = Self::price_twap_window();
+        let price = if window.is_zero() {
+            T::PriceProvider::price(asset_id)
+        } else {
+            T::PriceProvider::twap(asset_id, window)
+        }?;
+        Some((price, T::PriceProvider::decimals(asset_id)))
     }
 }
 
@@ -1206,6 +2958,24 @@ impl<T: Trait + Send + Sync> sp_std::fmt::Debug for P2PTxChecker<T> {
     }
 }
 
+impl<T: Trait + Send + Sync> P2PTxChecker<T> {
+    fn borrow_tag(borrow_id: P2PBorrowId) -> Vec<u8> {
+        ("p2p_borrow", borrow_id).encode()
+    }
+
+    fn loan_tag(loan_id: P2PLoanId) -> Vec<u8> {
+        ("p2p_loan", loan_id).encode()
+    }
+
+    fn auction_tag(loan_id: P2PLoanId) -> Vec<u8> {
+        ("p2p_auction", loan_id).encode()
+    }
+
+    fn bid_tag(loan_id: P2PLoanId) -> Vec<u8> {
+        ("p2p_bid", loan_id).encode()
+    }
+}
+
 impl<T: Trait + Send + Sync> SignedExtension for P2PTxChecker<T> {
     const IDENTIFIER: &'static str = "CheckP2PTxs";
     type AccountId = T::AccountId;
@@ -1230,6 +3000,12 @@ impl<T: Trait + Send + Sync> SignedExtension for P2PTxChecker<T> {
             None => return Ok(ValidTransaction::default()),
         };
 
+        if <Module<T>>::paused() {
+            return InvalidTransaction::from(Error::<T>::Paused).into();
+        }
+
+        // transactions touching the same borrow or loan provide the same tag,
+        // so only one of them makes it into a block
         match call {
             Call::make(collateral_balance, trading_pair, borrow_options) => {
                 if <generic_asset::Module<T>>::free_balance(&trading_pair.collateral, &who)
@@ -1240,27 +3016,43 @@ impl<T: Trait + Send + Sync> SignedExtension for P2PTxChecker<T> {
                 if !<Module<T>>::is_trading_pair_allowed(&trading_pair) {
                     return InvalidTransaction::from(Error::<T>::TradingPairNotAllowed).into();
                 }
-                if borrow_options.terms < <Module<T>>::min_borrow_terms() {
+                if !<Module<T>>::can_make_borrow(&who) {
+                    return InvalidTransaction::from(Error::<T>::TooManyAvailableBorrows).into();
+                }
+                let trading_pair_config = <Module<T>>::trading_pair_config(&trading_pair).unwrap();
+                if borrow_options.terms < trading_pair_config.min_borrow_terms {
                     return InvalidTransaction::from(Error::<T>::MinBorrowTerms).into();
                 }
-                if borrow_options.interest_rate < <Module<T>>::min_borrow_interest_rate() {
+                if borrow_options.terms > trading_pair_config.max_borrow_terms {
+                    return InvalidTransaction::from(Error::<T>::MaxBorrowTerms).into();
+                }
+                if <Module<T>>::current_interest_rate(
+                    borrow_options.rate_type,
+                    borrow_options.interest_rate,
+                ) < trading_pair_config.min_borrow_interest_rate
+                {
                     return InvalidTransaction::from(Error::<T>::MinBorrowInterestRate).into();
                 }
+                if borrow_options.repayment != P2PRepaymentSchedule::Bullet
+                    && borrow_options.rate_type != P2PRateType::Fixed
+                {
+                    return InvalidTransaction::from(Error::<T>::InstallmentsNeedFixedRate).into();
+                }
 
-                let trading_pair_prices = <Module<T>>::fetch_trading_pair_prices(
+                let trading_pair_prices = <Module<T>>::fetch_fresh_trading_pair_prices(
                     trading_pair.borrow,
                     trading_pair.collateral,
                 );
                 match trading_pair_prices {
-                    None => {
-                        return InvalidTransaction::from(Error::<T>::TradingPairPriceMissing)
-                            .into();
+                    Err(e) => {
+                        return InvalidTransaction::from(e).into();
                     }
-                    Some(tps) => {
+                    Ok(tps) => {
                         if !<Module<T>>::ltv_meet_safty(
                             &tps,
                             borrow_options.amount,
                             *collateral_balance,
+                            <Module<T>>::initial_safe_ltv(&trading_pair_config, &who),
                         ) {
                             return InvalidTransaction::from(Error::<T>::InitialCollateralRateFail)
                                 .into();
@@ -1270,6 +3062,86 @@ impl<T: Trait + Send + Sync> SignedExtension for P2PTxChecker<T> {
 
                 Ok(ValidTransaction::default())
             }
+            Call::cancel(borrow_id) => {
+                <Module<T>>::validate_cancel(who, *borrow_id).map_err(InvalidTransaction::from)?;
+                Ok(ValidTransaction {
+                    provides: vec![Self::borrow_tag(*borrow_id)],
+                    ..Default::default()
+                })
+            }
+            Call::take(borrow_id) => {
+                <Module<T>>::validate_take(who, *borrow_id).map_err(InvalidTransaction::from)?;
+                Ok(ValidTransaction {
+                    provides: vec![Self::borrow_tag(*borrow_id)],
+                    ..Default::default()
+                })
+            }
+            Call::add(borrow_id, amount) => {
+                <Module<T>>::validate_add(who, *borrow_id, *amount)
+                    .map_err(InvalidTransaction::from)?;
+                Ok(ValidTransaction::default())
+            }
+            Call::repay(borrow_id) => {
+                let loan_id = <Module<T>>::validate_repay(who, *borrow_id)
+                    .map_err(InvalidTransaction::from)?;
+                Ok(ValidTransaction {
+                    provides: vec![Self::loan_tag(loan_id)],
+                    ..Default::default()
+                })
+            }
+            Call::pay_installment(borrow_id) => {
+                let loan_id = <Module<T>>::validate_pay_installment(who, *borrow_id)
+                    .map_err(InvalidTransaction::from)?;
+                Ok(ValidTransaction {
+                    provides: vec![Self::loan_tag(loan_id)],
+                    ..Default::default()
+                })
+            }
+            Call::liquidate(loan_id) => {
+                let loan = <Module<T>>::validate_liquidate(who, *loan_id)
+                    .map_err(InvalidTransaction::from)?;
+                let mut provides = vec![Self::loan_tag(*loan_id)];
+                if loan.liquidation_type == LiquidationType::SellCollateral {
+                    provides.push(Self::auction_tag(*loan_id));
+                }
+                let priority = if loan.status == P2PLoanHealth::ToBeLiquidated {
+                    T::LiquidationPriority::get()
+                } else {
+                    0
+                };
+                Ok(ValidTransaction {
+                    priority,
+                    provides,
+                    ..Default::default()
+                })
+            }
+            Call::claim(loan_id) => {
+                <Module<T>>::validate_claim(who, *loan_id).map_err(InvalidTransaction::from)?;
+                Ok(ValidTransaction {
+                    provides: vec![Self::loan_tag(*loan_id)],
+                    ..Default::default()
+                })
+            }
+            Call::bid(loan_id) => match <Module<T>>::validate_bid(who, *loan_id) {
+                Ok(()) => Ok(ValidTransaction {
+                    provides: vec![Self::bid_tag(*loan_id)],
+                    ..Default::default()
+                }),
+                // the auction may still be started by a liquidation waiting in the pool
+                Err(Error::<T>::UnknownAuction) => {
+                    let loan = <Module<T>>::validate_liquidate(who, *loan_id)
+                        .map_err(InvalidTransaction::from)?;
+                    if loan.liquidation_type != LiquidationType::SellCollateral {
+                        return InvalidTransaction::from(Error::<T>::UnknownAuction).into();
+                    }
+                    Ok(ValidTransaction {
+                        requires: vec![Self::auction_tag(*loan_id)],
+                        provides: vec![Self::bid_tag(*loan_id)],
+                        ..Default::default()
+                    })
+                }
+                Err(e) => InvalidTransaction::from(e).into(),
+            },
             _ => Ok(ValidTransaction::default()),
         }
     }
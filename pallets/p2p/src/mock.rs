@@ -27,6 +27,7 @@ use support::{
 // The testing primitives are very useful for avoiding having to work with signatures
 // or public keys. `u64` is used as the `AccountId` and no `Signature`s are required.
 use crate::{GenesisConfig, Module, Trait};
+use new_oracle_primitives::{FixedPrices, PRICE_SCALE};
 use sp_core::H256;
 pub use sp_core::{sr25519, Pair, Public};
 use sp_std::convert::TryFrom;
@@ -54,6 +55,7 @@ thread_local! {
       pub(crate) static EXISTENTIAL_DEPOSIT: RefCell<u64> = RefCell::new(0);
       static TRANSFER_FEE: RefCell<u128> = RefCell::new(0);
       static CREATION_FEE: RefCell<u128> = RefCell::new(0);
+      pub(crate) static REFERENCE_RATE: RefCell<u64> = RefCell::new(0);
 }
 
 pub mod constants {
@@ -81,7 +83,6 @@ impl_outer_event! {
         system<T>,
         balances<T>,
         sudo<T>,
-        new_oracle<T>,
         generic_asset<T>,
         p2p<T>,
     }
@@ -117,33 +118,18 @@ impl system::Trait for Test {
     type OnKilledAccount = ();
 }
 
-type Extrinsic = TestXt<new_oracle::Call<Test>, ()>;
-type SubmitTransaction =
-    system::offchain::TransactionSubmitter<new_oracle::crypto::Public, Test, Extrinsic>;
-
-impl system::offchain::CreateTransaction<Test, Extrinsic> for Test {
-    type Public = sp_core::sr25519::Public;
-    type Signature = sp_core::sr25519::Signature;
-
-    fn create_transaction<F: system::offchain::Signer<Self::Public, Self::Signature>>(
-        call: <Extrinsic as ExtrinsicsT>::Call,
-        _public: Self::Public,
-        _account: <Test as system::Trait>::AccountId,
-        nonce: <Test as system::Trait>::Index,
-    ) -> Option<(
-        <Extrinsic as ExtrinsicsT>::Call,
-        <Extrinsic as ExtrinsicsT>::SignaturePayload,
-    )> {
-        Some((call, (nonce, ())))
-    }
-}
-
 pub struct ExistentialDeposit;
 impl Get<u64> for ExistentialDeposit {
     fn get() -> u64 {
         EXISTENTIAL_DEPOSIT.with(|v| *v.borrow())
     }
 }
+pub struct ReferenceRate;
+impl Get<u64> for ReferenceRate {
+    fn get() -> u64 {
+        REFERENCE_RATE.with(|v| *v.borrow())
+    }
+}
 impl balances::Trait for Test {
     type Balance = u64;
     type Event = MetaEvent;
@@ -171,28 +157,24 @@ impl generic_asset::Trait for Test {
     type AssetId = u32;
 }
 
-parameter_types! {
-    pub const AggregateInterval: BlockNumber = 5;
-}
-impl new_oracle::Trait for Test {
-    type Event = MetaEvent;
-    type Call = new_oracle::Call<Test>;
-    type SubmitUnsignedTransaction = SubmitTransaction;
-    type SubmitSignedTransaction = SubmitTransaction;
-    type AggregateInterval = AggregateInterval;
-    type PriceInUSDT = u64;
-}
-
 mod p2p {
     pub use super::super::*;
 }
 parameter_types! {
     pub const DaysInBlockNumber: BlockNumber = 86400u32.into();
+    pub const AuctionDuration: BlockNumber = 100;
+    pub const LiquidationPriority: TransactionPriority = 1_000_000;
 }
+pub type TestPrices = FixedPrices<BlockNumber>;
+
 impl Trait for Test {
     type Event = MetaEvent;
+    type PriceProvider = TestPrices;
     type Days = DaysInBlockNumber;
     type Call = Call;
+    type AuctionDuration = AuctionDuration;
+    type LiquidationPriority = LiquidationPriority;
+    type ReferenceRate = ReferenceRate;
 }
 
 pub type P2PTest = Module<Test>;
@@ -243,15 +225,9 @@ pub fn new_test_ext() -> sp_io::TestExternalities {
         .assimilate_storage(&mut t)
         .unwrap();
 
-    new_oracle::GenesisConfig::<Test> {
-        crypto_price_sources: vec![],
-        current_price: vec![
-            (b"DUSD".to_vec(), 1 * new_oracle::PRICE_SCALE),
-            (b"BTC".to_vec(), 10000 * new_oracle::PRICE_SCALE),
-        ],
-    }
-    .assimilate_storage(&mut t)
-    .unwrap();
+    TestPrices::clear();
+    TestPrices::set_price(constants::USDT, 1 * PRICE_SCALE);
+    TestPrices::set_price(constants::BTC, 10000 * PRICE_SCALE);
 
     generic_asset::GenesisConfig::<Test> {
         next_asset_id: 2,
@@ -266,17 +242,38 @@ pub fn new_test_ext() -> sp_io::TestExternalities {
     GenesisConfig::<Test> {
         money_pool,
         platform,
-        trading_pairs: vec![crate::TradingPair {
-            collateral: 1 as u32,
-            borrow: 0 as u32,
-        }],
-        safe_ltv: 30000,
-        liquidate_ltv: 15000,
-        min_borrow_terms: 1,
-        min_borrow_interest_rate: 10000,
+        trading_pairs: vec![(
+            crate::TradingPair {
+                collateral: 1 as u32,
+                borrow: 0 as u32,
+            },
+            crate::TradingPairConfig {
+                safe_ltv: 30000,
+                liquidate_ltv: 15000,
+                min_borrow_terms: 1,
+                max_borrow_terms: 365,
+                min_borrow_interest_rate: 10000,
+                enabled: true,
+                origination_fee_rate: 0,
+                interest_fee_rate: 0,
+                reputation_tier: 0,
+                reputation_safe_ltv: 0,
+                margin_call_ltv: 0,
+                margin_call_window: 0,
+                auto_liquidation: false,
+            },
+        )],
+        max_available_borrows_per_account: 3,
+        borrow_check_interval: 2,
+        loan_check_interval: 5,
+        loan_check_weight_budget: 10 * crate::LOAN_CHECK_WEIGHT,
+        overdue_grace_period: 3,
+        overdue_penalty_rate: 10000,
         charge_penalty: true,
         liquidator_discount: 90,
         liquidation_penalty: 50,
+        max_price_age: 0,
+        price_twap_window: 0,
     }
     .assimilate_storage(&mut t)
     .unwrap();
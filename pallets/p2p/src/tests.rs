@@ -32,6 +32,7 @@ use sp_runtime::{
 };
 
 use crate::mock::{constants::*, *};
+use new_oracle_primitives::PRICE_SCALE;
 
 #[test]
 fn unittest_works() {
@@ -52,6 +53,81 @@ fn fetch_prices_works() {
         let prices = prices.unwrap();
         assert_eq!(prices.borrow_asset_price, 10000u32.into());
         assert_eq!(prices.collateral_asset_price, 100000000u32.into());
+
+        // 0.00001234 would be 0 with the decimals of PRICE_SCALE
+        TestPrices::set_price(USDT, 1234);
+        TestPrices::set_decimals(USDT, 8);
+        assert_eq!(
+            P2PTest::fetch_trading_pair_prices(USDT, BTC),
+            Some(TradingPairPrices {
+                borrow_asset_price: 1234,
+                collateral_asset_price: 10000 * PRICE_SCALE * 10000,
+            })
+        );
+    });
+}
+
+#[test]
+fn stale_price_works() {
+    let root: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Root");
+    let eve: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Eve");
+
+    ExtBuilder::default().build().execute_with(|| {
+        assert_ok!(GenericAssetTest::mint_free(
+            &BTC,
+            &root,
+            &eve,
+            &1000_00000000u128,
+        ));
+        SystemTest::set_block_number(1);
+        assert_ok!(P2PTest::change_max_price_age(
+            system::RawOrigin::Root.into(),
+            10
+        ));
+        assert!(P2PTest::fetch_fresh_trading_pair_prices(USDT, BTC).is_ok());
+
+        TestPrices::set_stale(BTC, true);
+        assert!(P2PTest::fetch_fresh_trading_pair_prices(USDT, BTC).is_err());
+        // repayments still see the last price
+        assert!(P2PTest::fetch_trading_pair_prices(USDT, BTC).is_some());
+
+        let trading_pair = crate::TradingPair {
+            collateral: BTC,
+            borrow: USDT,
+        };
+        let options = crate::P2PBorrowOptions {
+            amount: 100_0000000u128,
+            terms: 10,
+            interest_rate: 20000,
+            warranty: Some(<Test as system::Trait>::BlockNumber::from(30u32)),
+            liquidation_type: LiquidationType::JustCollateral,
+            rate_type: P2PRateType::Fixed,
+            repayment: P2PRepaymentSchedule::Bullet,
+        };
+        assert_noop!(
+            P2PTest::create_borrow(eve, 100000000u128, trading_pair.clone(), options.clone()),
+            Error::<Test>::PriceStale
+        );
+
+        TestPrices::set_stale(BTC, false);
+        assert_ok!(P2PTest::create_borrow(
+            eve,
+            100000000u128,
+            trading_pair,
+            options,
+        ));
+    });
+}
+
+#[test]
+fn held_price_works() {
+    ExtBuilder::default().build().execute_with(|| {
+        TestPrices::set_held(BTC, true);
+        assert!(P2PTest::fetch_fresh_trading_pair_prices(USDT, BTC).is_err());
+        assert!(P2PTest::fetch_trading_pair_prices(USDT, BTC).is_some());
+
+        TestPrices::set_held(BTC, false);
+        assert!(P2PTest::fetch_fresh_trading_pair_prices(USDT, BTC).is_ok());
     });
 }
 
@@ -84,6 +160,9 @@ fn borrow_works() {
             terms: 10,
             interest_rate: 20000,
             warranty: Some(<Test as system::Trait>::BlockNumber::from(30u32)),
+            liquidation_type: LiquidationType::JustCollateral,
+            rate_type: P2PRateType::Fixed,
+            repayment: P2PRepaymentSchedule::Bullet,
         };
         let borrow_id = P2PTest::next_borrow_id();
         assert_ok!(P2PTest::create_borrow(
@@ -106,7 +185,7 @@ fn ltv_meet_safty_works() {
         let borrow_amount = 10000_00000000u128;
         let collateral_amount = 1_00000000u128;
         assert_eq!(
-            P2PTest::ltv_meet_safty(&prices.unwrap(), borrow_amount, collateral_amount),
+            P2PTest::ltv_meet_safty(&prices.unwrap(), borrow_amount, collateral_amount, 30000),
             false
         );
     });
@@ -150,19 +229,120 @@ fn multi_borrows_error_works() {
             terms: 10,
             interest_rate: 20000,
             warranty: Some(<Test as system::Trait>::BlockNumber::from(30u32)),
+            liquidation_type: LiquidationType::JustCollateral,
+            rate_type: P2PRateType::Fixed,
+            repayment: P2PRepaymentSchedule::Bullet,
         };
 
+        // at most 3 available borrows per account
+        let first_borrow_id = P2PTest::next_borrow_id();
+        for _ in 0..3 {
+            assert_ok!(P2PTest::create_borrow(
+                eve.clone(),
+                100000000u128,
+                trading_pair.clone(),
+                options.clone(),
+            ));
+        }
+        assert_eq!(P2PTest::available_borrow_ids_by_account_id(&eve).len(), 3);
+
+        assert_noop!(
+            P2PTest::create_borrow(
+                eve.clone(),
+                100000000u128,
+                trading_pair.clone(),
+                options.clone()
+            ),
+            Error::<Test>::TooManyAvailableBorrows
+        );
+
+        // a taken borrow no longer counts
+        assert_ok!(P2PTest::create_loan(dave, first_borrow_id));
+        assert_eq!(
+            P2PTest::available_borrow_ids().contains(&first_borrow_id),
+            false
+        );
         assert_ok!(P2PTest::create_borrow(
-            eve.clone(),
+            eve,
             100000000u128,
+            trading_pair,
+            options
+        ));
+    });
+}
+
+#[test]
+fn multi_borrows_lock_works() {
+    let root: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Root");
+    let eve: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Eve");
+    let dave: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Dave");
+
+    ExtBuilder::default().build().execute_with(|| {
+        assert_ok!(GenericAssetTest::mint_free(
+            &BTC,
+            &root,
+            &eve,
+            &10_00000000u128,
+        ));
+        assert_ok!(GenericAssetTest::mint_free(
+            &USDT,
+            &root,
+            &dave,
+            &1000_00000000u128,
+        ));
+
+        let trading_pair = crate::TradingPair {
+            collateral: BTC,
+            borrow: USDT,
+        };
+        let options = crate::P2PBorrowOptions {
+            amount: 100_00000000u128,
+            terms: 10,
+            interest_rate: 20000,
+            warranty: Some(<Test as system::Trait>::BlockNumber::from(30u32)),
+            liquidation_type: LiquidationType::JustCollateral,
+            rate_type: P2PRateType::Fixed,
+            repayment: P2PRepaymentSchedule::Bullet,
+        };
+        let one_btc = 100000000u128;
+
+        let first_borrow_id = P2PTest::next_borrow_id();
+        assert_ok!(P2PTest::create_borrow(
+            eve,
+            one_btc,
             trading_pair.clone(),
-            options.clone(),
+            options.clone()
+        ));
+        let second_borrow_id = P2PTest::next_borrow_id();
+        assert_ok!(P2PTest::create_borrow(
+            eve,
+            2 * one_btc,
+            trading_pair.clone(),
+            options.clone()
         ));
+        let third_borrow_id = P2PTest::next_borrow_id();
+        assert_ok!(P2PTest::create_borrow(eve, one_btc, trading_pair, options));
 
-        assert_noop!(
-            P2PTest::create_borrow(eve, 100000000u128, trading_pair, options),
-            Error::<Test>::MultipleAvailableBorrows
+        // adding collateral only touches the lock of that borrow
+        assert_ok!(P2PTest::add_collateral(eve, first_borrow_id, one_btc));
+        let second = P2PTest::borrows(second_borrow_id);
+        assert_eq!(
+            GenericAssetTest::locked_balance(&BTC, &eve, second.lock_id),
+            Some(2 * one_btc)
+        );
+        assert_ok!(P2PTest::remove_borrow(eve, second_borrow_id));
+        assert_ok!(P2PTest::create_loan(dave, first_borrow_id));
+        assert_eq!(GenericAssetTest::reserved_balance(&BTC, &eve), one_btc);
+
+        // the expired borrow gives its collateral back
+        next_n_block(41);
+        assert_eq!(
+            P2PTest::borrows(third_borrow_id).status,
+            P2PBorrowStatus::Dead
         );
+        assert_eq!(GenericAssetTest::reserved_balance(&BTC, &eve), 0);
+        assert_eq!(GenericAssetTest::free_balance(&BTC, &eve), 6 * one_btc);
+        assert_eq!(P2PTest::available_borrow_ids_by_account_id(&eve).len(), 0);
     });
 }
 
@@ -195,6 +375,9 @@ fn invalid_borrow_works() {
             terms: 10,
             interest_rate: 20000,
             warranty: Some(<Test as system::Trait>::BlockNumber::from(30u32)),
+            liquidation_type: LiquidationType::JustCollateral,
+            rate_type: P2PRateType::Fixed,
+            repayment: P2PRepaymentSchedule::Bullet,
         };
 
         assert_noop!(
@@ -204,6 +387,91 @@ fn invalid_borrow_works() {
     });
 }
 
+#[test]
+fn trading_pair_config_works() {
+    let root: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Root");
+    let eve: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Eve");
+
+    ExtBuilder::default().build().execute_with(|| {
+        assert_ok!(GenericAssetTest::mint_free(
+            &BTC,
+            &root,
+            &eve,
+            &1000_00000000u128,
+        ));
+
+        let trading_pair = crate::TradingPair {
+            collateral: BTC,
+            borrow: USDT,
+        };
+        let reversed_pair = crate::TradingPair {
+            collateral: USDT,
+            borrow: BTC,
+        };
+        let mut config = P2PTest::trading_pair_config(&trading_pair).unwrap();
+        assert_eq!(config.safe_ltv, 30000);
+        assert_eq!(P2PTest::is_trading_pair_allowed(&reversed_pair), false);
+
+        assert_noop!(
+            P2PTest::add_trading_pair(
+                system::RawOrigin::Root.into(),
+                trading_pair.clone(),
+                config.clone()
+            ),
+            Error::<Test>::TradingPairExists
+        );
+        assert_ok!(P2PTest::add_trading_pair(
+            system::RawOrigin::Root.into(),
+            reversed_pair.clone(),
+            config.clone()
+        ));
+        assert_eq!(P2PTest::is_trading_pair_allowed(&reversed_pair), true);
+        assert_eq!(P2PTest::trading_pairs().len(), 2);
+
+        let options = crate::P2PBorrowOptions {
+            amount: 100_00000000u128,
+            terms: 10,
+            interest_rate: 20000,
+            warranty: Some(<Test as system::Trait>::BlockNumber::from(30u32)),
+            liquidation_type: LiquidationType::JustCollateral,
+            rate_type: P2PRateType::Fixed,
+            repayment: P2PRepaymentSchedule::Bullet,
+        };
+
+        // tighten the max terms of the pair
+        config.max_borrow_terms = 5;
+        assert_ok!(P2PTest::update_trading_pair(
+            system::RawOrigin::Root.into(),
+            trading_pair.clone(),
+            config.clone()
+        ));
+        assert_noop!(
+            P2PTest::create_borrow(eve, 1_00000000u128, trading_pair.clone(), options.clone()),
+            Error::<Test>::MaxBorrowTerms
+        );
+
+        config.liquidate_ltv = config.safe_ltv;
+        assert_noop!(
+            P2PTest::update_trading_pair(
+                system::RawOrigin::Root.into(),
+                trading_pair.clone(),
+                config.clone()
+            ),
+            Error::<Test>::InvalidTradingPairConfig
+        );
+
+        assert_ok!(P2PTest::disable_trading_pair(
+            system::RawOrigin::Root.into(),
+            trading_pair.clone()
+        ));
+        assert_eq!(P2PTest::is_trading_pair_allowed(&trading_pair), false);
+        assert_noop!(
+            P2PTest::create_borrow(eve, 1_00000000u128, trading_pair, options),
+            Error::<Test>::TradingPairNotAllowed
+        );
+    });
+}
+
 #[test]
 fn lend_works() {
     let root: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Root");
@@ -233,6 +501,9 @@ fn lend_works() {
             terms: 10,
             interest_rate: 20000,
             warranty: Some(<Test as system::Trait>::BlockNumber::from(30u32)),
+            liquidation_type: LiquidationType::JustCollateral,
+            rate_type: P2PRateType::Fixed,
+            repayment: P2PRepaymentSchedule::Bullet,
         };
         let borrow_id = P2PTest::next_borrow_id();
         assert_ok!(P2PTest::create_borrow(
@@ -282,6 +553,9 @@ fn repay_works() {
             terms: 10,
             interest_rate: 20000,
             warranty: Some(<Test as system::Trait>::BlockNumber::from(30u32)),
+            liquidation_type: LiquidationType::JustCollateral,
+            rate_type: P2PRateType::Fixed,
+            repayment: P2PRepaymentSchedule::Bullet,
         };
         let borrow_id = P2PTest::next_borrow_id();
         assert_ok!(P2PTest::create_borrow(
@@ -353,6 +627,9 @@ fn liquidate_works() {
             terms: 1,
             interest_rate: 20000,
             warranty: Some(<Test as system::Trait>::BlockNumber::from(30u32)),
+            liquidation_type: LiquidationType::JustCollateral,
+            rate_type: P2PRateType::Fixed,
+            repayment: P2PRepaymentSchedule::Bullet,
         };
         let borrow_id = P2PTest::next_borrow_id();
         assert_ok!(P2PTest::create_borrow(
@@ -368,7 +645,7 @@ fn liquidate_works() {
             Error::<Test>::NotEnoughBalance
         );
 
-        next_n_block(86403u32.into());
+        next_n_block(86404u32.into());
         assert_eq!(
             SystemTest::events()
                 .into_iter()
@@ -446,61 +723,85 @@ fn liquidate_works() {
 
         let loan = P2PTest::loans(loan_id);
         assert_eq!(loan.status, P2PLoanHealth::Overdue);
+        // within the grace period, an overdue loan with a healthy ltv can not be liquidated
+        assert_noop!(
+            P2PTest::liquidate_loan(liquidator, loan_id),
+            Error::<Test>::LTVNotMeet
+        );
+
+        // pass the 3 days grace period
+        next_n_block(259190u32.into());
+        assert_eq!(
+            SystemTest::events()
+                .into_iter()
+                .map(|r| r.event)
+                .filter_map(|e| {
+                    if let MetaEvent::p2p(inner) = e {
+                        match inner {
+                            RawEvent::LoanDefaulted(_) => Some(inner),
+                            _ => None,
+                        }
+                    } else {
+                        None
+                    }
+                })
+                .last()
+                .unwrap(),
+            RawEvent::LoanDefaulted(loan_id)
+        );
+        let loan = P2PTest::loans(loan_id);
+        assert_eq!(loan.status, P2PLoanHealth::Defaulted);
         assert_ok!(P2PTest::liquidate_loan(liquidator, loan_id));
         let loan = P2PTest::loans(loan_id);
         assert_eq!(loan.status, P2PLoanHealth::Liquidated);
 
-        // now comes to money...
-        assert_eq!(
-            GenericAssetTest::free_balance(&BTC, &liquidator),
-            100000000u128
-        );
-        assert_eq!(
-            (P2PTest::liquidator_discount() as u128)
-                * loan.collateral_balance
-                * <new_oracle::Module<Test>>::current_price(b"BTC".to_vec()) as u128
-                / 100u128
-                / new_oracle::PRICE_SCALE as u128,
-            9000_00000000u128
-        );
+        // JustCollateral loans hand the collateral straight to the lender
+        assert_eq!(GenericAssetTest::free_balance(&BTC, &liquidator), 0);
+        assert_eq!(GenericAssetTest::free_balance(&BTC, &dave), 100000000u128);
         assert_eq!(
             GenericAssetTest::free_balance(&USDT, &liquidator),
-            1000_00000000u128
+            10000_00000000u128
         );
         assert_eq!(
             GenericAssetTest::free_balance(&USDT, &dave),
-            10000_02000000u128
+            9900_00000000u128
         );
         assert_eq!(GenericAssetTest::free_balance(&BTC, &eve), 999_00000000u128);
         assert_eq!(
             GenericAssetTest::free_balance(&USDT, &eve),
-            4549_99000000u128,
-        );
-        assert_eq!(
-            GenericAssetTest::free_balance(&USDT, &platform),
-            4449_99000000u128,
+            100_00000000u128
         );
+        assert_eq!(GenericAssetTest::free_balance(&USDT, &platform), 0);
     });
 }
 
 #[test]
-fn add_works() {
+fn auction_works() {
     let root: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Root");
     let eve: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Eve");
     let dave: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Dave");
+    let liquidator: <Test as system::Trait>::AccountId =
+        get_from_seed::<sr25519::Public>("liquidator");
+    let platform: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Platform");
 
     ExtBuilder::default().build().execute_with(|| {
         assert_ok!(GenericAssetTest::mint_free(
             &BTC,
             &root,
             &eve,
-            &1000_00000000u128
+            &1000_00000000u128,
         ));
         assert_ok!(GenericAssetTest::mint_free(
             &USDT,
             &root,
             &dave,
-            &100000_00000000u128
+            &10000_00000000u128,
+        ));
+        assert_ok!(GenericAssetTest::mint_free(
+            &USDT,
+            &root,
+            &liquidator,
+            &10000_00000000u128,
         ));
 
         let trading_pair = crate::TradingPair {
@@ -508,28 +809,100 @@ fn add_works() {
             borrow: USDT,
         };
         let options = crate::P2PBorrowOptions {
-            amount: 100_0000000u128,
-            terms: 10,
+            amount: 100_00000000u128,
+            terms: 1,
             interest_rate: 20000,
             warranty: Some(<Test as system::Trait>::BlockNumber::from(30u32)),
+            liquidation_type: LiquidationType::SellCollateral,
+            rate_type: P2PRateType::Fixed,
+            repayment: P2PRepaymentSchedule::Bullet,
         };
-        let one_btc = 100000000u128;
         let borrow_id = P2PTest::next_borrow_id();
-        assert_ok!(P2PTest::create_borrow(eve, one_btc, trading_pair, options));
-        assert_ok!(P2PTest::add_collateral(eve, borrow_id, one_btc));
-        let borrow = P2PTest::borrows(borrow_id);
-        assert_eq!(borrow.collateral_balance, one_btc * 2u128);
+        assert_ok!(P2PTest::create_borrow(
+            eve,
+            100000000u128,
+            trading_pair,
+            options,
+        ));
+        let loan_id = P2PTest::next_loan_id();
         assert_ok!(P2PTest::create_loan(dave, borrow_id));
-        assert_ok!(P2PTest::add_collateral(eve, borrow_id, one_btc));
-        let borrow = P2PTest::borrows(borrow_id);
-        assert_eq!(borrow.collateral_balance, one_btc * 3u128);
-        let loan = P2PTest::loans(borrow.loan_id.unwrap());
-        assert_eq!(loan.collateral_balance, one_btc * 3u128);
+
+        // overdue, then pass the grace period
+        next_n_block(86404u32.into());
+        next_n_block(259200u32.into());
+        assert_eq!(P2PTest::loans(loan_id).status, P2PLoanHealth::Defaulted);
+        assert_noop!(
+            P2PTest::claim_collateral(dave, loan_id),
+            Error::<Test>::CollateralMustBeAuctioned
+        );
+
+        assert_ok!(P2PTest::liquidate_loan(liquidator, loan_id));
+        assert_eq!(P2PTest::loans(loan_id).status, P2PLoanHealth::Auctioning);
+        let auction = P2PTest::auctions(loan_id).unwrap();
+        assert_eq!(auction.start_price, 10000_00000000u128);
+        assert_eq!(auction.floor_price, 9000_00000000u128);
+        assert_eq!(auction.debt, 100_05000000u128);
+        assert_noop!(
+            P2PTest::add_collateral(eve, borrow_id, 100000000u128),
+            Error::<Test>::AddCollateralNotAllowed
+        );
+        assert_noop!(
+            P2PTest::bid_auction(eve, loan_id),
+            Error::<Test>::CanNotLiquidateYourself
+        );
+
+        // half way down the price curve
+        SystemTest::set_block_number(auction.start_at + 50);
+        assert_eq!(
+            P2PTest::auction_price(&auction, SystemTest::block_number()),
+            9500_00000000u128
+        );
+        // nothing is paid if the collateral can't be handed over
+        let money_pool = P2PTest::money_pool();
+        assert_ok!(P2PTest::change_money_pool(
+            system::RawOrigin::Root.into(),
+            get_from_seed::<sr25519::Public>("Nobody")
+        ));
+        assert_noop!(
+            P2PTest::bid_auction(liquidator, loan_id),
+            Error::<Test>::MoneyPoolShortOfCollateral
+        );
+        assert_ok!(P2PTest::change_money_pool(
+            system::RawOrigin::Root.into(),
+            money_pool
+        ));
+        assert_ok!(P2PTest::bid_auction(liquidator, loan_id));
+        assert_eq!(P2PTest::loans(loan_id).status, P2PLoanHealth::Liquidated);
+        assert_eq!(P2PTest::auctions(loan_id), None);
+        assert_eq!(P2PTest::auction_loan_ids().len(), 0);
+
+        assert_eq!(
+            GenericAssetTest::free_balance(&BTC, &liquidator),
+            100000000u128
+        );
+        assert_eq!(
+            GenericAssetTest::free_balance(&USDT, &liquidator),
+            500_00000000u128
+        );
+        // principal + interest + 3 days of penalty interest
+        assert_eq!(
+            GenericAssetTest::free_balance(&USDT, &dave),
+            10000_05000000u128
+        );
+        // half of the rest is taken as penalty
+        assert_eq!(
+            GenericAssetTest::free_balance(&USDT, &platform),
+            4699_97500000u128
+        );
+        assert_eq!(
+            GenericAssetTest::free_balance(&USDT, &eve),
+            4799_97500000u128
+        );
     });
 }
 
 #[test]
-fn cancel_works() {
+fn auction_expire_works() {
     let root: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Root");
     let eve: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Eve");
     let dave: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Dave");
@@ -545,7 +918,7 @@ fn cancel_works() {
             &USDT,
             &root,
             &dave,
-            &100000_00000000u128,
+            &10000_00000000u128,
         ));
 
         let trading_pair = crate::TradingPair {
@@ -554,19 +927,281 @@ fn cancel_works() {
         };
         let options = crate::P2PBorrowOptions {
             amount: 100_00000000u128,
-            terms: 10,
+            terms: 1,
             interest_rate: 20000,
             warranty: Some(<Test as system::Trait>::BlockNumber::from(30u32)),
+            liquidation_type: LiquidationType::SellCollateral,
+            rate_type: P2PRateType::Fixed,
+            repayment: P2PRepaymentSchedule::Bullet,
         };
-        let one_btc = 100000000u128;
         let borrow_id = P2PTest::next_borrow_id();
         assert_ok!(P2PTest::create_borrow(
             eve,
-            one_btc,
-            trading_pair.clone(),
-            options.clone(),
-        ));
-
+            100000000u128,
+            trading_pair,
+            options,
+        ));
+        let loan_id = P2PTest::next_loan_id();
+        assert_ok!(P2PTest::create_loan(dave, borrow_id));
+
+        next_n_block(86404u32.into());
+        next_n_block(259200u32.into());
+        assert_ok!(P2PTest::liquidate_loan(dave, loan_id));
+
+        // nobody bids until the auction ends
+        next_n_block(100u32.into());
+        assert_noop!(
+            P2PTest::bid_auction(dave, loan_id),
+            Error::<Test>::UnknownAuction
+        );
+        assert_eq!(P2PTest::loans(loan_id).status, P2PLoanHealth::Liquidated);
+        assert_eq!(GenericAssetTest::free_balance(&BTC, &dave), 100000000u128);
+        assert!(SystemTest::events()
+            .into_iter()
+            .any(|r| r.event == MetaEvent::p2p(RawEvent::AuctionExpired(loan_id))));
+    });
+}
+
+#[test]
+fn overdue_repay_works() {
+    let root: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Root");
+    let eve: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Eve");
+    let dave: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Dave");
+
+    ExtBuilder::default().build().execute_with(|| {
+        assert_ok!(GenericAssetTest::mint_free(
+            &BTC,
+            &root,
+            &eve,
+            &1000_00000000u128,
+        ));
+        assert_ok!(GenericAssetTest::mint_free(
+            &USDT,
+            &root,
+            &dave,
+            &1000_00000000u128,
+        ));
+
+        let trading_pair = crate::TradingPair {
+            collateral: BTC,
+            borrow: USDT,
+        };
+        let options = crate::P2PBorrowOptions {
+            amount: 100_00000000u128,
+            terms: 1,
+            interest_rate: 20000,
+            warranty: Some(<Test as system::Trait>::BlockNumber::from(30u32)),
+            liquidation_type: LiquidationType::JustCollateral,
+            rate_type: P2PRateType::Fixed,
+            repayment: P2PRepaymentSchedule::Bullet,
+        };
+        let borrow_id = P2PTest::next_borrow_id();
+        assert_ok!(P2PTest::create_borrow(
+            eve,
+            100000000u128,
+            trading_pair,
+            options,
+        ));
+        let loan_id = P2PTest::next_loan_id();
+        assert_ok!(P2PTest::create_loan(dave, borrow_id));
+
+        next_n_block(86404u32.into());
+        let loan = P2PTest::loans(loan_id);
+        assert_eq!(loan.status, P2PLoanHealth::Overdue);
+        // one overdue day started
+        assert_eq!(
+            P2PTest::calculate_overdue_penalty(&loan, SystemTest::block_number()),
+            1000000u128
+        );
+
+        assert_ok!(GenericAssetTest::mint_free(
+            &USDT,
+            &root,
+            &eve,
+            &2_00000000u128,
+        ));
+        assert_ok!(P2PTest::repay_loan(eve, borrow_id));
+        assert_eq!(P2PTest::loans(loan_id).status, P2PLoanHealth::Completed);
+        assert_eq!(GenericAssetTest::free_balance(&USDT, &eve), 1_97000000u128);
+        assert_eq!(
+            GenericAssetTest::free_balance(&USDT, &dave),
+            1000_03000000u128
+        );
+        assert!(
+            SystemTest::events()
+                .into_iter()
+                .any(|r| r.event
+                    == MetaEvent::p2p(RawEvent::OverduePenaltyPaid(loan_id, 1000000u128)))
+        );
+    });
+}
+
+#[test]
+fn claim_works() {
+    let root: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Root");
+    let eve: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Eve");
+    let dave: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Dave");
+
+    ExtBuilder::default().build().execute_with(|| {
+        assert_ok!(GenericAssetTest::mint_free(
+            &BTC,
+            &root,
+            &eve,
+            &1000_00000000u128,
+        ));
+        assert_ok!(GenericAssetTest::mint_free(
+            &USDT,
+            &root,
+            &dave,
+            &1000_00000000u128,
+        ));
+        assert_ok!(GenericAssetTest::mint_free(
+            &USDT,
+            &root,
+            &eve,
+            &100_00000000u128,
+        ));
+
+        let trading_pair = crate::TradingPair {
+            collateral: BTC,
+            borrow: USDT,
+        };
+        let options = crate::P2PBorrowOptions {
+            amount: 100_00000000u128,
+            terms: 1,
+            interest_rate: 20000,
+            warranty: Some(<Test as system::Trait>::BlockNumber::from(30u32)),
+            liquidation_type: LiquidationType::JustCollateral,
+            rate_type: P2PRateType::Fixed,
+            repayment: P2PRepaymentSchedule::Bullet,
+        };
+        let borrow_id = P2PTest::next_borrow_id();
+        assert_ok!(P2PTest::create_borrow(
+            eve,
+            100000000u128,
+            trading_pair,
+            options,
+        ));
+        let loan_id = P2PTest::next_loan_id();
+        assert_ok!(P2PTest::create_loan(dave, borrow_id));
+
+        next_n_block(86404u32.into());
+        assert_eq!(P2PTest::loans(loan_id).status, P2PLoanHealth::Overdue);
+        assert_noop!(
+            P2PTest::claim_collateral(dave, loan_id),
+            Error::<Test>::LoanNotDefaulted
+        );
+
+        // grace period is over even though no periodic check has run yet
+        SystemTest::set_block_number(345602);
+        assert_noop!(
+            P2PTest::repay_loan(eve, borrow_id),
+            Error::<Test>::GracePeriodExpired
+        );
+        assert_noop!(
+            P2PTest::claim_collateral(eve, loan_id),
+            Error::<Test>::NotOwnerOfLoan
+        );
+        assert_ok!(P2PTest::claim_collateral(dave, loan_id));
+        assert_eq!(P2PTest::loans(loan_id).status, P2PLoanHealth::Liquidated);
+        assert_eq!(GenericAssetTest::free_balance(&BTC, &dave), 1_00000000u128);
+        assert_eq!(
+            P2PTest::borrows(borrow_id).status,
+            P2PBorrowStatus::Liquidated
+        );
+    });
+}
+
+#[test]
+fn add_works() {
+    let root: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Root");
+    let eve: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Eve");
+    let dave: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Dave");
+
+    ExtBuilder::default().build().execute_with(|| {
+        assert_ok!(GenericAssetTest::mint_free(
+            &BTC,
+            &root,
+            &eve,
+            &1000_00000000u128
+        ));
+        assert_ok!(GenericAssetTest::mint_free(
+            &USDT,
+            &root,
+            &dave,
+            &100000_00000000u128
+        ));
+
+        let trading_pair = crate::TradingPair {
+            collateral: BTC,
+            borrow: USDT,
+        };
+        let options = crate::P2PBorrowOptions {
+            amount: 100_0000000u128,
+            terms: 10,
+            interest_rate: 20000,
+            warranty: Some(<Test as system::Trait>::BlockNumber::from(30u32)),
+            liquidation_type: LiquidationType::JustCollateral,
+            rate_type: P2PRateType::Fixed,
+            repayment: P2PRepaymentSchedule::Bullet,
+        };
+        let one_btc = 100000000u128;
+        let borrow_id = P2PTest::next_borrow_id();
+        assert_ok!(P2PTest::create_borrow(eve, one_btc, trading_pair, options));
+        assert_ok!(P2PTest::add_collateral(eve, borrow_id, one_btc));
+        let borrow = P2PTest::borrows(borrow_id);
+        assert_eq!(borrow.collateral_balance, one_btc * 2u128);
+        assert_ok!(P2PTest::create_loan(dave, borrow_id));
+        assert_ok!(P2PTest::add_collateral(eve, borrow_id, one_btc));
+        let borrow = P2PTest::borrows(borrow_id);
+        assert_eq!(borrow.collateral_balance, one_btc * 3u128);
+        let loan = P2PTest::loans(borrow.loan_id.unwrap());
+        assert_eq!(loan.collateral_balance, one_btc * 3u128);
+    });
+}
+
+#[test]
+fn cancel_works() {
+    let root: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Root");
+    let eve: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Eve");
+    let dave: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Dave");
+
+    ExtBuilder::default().build().execute_with(|| {
+        assert_ok!(GenericAssetTest::mint_free(
+            &BTC,
+            &root,
+            &eve,
+            &1000_00000000u128,
+        ));
+        assert_ok!(GenericAssetTest::mint_free(
+            &USDT,
+            &root,
+            &dave,
+            &100000_00000000u128,
+        ));
+
+        let trading_pair = crate::TradingPair {
+            collateral: BTC,
+            borrow: USDT,
+        };
+        let options = crate::P2PBorrowOptions {
+            amount: 100_00000000u128,
+            terms: 10,
+            interest_rate: 20000,
+            warranty: Some(<Test as system::Trait>::BlockNumber::from(30u32)),
+            liquidation_type: LiquidationType::JustCollateral,
+            rate_type: P2PRateType::Fixed,
+            repayment: P2PRepaymentSchedule::Bullet,
+        };
+        let one_btc = 100000000u128;
+        let borrow_id = P2PTest::next_borrow_id();
+        assert_ok!(P2PTest::create_borrow(
+            eve,
+            one_btc,
+            trading_pair.clone(),
+            options.clone(),
+        ));
+
         assert_ok!(P2PTest::remove_borrow(eve, borrow_id));
 
         let borrow_id = P2PTest::next_borrow_id();
@@ -625,6 +1260,9 @@ fn ensure_borrow_available_for_loan_works() {
             terms: 1,
             interest_rate: 20000,
             warranty: Some(<Test as system::Trait>::BlockNumber::from(30u32)),
+            liquidation_type: LiquidationType::JustCollateral,
+            rate_type: P2PRateType::Fixed,
+            repayment: P2PRepaymentSchedule::Bullet,
         };
         let one_btc = 100000000u128;
         let borrow_id = P2PTest::next_borrow_id();
@@ -661,3 +1299,1252 @@ fn ensure_borrow_available_for_loan_works() {
         assert!(P2PTest::ensure_borrow_available_for_loan(borrow_id).is_err());
     });
 }
+
+#[test]
+fn periodic_checks_work() {
+    let root: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Root");
+    let eve: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Eve");
+    let dave: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Dave");
+
+    ExtBuilder::default().build().execute_with(|| {
+        assert_ok!(GenericAssetTest::mint_free(
+            &BTC,
+            &root,
+            &eve,
+            &10_00000000u128,
+        ));
+        assert_ok!(GenericAssetTest::mint_free(
+            &USDT,
+            &root,
+            &dave,
+            &1000_00000000u128,
+        ));
+
+        let trading_pair = crate::TradingPair {
+            collateral: BTC,
+            borrow: USDT,
+        };
+        let options = crate::P2PBorrowOptions {
+            amount: 100_00000000u128,
+            terms: 10,
+            interest_rate: 20000,
+            warranty: Some(<Test as system::Trait>::BlockNumber::from(30u32)),
+            liquidation_type: LiquidationType::JustCollateral,
+            rate_type: P2PRateType::Fixed,
+            repayment: P2PRepaymentSchedule::Bullet,
+        };
+        let one_btc = 100000000u128;
+
+        let mut borrow_ids = vec![];
+        for _ in 0..3 {
+            borrow_ids.push(P2PTest::next_borrow_id());
+            assert_ok!(P2PTest::create_borrow(
+                eve,
+                one_btc,
+                trading_pair.clone(),
+                options.clone()
+            ));
+        }
+        assert_eq!(P2PTest::borrow_ids_by_dead_after(31), borrow_ids);
+
+        let first_loan_id = P2PTest::next_loan_id();
+        assert_ok!(P2PTest::create_loan(dave, borrow_ids[0]));
+        let second_loan_id = P2PTest::next_loan_id();
+        assert_ok!(P2PTest::create_loan(dave, borrow_ids[1]));
+        assert_eq!(
+            P2PTest::live_loan_ids(0),
+            vec![first_loan_id, second_loan_id]
+        );
+
+        // one loan per block, a new pass every 10 blocks
+        assert_ok!(P2PTest::change_loan_check_weight_budget(
+            system::RawOrigin::Root.into(),
+            LOAN_CHECK_WEIGHT
+        ));
+        assert_ok!(P2PTest::change_loan_check_interval(
+            system::RawOrigin::Root.into(),
+            10
+        ));
+
+        let count_events = |target: RawEvent<_, _, _, _, _>| {
+            SystemTest::events()
+                .into_iter()
+                .filter(|r| r.event == MetaEvent::p2p(target.clone()))
+                .count()
+        };
+
+        next_n_block(4);
+        assert_eq!(count_events(RawEvent::CheckingAvailableLoans), 0);
+
+        next_n_block(5);
+        assert_eq!(count_events(RawEvent::CheckingAvailableLoans), 1);
+        assert_eq!(count_events(RawEvent::CheckingAvailableLoansDone), 0);
+        assert_eq!(P2PTest::loan_check_cursor(), Some(second_loan_id));
+
+        // the unfinished pass goes on in the next block
+        next_n_block(1);
+        assert_eq!(count_events(RawEvent::CheckingAvailableLoans), 1);
+        assert_eq!(count_events(RawEvent::CheckingAvailableLoansDone), 1);
+        assert_eq!(P2PTest::loan_check_cursor(), None);
+
+        // the expiring borrow is only picked up on a borrow check block
+        next_n_block(20);
+        assert_eq!(
+            P2PTest::borrows(borrow_ids[2]).status,
+            P2PBorrowStatus::Available
+        );
+        next_n_block(1);
+        assert_eq!(
+            P2PTest::borrows(borrow_ids[2]).status,
+            P2PBorrowStatus::Dead
+        );
+        assert_eq!(
+            P2PTest::borrows(borrow_ids[0]).status,
+            P2PBorrowStatus::Taken
+        );
+        assert_eq!(P2PTest::borrow_ids_by_dead_after(31).len(), 0);
+        assert_eq!(P2PTest::borrows_checked_until(), 32);
+        assert_eq!(GenericAssetTest::reserved_balance(&BTC, &eve), 0);
+
+        // a long gap is caught up BORROW_CHECK_MAX_BLOCKS blocks at a time
+        P2PTest::periodic_check_borrows(1000);
+        assert_eq!(
+            P2PTest::borrows_checked_until(),
+            32 + BORROW_CHECK_MAX_BLOCKS as u64
+        );
+    });
+}
+
+#[test]
+fn tx_checker_works() {
+    let root: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Root");
+    let eve: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Eve");
+    let dave: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Dave");
+    let alice: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Alice");
+    let liquidator: <Test as system::Trait>::AccountId =
+        get_from_seed::<sr25519::Public>("liquidator");
+
+    ExtBuilder::default().build().execute_with(|| {
+        assert_ok!(GenericAssetTest::mint_free(
+            &BTC,
+            &root,
+            &eve,
+            &1000_00000000u128,
+        ));
+        assert_ok!(GenericAssetTest::mint_free(
+            &USDT,
+            &root,
+            &dave,
+            &10000_00000000u128,
+        ));
+        assert_ok!(GenericAssetTest::mint_free(
+            &USDT,
+            &root,
+            &liquidator,
+            &10000_00000000u128,
+        ));
+
+        let checker = P2PTxChecker::<Test>::default();
+        let validate = |who: &<Test as system::Trait>::AccountId, call: crate::Call<Test>| {
+            checker.validate(
+                who,
+                &crate::mock::Call::P2PTest(call),
+                Default::default(),
+                0,
+            )
+        };
+        let invalid = |e: Error<Test>| -> TransactionValidity {
+            Err(InvalidTransaction::Custom(e.as_u8()).into())
+        };
+
+        let trading_pair = crate::TradingPair {
+            collateral: BTC,
+            borrow: USDT,
+        };
+        let options = crate::P2PBorrowOptions {
+            amount: 100_00000000u128,
+            terms: 1,
+            interest_rate: 20000,
+            warranty: Some(<Test as system::Trait>::BlockNumber::from(30u32)),
+            liquidation_type: LiquidationType::SellCollateral,
+            rate_type: P2PRateType::Fixed,
+            repayment: P2PRepaymentSchedule::Bullet,
+        };
+        let borrow_id = P2PTest::next_borrow_id();
+        assert_ok!(P2PTest::create_borrow(
+            eve,
+            100000000u128,
+            trading_pair,
+            options,
+        ));
+
+        // competing takers provide the same tag
+        let borrow_tag = ("p2p_borrow", borrow_id).encode();
+        assert_eq!(
+            validate(&dave, crate::Call::take(borrow_id))
+                .unwrap()
+                .provides,
+            vec![borrow_tag.clone()]
+        );
+        assert_eq!(
+            validate(&alice, crate::Call::take(borrow_id)),
+            invalid(Error::<Test>::NotEnoughBalance)
+        );
+        assert_eq!(
+            validate(&dave, crate::Call::cancel(borrow_id)),
+            invalid(Error::<Test>::NotOwnerOfBorrow)
+        );
+        assert_eq!(
+            validate(&eve, crate::Call::cancel(borrow_id))
+                .unwrap()
+                .provides,
+            vec![borrow_tag]
+        );
+        assert_eq!(
+            validate(&dave, crate::Call::add(borrow_id, 1)),
+            invalid(Error::<Test>::NotOwnerOfBorrow)
+        );
+
+        let loan_id = P2PTest::next_loan_id();
+        assert_ok!(P2PTest::create_loan(dave, borrow_id));
+        assert_eq!(
+            validate(&liquidator, crate::Call::take(borrow_id)),
+            invalid(Error::<Test>::BorrowNotAvailable)
+        );
+
+        // eve got 100 DUSD but also owes the interest
+        let loan_tag = ("p2p_loan", loan_id).encode();
+        assert_eq!(
+            validate(&eve, crate::Call::repay(borrow_id)),
+            invalid(Error::<Test>::NotEnoughBalance)
+        );
+        assert_ok!(GenericAssetTest::mint_free(
+            &USDT,
+            &root,
+            &eve,
+            &1_00000000u128,
+        ));
+        assert_eq!(
+            validate(&eve, crate::Call::repay(borrow_id))
+                .unwrap()
+                .provides,
+            vec![loan_tag.clone()]
+        );
+        assert_eq!(
+            validate(&liquidator, crate::Call::liquidate(loan_id)),
+            invalid(Error::<Test>::LTVNotMeet)
+        );
+
+        // BTC crashes
+        TestPrices::set_price(BTC, 100 * PRICE_SCALE);
+        P2PTest::periodic_check_loans(SystemTest::block_number());
+        assert_eq!(
+            P2PTest::loans(loan_id).status,
+            P2PLoanHealth::ToBeLiquidated
+        );
+        assert_eq!(
+            validate(&eve, crate::Call::repay(borrow_id)),
+            invalid(Error::<Test>::LoanNotWell)
+        );
+        assert_eq!(
+            validate(&eve, crate::Call::liquidate(loan_id)),
+            invalid(Error::<Test>::CanNotLiquidateYourself)
+        );
+        let auction_tag = ("p2p_auction", loan_id).encode();
+        let valid = validate(&liquidator, crate::Call::liquidate(loan_id)).unwrap();
+        assert_eq!(valid.priority, LiquidationPriority::get());
+        assert_eq!(valid.provides, vec![loan_tag, auction_tag.clone()]);
+
+        // bids wait for the liquidation starting the auction
+        let valid = validate(&dave, crate::Call::bid(loan_id)).unwrap();
+        assert_eq!(valid.requires, vec![auction_tag]);
+        assert_eq!(valid.provides, vec![("p2p_bid", loan_id).encode()]);
+        assert_eq!(
+            validate(&eve, crate::Call::bid(loan_id)),
+            invalid(Error::<Test>::CanNotLiquidateYourself)
+        );
+
+        assert_ok!(P2PTest::pause(system::RawOrigin::Root.into()));
+        assert_eq!(
+            validate(&liquidator, crate::Call::liquidate(loan_id)),
+            invalid(Error::<Test>::Paused)
+        );
+    });
+}
+
+#[test]
+fn query_works() {
+    let root: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Root");
+    let eve: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Eve");
+    let dave: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Dave");
+    let alice: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Alice");
+
+    ExtBuilder::default().build().execute_with(|| {
+        assert_ok!(GenericAssetTest::mint_free(
+            &BTC,
+            &root,
+            &eve,
+            &10_00000000u128,
+        ));
+        assert_ok!(GenericAssetTest::mint_free(
+            &USDT,
+            &root,
+            &dave,
+            &1000_00000000u128,
+        ));
+
+        let trading_pair = crate::TradingPair {
+            collateral: BTC,
+            borrow: USDT,
+        };
+        let mut borrow_ids = vec![];
+        for (amount, interest_rate, terms) in &[
+            (100_00000000u128, 20000u64, 10u64),
+            (300_00000000u128, 30000u64, 20u64),
+            (200_00000000u128, 40000u64, 30u64),
+        ] {
+            borrow_ids.push(P2PTest::next_borrow_id());
+            assert_ok!(P2PTest::create_borrow(
+                eve,
+                100000000u128,
+                trading_pair.clone(),
+                crate::P2PBorrowOptions {
+                    amount: *amount,
+                    terms: *terms,
+                    interest_rate: *interest_rate,
+                    warranty: None,
+                    liquidation_type: LiquidationType::JustCollateral,
+                    rate_type: P2PRateType::Fixed,
+                    repayment: P2PRepaymentSchedule::Bullet,
+                },
+            ));
+        }
+        let loan_id = P2PTest::next_loan_id();
+        assert_ok!(P2PTest::create_loan(dave, borrow_ids[1]));
+        assert_eq!(
+            P2PTest::borrow_ids_by_status(P2PBorrowStatus::Available),
+            vec![borrow_ids[0], borrow_ids[2]]
+        );
+        assert_eq!(
+            P2PTest::borrow_ids_by_status(P2PBorrowStatus::Taken),
+            vec![borrow_ids[1]]
+        );
+
+        // cheapest available borrows first, one per page
+        let mut query = P2PQuery {
+            status: Some(P2PBorrowStatus::Available),
+            sort_by: P2PSortBy::Amount,
+            order: P2PSortOrder::Asc,
+            size: Some(1),
+            ..Default::default()
+        };
+        let page = P2PTest::query_borrows(query.clone()).unwrap();
+        assert_eq!(page.items.len(), 1);
+        assert_eq!(page.items[0].id, borrow_ids[0]);
+        assert_eq!(
+            page.next_cursor,
+            Some(P2PCursor {
+                key: 100_00000000u128,
+                id: borrow_ids[0]
+            })
+        );
+        query.cursor = page.next_cursor;
+        let page = P2PTest::query_borrows(query).unwrap();
+        assert_eq!(page.items.len(), 1);
+        assert_eq!(page.items[0].id, borrow_ids[2]);
+        assert_eq!(page.next_cursor, None);
+
+        // newest first by default
+        let page = P2PTest::query_borrows(P2PQuery {
+            trading_pair: Some(trading_pair.clone()),
+            min_interest_rate: Some(25000),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(
+            page.items.iter().map(|b| b.id).collect::<Vec<_>>(),
+            vec![borrow_ids[2], borrow_ids[1]]
+        );
+
+        // both the borrower and the lender find the loan
+        for (who, n) in &[(eve, 1), (dave, 1), (alice, 0)] {
+            let page = P2PTest::query_loans(P2PQuery {
+                account: Some(*who),
+                ..Default::default()
+            })
+            .unwrap();
+            assert_eq!(page.items.len(), *n);
+        }
+        let page = P2PTest::query_loans(P2PQuery {
+            status: Some(P2PLoanHealth::Well),
+            min_terms: Some(15),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(page.items[0].id, loan_id);
+        let page = P2PTest::query_loans(P2PQuery {
+            max_terms: Some(15),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(page.items.len(), 0);
+
+        // without an indexed filter only sorting by id is allowed
+        assert_eq!(
+            P2PTest::query_loans(P2PQuery {
+                min_terms: Some(15),
+                sort_by: P2PSortBy::Amount,
+                ..Default::default()
+            }),
+            None
+        );
+    });
+}
+
+#[test]
+fn platform_fee_works() {
+    let root: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Root");
+    let eve: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Eve");
+    let dave: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Dave");
+    let platform: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Platform");
+
+    ExtBuilder::default().build().execute_with(|| {
+        assert_ok!(GenericAssetTest::mint_free(
+            &BTC,
+            &root,
+            &eve,
+            &10_00000000u128,
+        ));
+        assert_ok!(GenericAssetTest::mint_free(
+            &USDT,
+            &root,
+            &dave,
+            &1000_00000000u128,
+        ));
+
+        let trading_pair = crate::TradingPair {
+            collateral: BTC,
+            borrow: USDT,
+        };
+        let mut config = P2PTest::trading_pair_config(&trading_pair).unwrap();
+        config.interest_fee_rate = FEE_RATE_SCALE + 1;
+        assert_noop!(
+            P2PTest::update_trading_pair(
+                system::RawOrigin::Root.into(),
+                trading_pair.clone(),
+                config.clone()
+            ),
+            Error::<Test>::InvalidTradingPairConfig
+        );
+        // 1% of the amount when taken, 10% of the interest when repaid
+        config.origination_fee_rate = 100;
+        config.interest_fee_rate = 1000;
+        assert_ok!(P2PTest::update_trading_pair(
+            system::RawOrigin::Root.into(),
+            trading_pair.clone(),
+            config
+        ));
+
+        assert_eq!(
+            P2PTest::quote_borrow_cost(&trading_pair, 100_00000000u128, 10, 20000),
+            Some(P2PBorrowQuote {
+                interest: 20000000,
+                origination_fee: 1_00000000,
+                interest_fee: 2000000,
+                total_cost: 1_20000000,
+            })
+        );
+
+        let options = crate::P2PBorrowOptions {
+            amount: 100_00000000u128,
+            terms: 10,
+            interest_rate: 20000,
+            warranty: None,
+            liquidation_type: LiquidationType::JustCollateral,
+            rate_type: P2PRateType::Fixed,
+            repayment: P2PRepaymentSchedule::Bullet,
+        };
+        let borrow_id = P2PTest::next_borrow_id();
+        assert_ok!(P2PTest::create_borrow(
+            eve,
+            1_00000000u128,
+            trading_pair,
+            options
+        ));
+        // enough for the borrower's 99 but not for the fee on top, no loan and nothing moved
+        assert_ok!(GenericAssetTest::make_transfer(
+            &USDT,
+            &dave,
+            &root,
+            900_50000000u128
+        ));
+        assert_noop!(
+            P2PTest::create_loan(dave, borrow_id),
+            Error::<Test>::NotEnoughBalance
+        );
+        assert_ok!(GenericAssetTest::make_transfer(
+            &USDT,
+            &root,
+            &dave,
+            900_50000000u128
+        ));
+        let loan_id = P2PTest::next_loan_id();
+        assert_ok!(P2PTest::create_loan(dave, borrow_id));
+        assert_eq!(P2PTest::loans(loan_id).loan_balance, 100_00000000u128);
+        assert_eq!(GenericAssetTest::free_balance(&USDT, &eve), 99_00000000u128);
+        assert_eq!(
+            GenericAssetTest::free_balance(&USDT, &platform),
+            1_00000000u128
+        );
+        assert_eq!(
+            GenericAssetTest::free_balance(&USDT, &dave),
+            900_00000000u128
+        );
+
+        assert_ok!(GenericAssetTest::mint_free(
+            &USDT,
+            &root,
+            &eve,
+            &2_00000000u128,
+        ));
+        assert_ok!(P2PTest::repay_loan(eve, borrow_id));
+        assert_eq!(GenericAssetTest::free_balance(&USDT, &eve), 80000000u128);
+        assert_eq!(
+            GenericAssetTest::free_balance(&USDT, &platform),
+            1_02000000u128
+        );
+        assert_eq!(
+            GenericAssetTest::free_balance(&USDT, &dave),
+            1000_18000000u128
+        );
+
+        let fee_events = SystemTest::events()
+            .into_iter()
+            .map(|r| r.event)
+            .filter_map(|e| {
+                if let MetaEvent::p2p(inner) = e {
+                    match inner {
+                        RawEvent::OriginationFeePaid(_, _) | RawEvent::InterestFeePaid(_, _) => {
+                            Some(inner)
+                        }
+                        _ => None,
+                    }
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            fee_events,
+            vec![
+                RawEvent::OriginationFeePaid(loan_id, 1_00000000u128),
+                RawEvent::InterestFeePaid(loan_id, 2000000u128),
+            ]
+        );
+    });
+}
+
+#[test]
+fn credit_record_works() {
+    let root: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Root");
+    let eve: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Eve");
+    let dave: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Dave");
+
+    ExtBuilder::default().build().execute_with(|| {
+        assert_ok!(GenericAssetTest::mint_free(
+            &BTC,
+            &root,
+            &eve,
+            &10_00000000u128,
+        ));
+        assert_ok!(GenericAssetTest::mint_free(
+            &USDT,
+            &root,
+            &dave,
+            &10000_00000000u128,
+        ));
+
+        let trading_pair = crate::TradingPair {
+            collateral: BTC,
+            borrow: USDT,
+        };
+        let mut config = P2PTest::trading_pair_config(&trading_pair).unwrap();
+        config.reputation_tier = 1;
+        config.reputation_safe_ltv = config.liquidate_ltv;
+        assert_noop!(
+            P2PTest::update_trading_pair(
+                system::RawOrigin::Root.into(),
+                trading_pair.clone(),
+                config.clone()
+            ),
+            Error::<Test>::InvalidTradingPairConfig
+        );
+        config.reputation_safe_ltv = 20000;
+        assert_ok!(P2PTest::update_trading_pair(
+            system::RawOrigin::Root.into(),
+            trading_pair.clone(),
+            config
+        ));
+
+        // LTV of 1 BTC against 4000 USDT is 25000
+        let large_options = crate::P2PBorrowOptions {
+            amount: 4000_00000000u128,
+            terms: 10,
+            interest_rate: 20000,
+            warranty: None,
+            liquidation_type: LiquidationType::JustCollateral,
+            rate_type: P2PRateType::Fixed,
+            repayment: P2PRepaymentSchedule::Bullet,
+        };
+        assert_noop!(
+            P2PTest::create_borrow(
+                eve.clone(),
+                1_00000000u128,
+                trading_pair.clone(),
+                large_options.clone()
+            ),
+            Error::<Test>::InitialCollateralRateFail
+        );
+
+        let borrow_id = P2PTest::next_borrow_id();
+        assert_ok!(P2PTest::create_borrow(
+            eve.clone(),
+            1_00000000u128,
+            trading_pair.clone(),
+            crate::P2PBorrowOptions {
+                amount: 100_00000000u128,
+                terms: 10,
+                interest_rate: 20000,
+                warranty: None,
+                liquidation_type: LiquidationType::JustCollateral,
+                rate_type: P2PRateType::Fixed,
+                repayment: P2PRepaymentSchedule::Bullet,
+            }
+        ));
+        assert_noop!(
+            P2PTest::create_loan(eve.clone(), borrow_id),
+            Error::<Test>::CanNotLendToYourself
+        );
+        assert_ok!(P2PTest::create_loan(dave.clone(), borrow_id));
+        assert_ok!(GenericAssetTest::mint_free(
+            &USDT,
+            &root,
+            &eve,
+            &1_00000000u128,
+        ));
+        assert_ok!(P2PTest::repay_loan(eve.clone(), borrow_id));
+
+        let record = P2PTest::credit_record(&eve);
+        assert_eq!(
+            record,
+            P2PCreditRecord {
+                repaid_on_time: 1,
+                repaid_overdue: 0,
+                liquidated: 0,
+                weighted_days: 10,
+                total_volume: 100_00000000u128,
+            }
+        );
+        assert_eq!(P2PTest::credit_record(&dave), Default::default());
+
+        let borrow_id = P2PTest::next_borrow_id();
+        assert_ok!(P2PTest::create_borrow(
+            eve.clone(),
+            1_00000000u128,
+            trading_pair.clone(),
+            large_options
+        ));
+        assert_ok!(P2PTest::create_loan(dave.clone(), borrow_id));
+
+        // repaid after due, before the periodic check marked the loan overdue
+        let borrow_id = P2PTest::next_borrow_id();
+        assert_ok!(P2PTest::create_borrow(
+            eve.clone(),
+            1_00000000u128,
+            trading_pair,
+            crate::P2PBorrowOptions {
+                amount: 100_00000000u128,
+                terms: 10,
+                interest_rate: 20000,
+                warranty: None,
+                liquidation_type: LiquidationType::JustCollateral,
+                rate_type: P2PRateType::Fixed,
+                repayment: P2PRepaymentSchedule::Bullet,
+            }
+        ));
+        let loan_id = P2PTest::next_loan_id();
+        assert_ok!(P2PTest::create_loan(dave, borrow_id));
+        SystemTest::set_block_number(P2PTest::loans(loan_id).due + 1);
+        assert_eq!(P2PTest::loans(loan_id).status, P2PLoanHealth::Well);
+        assert_ok!(GenericAssetTest::mint_free(
+            &USDT,
+            &root,
+            &eve,
+            &100_00000000u128,
+        ));
+        assert_ok!(P2PTest::repay_loan(eve.clone(), borrow_id));
+        let record = P2PTest::credit_record(&eve);
+        assert_eq!(record.repaid_overdue, 1);
+        assert_eq!(record.weighted_days, 0);
+    });
+}
+
+#[test]
+fn margin_call_works() {
+    let root: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Root");
+    let eve: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Eve");
+    let dave: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Dave");
+    let liquidator: <Test as system::Trait>::AccountId =
+        get_from_seed::<sr25519::Public>("liquidator");
+
+    ExtBuilder::default().build().execute_with(|| {
+        assert_ok!(GenericAssetTest::mint_free(
+            &BTC,
+            &root,
+            &eve,
+            &10_00000000u128,
+        ));
+        assert_ok!(GenericAssetTest::mint_free(
+            &USDT,
+            &root,
+            &dave,
+            &10000_00000000u128,
+        ));
+
+        let trading_pair = crate::TradingPair {
+            collateral: BTC,
+            borrow: USDT,
+        };
+        let mut config = P2PTest::trading_pair_config(&trading_pair).unwrap();
+        config.margin_call_ltv = config.safe_ltv;
+        assert_noop!(
+            P2PTest::update_trading_pair(
+                system::RawOrigin::Root.into(),
+                trading_pair.clone(),
+                config.clone()
+            ),
+            Error::<Test>::InvalidTradingPairConfig
+        );
+        config.margin_call_ltv = 20000;
+        config.margin_call_window = 10;
+        assert_ok!(P2PTest::update_trading_pair(
+            system::RawOrigin::Root.into(),
+            trading_pair.clone(),
+            config
+        ));
+
+        let borrow_id = P2PTest::next_borrow_id();
+        assert_ok!(P2PTest::create_borrow(
+            eve,
+            1_00000000u128,
+            trading_pair,
+            crate::P2PBorrowOptions {
+                amount: 100_00000000u128,
+                terms: 10,
+                interest_rate: 20000,
+                warranty: None,
+                liquidation_type: LiquidationType::JustCollateral,
+                rate_type: P2PRateType::Fixed,
+                repayment: P2PRepaymentSchedule::Bullet,
+            }
+        ));
+        let loan_id = P2PTest::next_loan_id();
+        assert_ok!(P2PTest::create_loan(dave, borrow_id));
+
+        let set_btc_price = |price: u64| {
+            TestPrices::set_price(BTC, price * PRICE_SCALE);
+        };
+        let last_margin_event = || {
+            SystemTest::events()
+                .into_iter()
+                .map(|r| r.event)
+                .filter_map(|e| {
+                    if let MetaEvent::p2p(inner) = e {
+                        match inner {
+                            RawEvent::LoanMarginCalled(_, _) | RawEvent::MarginCallCleared(_) => {
+                                Some(inner)
+                            }
+                            _ => None,
+                        }
+                    } else {
+                        None
+                    }
+                })
+                .last()
+        };
+
+        // ltv 18000, between margin_call_ltv and liquidate_ltv
+        set_btc_price(180);
+        P2PTest::periodic_check_loans(SystemTest::block_number());
+        assert_eq!(P2PTest::loans(loan_id).status, P2PLoanHealth::MarginCall);
+        assert_eq!(P2PTest::margin_call_deadline(loan_id), Some(11));
+        assert_eq!(
+            last_margin_event(),
+            Some(RawEvent::LoanMarginCalled(loan_id, 11))
+        );
+
+        // even below liquidate_ltv, the loan is protected during the window
+        set_btc_price(100);
+        P2PTest::periodic_check_loans(SystemTest::block_number());
+        assert_eq!(P2PTest::loans(loan_id).status, P2PLoanHealth::MarginCall);
+        assert_noop!(
+            P2PTest::liquidate_loan(liquidator, loan_id),
+            Error::<Test>::MarginCallPending
+        );
+
+        // topping up to ltv 30000 clears the margin call
+        assert_ok!(P2PTest::add_collateral(eve, borrow_id, 2_00000000u128));
+        assert_eq!(P2PTest::loans(loan_id).status, P2PLoanHealth::Well);
+        assert_eq!(P2PTest::margin_call_deadline(loan_id), None);
+        assert_eq!(
+            last_margin_event(),
+            Some(RawEvent::MarginCallCleared(loan_id))
+        );
+        assert_noop!(
+            P2PTest::liquidate_loan(liquidator, loan_id),
+            Error::<Test>::MarginCallPending
+        );
+
+        // ltv 15000, margin called again and not topped up in time
+        set_btc_price(50);
+        P2PTest::periodic_check_loans(SystemTest::block_number());
+        assert_eq!(P2PTest::loans(loan_id).status, P2PLoanHealth::MarginCall);
+        SystemTest::set_block_number(12);
+        P2PTest::periodic_check_loans(SystemTest::block_number());
+        assert_eq!(
+            P2PTest::loans(loan_id).status,
+            P2PLoanHealth::ToBeLiquidated
+        );
+        assert_eq!(P2PTest::margin_call_deadline(loan_id), None);
+        assert_ok!(P2PTest::liquidate_loan(liquidator, loan_id));
+        assert_eq!(P2PTest::loans(loan_id).status, P2PLoanHealth::Liquidated);
+    });
+}
+
+#[test]
+fn auto_liquidation_works() {
+    let root: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Root");
+    let eve: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Eve");
+    let dave: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Dave");
+    let platform: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Platform");
+
+    ExtBuilder::default().build().execute_with(|| {
+        assert_ok!(GenericAssetTest::mint_free(
+            &BTC,
+            &root,
+            &eve,
+            &10_00000000u128,
+        ));
+        assert_ok!(GenericAssetTest::mint_free(
+            &USDT,
+            &root,
+            &dave,
+            &10000_00000000u128,
+        ));
+
+        let trading_pair = crate::TradingPair {
+            collateral: BTC,
+            borrow: USDT,
+        };
+        let mut config = P2PTest::trading_pair_config(&trading_pair).unwrap();
+        config.auto_liquidation = true;
+        assert_ok!(P2PTest::update_trading_pair(
+            system::RawOrigin::Root.into(),
+            trading_pair.clone(),
+            config
+        ));
+
+        let borrow_id = P2PTest::next_borrow_id();
+        assert_ok!(P2PTest::create_borrow(
+            eve,
+            1_00000000u128,
+            trading_pair,
+            crate::P2PBorrowOptions {
+                amount: 100_00000000u128,
+                terms: 10,
+                interest_rate: 20000,
+                warranty: None,
+                liquidation_type: LiquidationType::SellCollateral,
+                rate_type: P2PRateType::Fixed,
+                repayment: P2PRepaymentSchedule::Bullet,
+            }
+        ));
+        let loan_id = P2PTest::next_loan_id();
+        assert_ok!(P2PTest::create_loan(dave, borrow_id));
+
+        // ltv 14000, no liquidator needed
+        TestPrices::set_price(BTC, 140 * PRICE_SCALE);
+        // nothing is paid out while the money pool can't hand over all the collateral, the next check tries again
+        let money_pool = P2PTest::money_pool();
+        assert_ok!(P2PTest::change_money_pool(
+            system::RawOrigin::Root.into(),
+            get_from_seed::<sr25519::Public>("Nobody")
+        ));
+        P2PTest::periodic_check_loans(SystemTest::block_number());
+        assert_eq!(
+            P2PTest::loans(loan_id).status,
+            P2PLoanHealth::ToBeLiquidated
+        );
+        assert_eq!(GenericAssetTest::free_balance(&BTC, &dave), 0);
+        assert_ok!(P2PTest::change_money_pool(
+            system::RawOrigin::Root.into(),
+            money_pool
+        ));
+        P2PTest::periodic_check_loans(SystemTest::block_number());
+        assert_eq!(P2PTest::loans(loan_id).status, P2PLoanHealth::Liquidated);
+        assert_eq!(P2PTest::auctions(loan_id), None);
+
+        // debt of 100.2 USDT is worth 0.71571428 BTC, bought at 90% of the price
+        let to_lender = 79523809u128;
+        let penalty = (1_00000000u128 - to_lender) / 2;
+        assert_eq!(GenericAssetTest::free_balance(&BTC, &dave), to_lender);
+        assert_eq!(GenericAssetTest::free_balance(&BTC, &platform), penalty);
+        assert_eq!(
+            GenericAssetTest::free_balance(&BTC, &eve),
+            9_00000000u128 + 1_00000000u128 - to_lender - penalty
+        );
+        assert_eq!(P2PTest::credit_record(&eve).liquidated, 1);
+        assert_eq!(P2PTest::credit_record(&eve).weighted_days, 0);
+        assert!(SystemTest::events()
+            .into_iter()
+            .map(|r| r.event)
+            .any(|e| e == MetaEvent::p2p(RawEvent::LoanAutoLiquidated(loan_id, to_lender))));
+    });
+}
+
+#[test]
+fn variable_rate_works() {
+    let root: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Root");
+    let eve: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Eve");
+    let dave: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Dave");
+
+    ExtBuilder::default().build().execute_with(|| {
+        assert_ok!(GenericAssetTest::mint_free(
+            &BTC,
+            &root,
+            &eve,
+            &10_00000000u128,
+        ));
+        assert_ok!(GenericAssetTest::mint_free(
+            &USDT,
+            &root,
+            &dave,
+            &1000_00000000u128,
+        ));
+
+        let trading_pair = crate::TradingPair {
+            collateral: BTC,
+            borrow: USDT,
+        };
+        let options = crate::P2PBorrowOptions {
+            amount: 100_00000000u128,
+            terms: 10,
+            interest_rate: 5000,
+            warranty: None,
+            liquidation_type: LiquidationType::JustCollateral,
+            rate_type: P2PRateType::Variable,
+            repayment: P2PRepaymentSchedule::Bullet,
+        };
+        // the spread alone is below min_borrow_interest_rate
+        assert_noop!(
+            P2PTest::create_borrow(eve, 1_00000000u128, trading_pair.clone(), options.clone()),
+            Error::<Test>::MinBorrowInterestRate
+        );
+
+        REFERENCE_RATE.with(|v| *v.borrow_mut() = 15000);
+        let borrow_id = P2PTest::next_borrow_id();
+        assert_ok!(P2PTest::create_borrow(
+            eve,
+            1_00000000u128,
+            trading_pair,
+            options
+        ));
+        let loan_id = P2PTest::next_loan_id();
+        assert_ok!(P2PTest::create_loan(dave, borrow_id));
+        let loan = P2PTest::loans(loan_id);
+        assert_eq!(loan.rate_type, P2PRateType::Variable);
+        assert_eq!(P2PTest::loan_interest_rate(&loan), 20000);
+
+        // half a day at 20000, then the reference moves
+        SystemTest::set_block_number(43201);
+        REFERENCE_RATE.with(|v| *v.borrow_mut() = 35000);
+        P2PTest::periodic_check_loans(SystemTest::block_number());
+        assert_eq!(
+            P2PTest::loan_accrual(loan_id),
+            Some(P2PInterestAccrual {
+                rate: 40000,
+                interest: 1000000,
+                accrued_at: 43201,
+            })
+        );
+
+        // another half a day at 40000
+        SystemTest::set_block_number(86401);
+        let borrow = P2PTest::borrows(borrow_id);
+        assert_eq!(
+            P2PTest::calculate_loan_interest(&borrow, &loan, SystemTest::block_number()),
+            3000000
+        );
+        assert_ok!(GenericAssetTest::mint_free(
+            &USDT,
+            &root,
+            &eve,
+            &1_00000000u128,
+        ));
+        assert_ok!(P2PTest::repay_loan(eve, borrow_id));
+        assert_eq!(
+            GenericAssetTest::free_balance(&USDT, &dave),
+            1000_03000000u128
+        );
+        assert_eq!(GenericAssetTest::free_balance(&USDT, &eve), 97000000u128);
+        assert_eq!(P2PTest::loan_accrual(loan_id), None);
+    });
+}
+
+#[test]
+fn installment_works() {
+    let root: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Root");
+    let eve: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Eve");
+    let dave: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Dave");
+
+    ExtBuilder::default().build().execute_with(|| {
+        assert_ok!(GenericAssetTest::mint_free(
+            &BTC,
+            &root,
+            &eve,
+            &10_00000000u128,
+        ));
+        assert_ok!(GenericAssetTest::mint_free(
+            &USDT,
+            &root,
+            &dave,
+            &1000_00000000u128,
+        ));
+
+        let trading_pair = crate::TradingPair {
+            collateral: BTC,
+            borrow: USDT,
+        };
+        let mut options = crate::P2PBorrowOptions {
+            amount: 100_00000000u128,
+            terms: 14,
+            interest_rate: 20000,
+            warranty: None,
+            liquidation_type: LiquidationType::JustCollateral,
+            rate_type: P2PRateType::Variable,
+            repayment: P2PRepaymentSchedule::Weekly,
+        };
+        assert_noop!(
+            P2PTest::create_borrow(eve, 1_00000000u128, trading_pair.clone(), options.clone()),
+            Error::<Test>::InstallmentsNeedFixedRate
+        );
+        options.rate_type = P2PRateType::Fixed;
+        let borrow_id = P2PTest::next_borrow_id();
+        assert_ok!(P2PTest::create_borrow(
+            eve,
+            1_00000000u128,
+            trading_pair,
+            options
+        ));
+        let loan_id = P2PTest::next_loan_id();
+        assert_ok!(P2PTest::create_loan(dave, borrow_id));
+
+        // the second week only pays interest on the half still outstanding
+        assert_eq!(
+            P2PTest::loan_installments(loan_id),
+            vec![
+                P2PInstallment {
+                    due: 1 + 7 * 86400,
+                    principal: 50_00000000u128,
+                    interest: 14000000u128,
+                    paid: false,
+                },
+                P2PInstallment {
+                    due: 1 + 14 * 86400,
+                    principal: 50_00000000u128,
+                    interest: 7000000u128,
+                    paid: false,
+                },
+            ]
+        );
+        let loan = P2PTest::loans(loan_id);
+        assert_eq!(P2PTest::current_due(&loan), 1 + 7 * 86400);
+        assert_eq!(
+            P2PTest::calculate_loan_interest(&P2PTest::borrows(borrow_id), &loan, 1),
+            21000000u128
+        );
+
+        // the first installment is missed
+        SystemTest::set_block_number(2 + 7 * 86400);
+        P2PTest::periodic_check_loans(SystemTest::block_number());
+        assert_eq!(P2PTest::loans(loan_id).status, P2PLoanHealth::Overdue);
+
+        // short of the installment, the lender isn't paid a part of it either
+        assert_ok!(GenericAssetTest::make_transfer(
+            &USDT,
+            &eve,
+            &root,
+            60_00000000u128
+        ));
+        assert_noop!(
+            P2PTest::pay_loan_installment(eve, borrow_id),
+            Error::<Test>::NotEnoughBalance
+        );
+        assert_ok!(GenericAssetTest::make_transfer(
+            &USDT,
+            &root,
+            &eve,
+            60_00000000u128
+        ));
+
+        // paid late with a day of penalty, the loan is well again
+        assert_ok!(P2PTest::pay_loan_installment(eve, borrow_id));
+        let loan = P2PTest::loans(loan_id);
+        assert_eq!(loan.status, P2PLoanHealth::Well);
+        assert_eq!(loan.loan_balance, 50_00000000u128);
+        assert_eq!(P2PTest::current_due(&loan), 1 + 14 * 86400);
+        assert_eq!(GenericAssetTest::free_balance(&USDT, &eve), 49_85000000u128);
+        assert!(SystemTest::events()
+            .into_iter()
+            .map(|r| r.event)
+            .any(|e| e == MetaEvent::p2p(RawEvent::InstallmentPaid(loan_id, 0))));
+
+        // the last installment repays the loan
+        assert_ok!(GenericAssetTest::mint_free(
+            &USDT,
+            &root,
+            &eve,
+            &1_00000000u128,
+        ));
+        assert_ok!(P2PTest::pay_loan_installment(eve, borrow_id));
+        assert_eq!(P2PTest::loans(loan_id).status, P2PLoanHealth::Completed);
+        assert_eq!(P2PTest::loan_installments(loan_id), vec![]);
+        assert_eq!(GenericAssetTest::free_balance(&USDT, &eve), 78000000u128);
+        assert_eq!(
+            GenericAssetTest::free_balance(&USDT, &dave),
+            1000_22000000u128
+        );
+        assert_eq!(
+            P2PTest::pay_loan_installment(eve, borrow_id),
+            Err(Error::<Test>::NoInstallmentDue.into())
+        );
+    });
+}
+
+#[test]
+fn storage_migration_works() {
+    let eve: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Eve");
+    let dave: <Test as system::Trait>::AccountId = get_from_seed::<sr25519::Public>("Dave");
+
+    ExtBuilder::default().build().execute_with(|| {
+        let trading_pair = crate::TradingPair {
+            collateral: BTC,
+            borrow: USDT,
+        };
+        // what was stored before STORAGE_VERSION 1, the legacy layouts encode as tuples of their fields
+        StorageVersion::put(0);
+        <TradingPairConfigs<Test>>::remove(&trading_pair);
+        sp_io::storage::set(&P2PTest::value_key(b"SafeLTV"), &20000u32.encode());
+        sp_io::storage::set(&P2PTest::value_key(b"LiquidateLTV"), &15000u32.encode());
+        sp_io::storage::set(&P2PTest::value_key(b"MinBorrowTerms"), &1u64.encode());
+        sp_io::storage::set(
+            &P2PTest::value_key(b"MinBorrowInterestRate"),
+            &10u64.encode(),
+        );
+        sp_io::storage::set(
+            &P2PTest::value_key(b"AccountIdsWithLiveLoans"),
+            &vec![dave].encode(),
+        );
+        let legacy_borrow = |id: u128, status: P2PBorrowStatus, loan_id: Option<u128>| {
+            (
+                id,
+                id,
+                eve,
+                status,
+                USDT,
+                BTC,
+                100_00000000u128,
+                1_00000000u128,
+                10u64,
+                20000u64,
+                Some(50u64),
+                loan_id,
+            )
+                .encode()
+        };
+        sp_io::storage::set(
+            &<Borrows<Test>>::hashed_key_for(1),
+            &legacy_borrow(1, P2PBorrowStatus::Taken, Some(1)),
+        );
+        sp_io::storage::set(
+            &<Borrows<Test>>::hashed_key_for(2),
+            &legacy_borrow(2, P2PBorrowStatus::Available, None),
+        );
+        sp_io::storage::set(
+            &<Loans<Test>>::hashed_key_for(1),
+            &(
+                1u128,
+                1u128,
+                eve,
+                dave,
+                864001u64,
+                BTC,
+                1_00000000u128,
+                100_00000000u128,
+                USDT,
+                P2PLoanHealth::Well,
+                20000u64,
+                LiquidationType::SellCollateral,
+            )
+                .encode(),
+        );
+        NextBorrowId::put(3);
+        NextLoanId::put(2);
+
+        SystemTest::set_block_number(100);
+        P2PTest::on_initialize(100);
+        assert_eq!(P2PTest::storage_version(), STORAGE_VERSION);
+
+        let borrow = P2PTest::borrows(1);
+        assert_eq!(borrow.loan_id, Some(1));
+        assert_eq!(borrow.liquidation_type, LiquidationType::JustCollateral);
+        assert_eq!(borrow.rate_type, P2PRateType::Fixed);
+        assert_eq!(borrow.repayment, P2PRepaymentSchedule::Bullet);
+        let loan = P2PTest::loans(1);
+        assert_eq!(loan.loaner_id, dave);
+        assert_eq!(loan.liquidation_type, LiquidationType::SellCollateral);
+        assert_eq!(loan.rate_type, P2PRateType::Fixed);
+        assert_eq!(loan.repayment, P2PRepaymentSchedule::Bullet);
+
+        assert_eq!(
+            P2PTest::trading_pair_config(&trading_pair),
+            Some(TradingPairConfig {
+                safe_ltv: 20000,
+                liquidate_ltv: 15000,
+                min_borrow_terms: 1,
+                max_borrow_terms: u64::max_value(),
+                min_borrow_interest_rate: 10,
+                enabled: true,
+                ..Default::default()
+            })
+        );
+        for item in &[&b"SafeLTV"[..], b"AccountIdsWithLiveLoans"] {
+            assert_eq!(sp_io::storage::get(&P2PTest::value_key(item)), None);
+        }
+
+        assert_eq!(P2PTest::live_loan_ids(0), vec![1]);
+        assert_eq!(P2PTest::loan_ids_by_borrower_id(&eve), vec![1]);
+        assert_eq!(P2PTest::loan_ids_by_trading_pair(&trading_pair), vec![1]);
+        assert_eq!(
+            P2PTest::borrow_ids_by_trading_pair(&trading_pair),
+            vec![1, 2]
+        );
+        assert_eq!(P2PTest::available_borrow_ids_by_account_id(&eve), vec![2]);
+
+        // the borrow dead before the upgrade is found by the next check
+        assert_eq!(P2PTest::borrows_checked_until(), 99);
+        assert_eq!(P2PTest::borrow_ids_by_dead_after(100), vec![2]);
+        P2PTest::periodic_check_borrows(100);
+        assert_eq!(P2PTest::borrows(2).status, P2PBorrowStatus::Dead);
+    });
+}
@@ -93,6 +93,7 @@ where
     C::Api: generic_asset_rpc::GenericAssetRuntimeApi<Block, AssetId, Balance, AccountId>,
     C::Api: p2p_rpc::P2PRuntimeApi<Block, AssetId, Balance, BlockNumber, AccountId, Moment>,
     C::Api: deposit_loan_rpc::DepositLoanRuntimeApi<Block, AccountId, Balance>,
+    C::Api: new_oracle_rpc::NewOracleRuntimeApi<Block, AssetId, BlockNumber, Moment, u64>,
     C::Api: BabeApi<Block>,
     <C::Api as sp_api::ApiErrorExt>::Error: fmt::Debug,
     P: TransactionPool + 'static,
@@ -101,6 +102,7 @@ where
 {
     use deposit_loan_rpc::{DepositLoan, DepositLoanApi};
     use generic_asset_rpc::{GenericAsset, GenericAssetApi};
+    use new_oracle_rpc::{NewOracle, NewOracleApi};
     use p2p_rpc::{P2PApi, P2P};
     use pallet_contracts_rpc::{Contracts, ContractsApi};
     use pallet_transaction_payment_rpc::{TransactionPayment, TransactionPaymentApi};
@@ -137,6 +139,7 @@ where
     io.extend_with(DepositLoanApi::to_delegate(DepositLoan::new(
         client.clone(),
     )));
+    io.extend_with(NewOracleApi::to_delegate(NewOracle::new(client.clone())));
     io.extend_with(sc_consensus_babe_rpc::BabeApi::to_delegate(
         BabeRPCHandler::new(
             client,
@@ -20,7 +20,7 @@ use node_primitives::Balance;
 use sp_runtime::traits::{Convert, Saturating};
 use sp_runtime::{Fixed64, Perbill};
 use frame_support::{traits::{OnUnbalanced, Currency, Get}, weights::Weight};
-use crate::{Balances, System, Authorship, MaximumBlockWeight, NegativeImbalance};
+use crate::{Balances, System, Authorship, MaximumBlockWeight, NegativeImbalance, DepositLoan};
 
 pub struct Author;
 impl OnUnbalanced<NegativeImbalance> for Author {
@@ -29,6 +29,16 @@ impl OnUnbalanced<NegativeImbalance> for Author {
 	}
 }
 
+/// The daily loan rate of deposit-loan, variable rate p2p loans float over it.
+/// Both pallets scale rates by 1e8, deposit-loan's is annual.
+pub struct DepositLoanReferenceRate;
+impl Get<u64> for DepositLoanReferenceRate {
+	fn get() -> u64 {
+		(DepositLoan::loan_interest_rate_current()
+			/ deposit_loan_primitives::DAYS_PER_YEAR as Balance) as u64
+	}
+}
+
 /// Struct that handles the conversion of Balance -> `u64`. This is used for staking's election
 /// calculation.
 pub struct CurrencyToVoteHandler;
@@ -22,7 +22,7 @@
 
 use frame_support::{
     construct_runtime, debug, parameter_types,
-    traits::{Currency, Imbalance, OnUnbalanced, Randomness},
+    traits::{Currency, Get, Imbalance, OnUnbalanced, Randomness},
     weights::Weight,
 };
 use frame_system::offchain::TransactionSubmitter;
@@ -42,7 +42,7 @@ use sp_runtime::curve::PiecewiseLinear;
 use sp_runtime::traits::{
     self, BlakeTwo256, Block as BlockT, ConvertInto, OpaqueKeys, SaturatedConversion, StaticLookup,
 };
-use sp_runtime::transaction_validity::TransactionValidity;
+use sp_runtime::transaction_validity::{TransactionPriority, TransactionValidity};
 use sp_runtime::{
     create_runtime_str, generic, impl_opaque_keys, ApplyExtrinsicResult, Perbill, Percent, Permill,
 };
@@ -53,7 +53,7 @@ use sp_version::RuntimeVersion;
 
 pub use bridge;
 pub use frame_support::StorageValue;
-pub use p2p::TradingPair;
+pub use p2p::{TradingPair, TradingPairConfig};
 pub use pallet_balances::Call as BalancesCall;
 pub use pallet_contracts::Gas;
 pub use pallet_staking::StakerStatus;
@@ -63,7 +63,10 @@ pub use sp_runtime::BuildStorage;
 
 /// Implementations of some helper traits passed into runtime modules as associated types.
 pub mod impls;
-use impls::{Author, CurrencyToVoteHandler, LinearWeightToFee, TargetedFeeAdjustment};
+use impls::{
+    Author, CurrencyToVoteHandler, DepositLoanReferenceRate, LinearWeightToFee,
+    TargetedFeeAdjustment,
+};
 
 /// Constant values used within the runtime.
 pub mod constants;
@@ -73,7 +76,10 @@ use constants::{currency::*, time::*};
 #[cfg(feature = "std")]
 include!(concat!(env!("OUT_DIR"), "/wasm_binary.rs"));
 
+pub use new_oracle::crypto::Public as OracleId;
 pub use new_oracle::PRICE_SCALE as ORACLE_PRICE_SCALE;
+pub use new_oracle::{FormulaOp, JsonPathSegment, PriceSource};
+pub use p2p::LOAN_CHECK_WEIGHT as P2P_LOAN_CHECK_WEIGHT;
 
 /// Runtime version.
 pub const VERSION: RuntimeVersion = RuntimeVersion {
@@ -84,8 +90,8 @@ pub const VERSION: RuntimeVersion = RuntimeVersion {
     // and set impl_version to 0. If only runtime
     // implementation changes and behavior does not, then leave spec_version as
     // is and increment impl_version.
-    spec_version: 240,
-    impl_version: 1,
+    spec_version: 241,
+    impl_version: 0,
     apis: RUNTIME_API_VERSIONS,
 };
 
@@ -661,15 +667,22 @@ impl bridge::Trait for Runtime {
 
 parameter_types! {
     pub const DaysInBlockNumber: BlockNumber = 1 * DAYS;
+    pub const P2PAuctionDuration: BlockNumber = 1 * HOURS;
+    pub const P2PLiquidationPriority: TransactionPriority = TransactionPriority::max_value() / 2;
 }
 impl p2p::Trait for Runtime {
     type Event = Event;
     type Days = DaysInBlockNumber;
     type Call = Call;
+    type AuctionDuration = P2PAuctionDuration;
+    type LiquidationPriority = P2PLiquidationPriority;
+    type ReferenceRate = DepositLoanReferenceRate;
+    type PriceProvider = NewOracle;
 }
 
 impl deposit_loan::Trait for Runtime {
     type Event = Event;
+    type PriceProvider = NewOracle;
 }
 
 type SubmitOracleTransaction =
@@ -677,6 +690,7 @@ type SubmitOracleTransaction =
 
 parameter_types! {
     pub const AggregateInterval: BlockNumber = 5;
+    pub const FetchInterval: BlockNumber = 2;
 }
 
 impl new_oracle::Trait for Runtime {
@@ -685,6 +699,7 @@ impl new_oracle::Trait for Runtime {
     type SubmitUnsignedTransaction = SubmitOracleTransaction;
     type SubmitSignedTransaction = SubmitOracleTransaction;
     type AggregateInterval = AggregateInterval;
+    type FetchInterval = FetchInterval;
     type PriceInUSDT = u64;
 }
 
@@ -942,9 +957,91 @@ impl_runtime_apis! {
             PToP::get_user_loans(who, size, offset)
         }
 
+        fn query_borrows(query: p2p_primitives::P2PQuery<AssetId, Balance, AccountId, p2p_primitives::P2PBorrowStatus>) -> Option<p2p_primitives::P2PPage<p2p_primitives::P2PBorrow<AssetId, Balance, BlockNumber, AccountId>>> {
+            PToP::query_borrows(query)
+        }
+
+        fn query_loans(query: p2p_primitives::P2PQuery<AssetId, Balance, AccountId, p2p_primitives::P2PLoanHealth>) -> Option<p2p_primitives::P2PPage<p2p_primitives::P2PLoan<AssetId, Balance, BlockNumber, AccountId>>> {
+            PToP::query_loans(query)
+        }
+
+        fn get_auctions(size: Option<u64>, offset: Option<u64>) -> Vec<p2p_primitives::P2PAuction<AssetId, Balance, BlockNumber>> {
+            PToP::get_auctions(size, offset)
+        }
+
+        fn get_borrow_quote(trading_pair: p2p_primitives::TradingPair<AssetId>, amount: Balance, terms: u64, interest_rate: u64) -> Option<p2p_primitives::P2PBorrowQuote<Balance>> {
+            PToP::quote_borrow_cost(&trading_pair, amount, terms, interest_rate)
+        }
+
+        fn get_credit_record(who: AccountId) -> p2p_primitives::P2PCreditRecord<Balance> {
+            PToP::credit_record(who)
+        }
+
         fn get_secs_per_block() -> Moment {
             SECS_PER_BLOCK
         }
+
+        fn get_grace_period_end(loan_id: p2p_primitives::P2PLoanId) -> BlockNumber {
+            PToP::grace_period_end(&PToP::loans(loan_id))
+        }
+
+        fn get_overdue_penalty(loan_id: p2p_primitives::P2PLoanId) -> Balance {
+            PToP::calculate_overdue_penalty(&PToP::loans(loan_id), System::block_number())
+        }
+
+        fn get_margin_call_deadline(loan_id: p2p_primitives::P2PLoanId) -> Option<BlockNumber> {
+            PToP::margin_call_deadline(loan_id)
+        }
+
+        fn get_loan_interest(loan_id: p2p_primitives::P2PLoanId) -> Balance {
+            let loan = PToP::loans(loan_id);
+            PToP::calculate_loan_interest(&PToP::borrows(loan.borrow_id), &loan, System::block_number())
+        }
+
+        fn get_loan_interest_rate(loan_id: p2p_primitives::P2PLoanId) -> u64 {
+            PToP::loan_interest_rate(&PToP::loans(loan_id))
+        }
+
+        fn get_reference_rate() -> u64 {
+            DepositLoanReferenceRate::get()
+        }
+
+        fn get_loan_installments(loan_id: p2p_primitives::P2PLoanId) -> Vec<p2p_primitives::P2PInstallment<Balance, BlockNumber>> {
+            PToP::loan_installments(loan_id)
+        }
+    }
+
+    impl new_oracle_rpc_runtime_api::NewOracleApi<Block, AssetId, BlockNumber, Moment, u64> for Runtime {
+        fn get_prices() -> Vec<(Vec<u8>, new_oracle::TokenPriceInfo<Runtime>)> {
+            NewOracle::prices()
+        }
+        fn get_price(token: Vec<u8>) -> Option<new_oracle::TokenPriceInfo<Runtime>> {
+            NewOracle::price_info(&token)
+        }
+        fn get_asset_price(asset_id: AssetId) -> Option<(Vec<u8>, new_oracle::TokenPriceInfo<Runtime>)> {
+            NewOracle::asset_price(asset_id)
+        }
+        fn get_candidates(token: Vec<u8>) -> Vec<(Vec<u8>, u64)> {
+            NewOracle::price_candidates(token)
+                .into_iter()
+                .map(|(_, source, price)| (source, price))
+                .collect()
+        }
+        fn get_sources(token: Vec<u8>) -> Vec<new_oracle::PriceSource> {
+            NewOracle::crypto_price_sources(token)
+        }
+        fn get_next_aggregate_at() -> BlockNumber {
+            NewOracle::next_aggregate_at()
+        }
+        fn get_price_history(token: Vec<u8>) -> Vec<(BlockNumber, Moment, u64)> {
+            NewOracle::price_history(token)
+        }
+        fn get_source_health(token: Vec<u8>) -> Vec<(Vec<u8>, new_oracle::SourceHealth)> {
+            NewOracle::source_health(&token)
+        }
+        fn get_pair_price(base: Vec<u8>, quote: Vec<u8>) -> Option<(u64, BlockNumber)> {
+            NewOracle::pair_price(base, quote)
+        }
     }
 
     impl deposit_loan_rpc_runtime_api::DepositLoanApi<Block, AccountId, Balance> for Runtime {